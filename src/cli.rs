@@ -0,0 +1,679 @@
+//! Command-line options, which take priority over the scene file
+
+use std::{fmt::Display, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use clap::Parser;
+
+use crate::{
+    image_utils::{
+        ImageFormat,
+        denoise::Denoiser,
+        tone_map::{DisplayTransform, ToneMap},
+    },
+    math_utils::{point::Point, samplers::SamplerKind, vector::Vec3},
+    ray_tracing::{
+        aov::{self, Aov},
+        background::{EnvironmentMap, SolidBackground},
+        camera::{
+            adaptive::AdaptiveSampling,
+            camera::CameraBuilder,
+            crop::{Crop, CropWindow},
+            filter::{Filter, FilterKind},
+            tiles::TileOrder,
+        },
+        color::Color,
+        integrators::{IntegratorKind, IntegratorSettings},
+    },
+    scene::file::Scene,
+};
+
+/// Rendered when no scene file is given
+const DEFAULT_SCENE: &str = "scenes/three_spheres.toml";
+
+/// Path trace a scene file into an image
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Args {
+    /// TOML scene description to render
+    #[arg(default_value = DEFAULT_SCENE)]
+    pub scene: PathBuf,
+
+    /// Where to write the image, instead of the scene's output
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Also write an AOV image to a `.pfm` file, can be repeated [possible names:
+    /// normal, albedo, depth, position, object-id]
+    #[arg(long, value_name = "NAME=FILE", value_parser = aov_file)]
+    pub aov: Vec<(Aov, PathBuf)>,
+
+    /// Image format [possible values: png, ppm, ppm-ascii, hdr, pfm], picked from the
+    /// output's extension by default
+    #[arg(short, long)]
+    pub format: Option<ImageFormat>,
+
+    /// Image width in pixels
+    #[arg(long, value_parser = at_least_one())]
+    pub width: Option<isize>,
+
+    /// Image height in pixels, changes the aspect ratio to match the width
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Width over height, ignored when `--height` is given
+    #[arg(long, value_parser = positive)]
+    pub aspect_ratio: Option<f64>,
+
+    /// Rays traced through every pixel
+    #[arg(short, long, value_parser = at_least_one())]
+    pub samples: Option<isize>,
+
+    /// Where the random numbers of a pixel's samples come from [possible values:
+    /// independent, stratified, halton, sobol]
+    #[arg(long, value_name = "NAME")]
+    pub sampler: Option<SamplerKind>,
+
+    /// How samples are shared with the pixels around them [possible values: box,
+    /// tent, gaussian, mitchell, lanczos]
+    #[arg(long, value_name = "NAME")]
+    pub filter: Option<FilterKind>,
+
+    /// Reach of the filter in pixels, each filter's own default otherwise
+    #[arg(long, value_name = "PIXELS", value_parser = positive)]
+    pub filter_radius: Option<f64>,
+
+    /// Width and height of the square tiles the threads take in turn
+    #[arg(long, value_name = "PIXELS", value_parser = at_least_one())]
+    pub tile_size: Option<isize>,
+
+    /// The order tiles are rendered in [possible values: hilbert, spiral]
+    #[arg(long, value_name = "NAME")]
+    pub tile_order: Option<TileOrder>,
+
+    /// Only render this rectangle of pixels, from its top left corner
+    #[arg(long, value_name = "X,Y,WIDTH,HEIGHT", value_parser = crop_pixels)]
+    pub crop_pixels: Option<CropWindow>,
+
+    /// Only render this part of the image, its edges as fractions of the image from
+    /// the top left
+    #[arg(
+        long,
+        value_name = "X_MIN,X_MAX,Y_MIN,Y_MAX",
+        value_parser = crop_window,
+        conflicts_with = "crop_pixels"
+    )]
+    pub crop_window: Option<CropWindow>,
+
+    /// Save the whole image with everything outside the crop window black, rather
+    /// than just the window
+    #[arg(long)]
+    pub crop_full_size: bool,
+
+    /// Stop sampling a pixel once the standard error of its mean luminance falls below
+    /// this fraction of the mean, and spend its samples on noisier pixels
+    #[arg(long, value_parser = positive)]
+    pub adaptive_threshold: Option<f64>,
+
+    /// Samples every pixel gets before adaptive sampling judges its noise, turns
+    /// adaptive sampling on
+    #[arg(long, value_parser = at_least_one())]
+    pub min_samples: Option<isize>,
+
+    /// Most samples adaptive sampling gives one pixel, 4 times `--samples` by default,
+    /// turns adaptive sampling on
+    #[arg(long, value_parser = at_least_one())]
+    pub max_samples: Option<isize>,
+
+    /// Write an image of how many samples every pixel got
+    #[arg(long, value_name = "FILE")]
+    pub heatmap: Option<PathBuf>,
+
+    /// Render progressively, adding this many samples a pixel to the whole image in
+    /// every pass and saving it after each one
+    #[arg(long, value_parser = at_least_one())]
+    pub pass_samples: Option<isize>,
+
+    /// Least time between two snapshots of a progressive render
+    #[arg(long, value_name = "SECONDS", value_parser = seconds)]
+    pub snapshot_interval: Option<Duration>,
+
+    /// How the light along each ray is worked out [possible values: path, normals,
+    /// depth, albedo, ambient-occlusion]
+    #[arg(long, value_name = "NAME")]
+    pub integrator: Option<IntegratorKind>,
+
+    /// Most bounces a ray makes before giving up on it
+    #[arg(short = 'd', long, value_parser = at_least_one())]
+    pub max_depth: Option<isize>,
+
+    /// Bounces every path makes before Russian roulette may end it early
+    #[arg(long, value_parser = at_least_one())]
+    pub roulette_depth: Option<isize>,
+
+    /// Shadow rays per sample for the ambient occlusion integrator
+    #[arg(long, value_parser = at_least_one())]
+    pub occlusion_samples: Option<isize>,
+
+    /// How far away a surface can be and still occlude
+    #[arg(long, value_parser = positive)]
+    pub occlusion_distance: Option<f64>,
+
+    /// Worker threads, all cores by default
+    #[arg(short = 'j', long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub threads: Option<usize>,
+
+    /// Make the render repeatable by fixing its random numbers
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Describe the scene and the render settings before rendering
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Camera position
+    #[arg(long, value_name = "X,Y,Z", value_parser = triple)]
+    pub look_from: Option<[f64; 3]>,
+
+    /// Point the camera looks at
+    #[arg(long, value_name = "X,Y,Z", value_parser = triple)]
+    pub look_at: Option<[f64; 3]>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = field_of_view)]
+    pub vertical_fov: Option<f64>,
+
+    /// Cone angle of rays through each pixel in degrees, 0 for a pinhole camera
+    #[arg(long, value_parser = angle)]
+    pub defocus_angle: Option<f64>,
+
+    /// Distance from the camera to the plane that is perfectly in focus
+    #[arg(long, value_parser = positive)]
+    pub focus_dist: Option<f64>,
+
+    /// Solid background color instead of the scene's
+    #[arg(long, value_name = "R,G,B", value_parser = triple, conflicts_with = "environment")]
+    pub background: Option<[f64; 3]>,
+
+    /// Radiance `.hdr` or `.pfm` environment map to use as the background
+    #[arg(long, value_name = "FILE")]
+    pub environment: Option<PathBuf>,
+
+    /// Brighten or darken 8 bit images, every stop doubles the light
+    #[arg(long, value_name = "STOPS", allow_negative_numbers = true, value_parser = finite)]
+    pub exposure: Option<f64>,
+
+    /// How 8 bit images fit bright light into range [possible values: clamp, reinhard,
+    /// extended-reinhard, aces]
+    #[arg(long, value_name = "NAME")]
+    pub tone_map: Option<ToneMap>,
+
+    /// Luminance that becomes pure white with the extended Reinhard tone map
+    #[arg(long, value_parser = positive)]
+    pub white_point: Option<f64>,
+
+    /// Filter the noise out of the image, guided by normal and albedo AOVs
+    #[arg(long)]
+    pub denoise: bool,
+
+    /// Denoise this `.hdr` or `.pfm` image into `--output` instead of rendering
+    #[arg(long, value_name = "FILE", requires = "output")]
+    pub denoise_image: Option<PathBuf>,
+
+    /// Normal AOV of the image given to `--denoise-image`
+    #[arg(long, value_name = "FILE", requires = "denoise_image")]
+    pub normal_guide: Option<PathBuf>,
+
+    /// Albedo AOV of the image given to `--denoise-image`
+    #[arg(long, value_name = "FILE", requires = "denoise_image")]
+    pub albedo_guide: Option<PathBuf>,
+}
+
+impl Args {
+    /// Override the scene's settings with the ones given on the command line
+    ///
+    /// # Errors
+    /// If the environment map can't be loaded or the camera would end up with no
+    /// direction to look in
+    pub fn apply(&self, scene: &mut Scene) -> Result<(), String> {
+        if let Some(output) = &self.output {
+            scene.output.clone_from(output);
+        }
+        if let Some(heatmap) = &self.heatmap {
+            scene.heatmap = Some(heatmap.clone());
+        }
+        if let Some(snapshot_interval) = self.snapshot_interval {
+            scene.snapshot_interval = snapshot_interval;
+        }
+        for (aov, path) in &self.aov {
+            scene.aovs.insert(*aov, path.clone());
+        }
+
+        self.apply_film(&mut scene.camera)?;
+        self.apply_view(&mut scene.camera)?;
+        self.apply_integrator(&mut scene.integrator);
+        self.apply_display(&mut scene.display);
+        if self.denoise && scene.denoiser.is_none() {
+            scene.denoiser = Some(Denoiser::default());
+        }
+
+        Ok(())
+    }
+
+    /// Override the image's size and how its pixels are sampled, filtered and split
+    /// into tiles
+    ///
+    /// # Errors
+    /// If the image is cropped to its full size without a crop window
+    #[allow(clippy::cast_precision_loss)] // image sizes are far below 2^52
+    pub fn apply_film(&self, camera: &mut CameraBuilder) -> Result<(), String> {
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(width) = self.width {
+            camera.image_width = width;
+        }
+        if let Some(height) = self.height {
+            camera.aspect_ratio = camera.image_width as f64 / f64::from(height);
+        }
+        if let Some(samples) = self.samples {
+            camera.samples_per_pixel = samples;
+        }
+        if let Some(sampler) = self.sampler {
+            camera.sampler = sampler;
+        }
+        if let Some(kind) = self.filter {
+            camera.filter = Filter::new(kind);
+        }
+        if let Some(radius) = self.filter_radius {
+            camera.filter.radius = radius;
+        }
+        if let Some(size) = self.tile_size {
+            camera.tiling.size = size;
+        }
+        if let Some(order) = self.tile_order {
+            camera.tiling.order = order;
+        }
+        if let Some(window) = self.crop_pixels.or(self.crop_window) {
+            camera.crop = Some(Crop {
+                window,
+                full_size: false,
+            });
+        }
+        if self.crop_full_size {
+            camera
+                .crop
+                .as_mut()
+                .ok_or("--crop-full-size needs a crop window")?
+                .full_size = true;
+        }
+        if self.adaptive_threshold.is_some()
+            || self.min_samples.is_some()
+            || self.max_samples.is_some()
+        {
+            let adaptive = camera
+                .adaptive
+                .get_or_insert_with(AdaptiveSampling::default);
+            if let Some(threshold) = self.adaptive_threshold {
+                adaptive.threshold = threshold;
+            }
+            if let Some(min_samples) = self.min_samples {
+                adaptive.min_samples = min_samples;
+            }
+            if let Some(max_samples) = self.max_samples {
+                adaptive.max_samples = Some(max_samples);
+            }
+        }
+        if let Some(seed) = self.seed {
+            camera.seed = Some(seed);
+        }
+        if let Some(pass_samples) = self.pass_samples {
+            camera.pass_samples = Some(pass_samples);
+        }
+
+        Ok(())
+    }
+
+    /// Override where the camera looks from and to, its lens and what it sees where
+    /// nothing is hit
+    ///
+    /// # Errors
+    /// If the environment map can't be loaded, the background is negative or the
+    /// camera would end up with no direction to look in
+    pub fn apply_view(&self, camera: &mut CameraBuilder) -> Result<(), String> {
+        if let Some([x, y, z]) = self.look_from {
+            camera.lookfrom = Point::new(x, y, z);
+        }
+        if let Some([x, y, z]) = self.look_at {
+            camera.lookat = Point::new(x, y, z);
+        }
+        if (camera.lookat - camera.lookfrom)
+            .cross(&Vec3::new(0.0, 1.0, 0.0))
+            .near_zero()
+        {
+            return Err(
+                "look-at must differ from look-from and not be straight above or below it"
+                    .to_string(),
+            );
+        }
+        if let Some(vertical_fov) = self.vertical_fov {
+            camera.vertical_fov = vertical_fov;
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+
+        if let Some([red, green, blue]) = self.background {
+            if red < 0.0 || green < 0.0 || blue < 0.0 {
+                return Err("background color channels can't be negative".to_string());
+            }
+            camera.background = Arc::new(SolidBackground::new(Color { red, green, blue }));
+        }
+        if let Some(environment) = &self.environment {
+            let map = EnvironmentMap::from_file(environment)
+                .map_err(|error| format!("can't load {}: {error}", environment.display()))?;
+            camera.background = Arc::new(map);
+        }
+
+        Ok(())
+    }
+
+    /// Override how the integrator traces paths
+    pub const fn apply_integrator(&self, integrator: &mut IntegratorSettings) {
+        if let Some(kind) = self.integrator {
+            integrator.kind = kind;
+        }
+        if let Some(max_depth) = self.max_depth {
+            integrator.max_depth = max_depth;
+        }
+        if let Some(roulette_depth) = self.roulette_depth {
+            integrator.roulette_depth = roulette_depth;
+        }
+        if let Some(occlusion_samples) = self.occlusion_samples {
+            integrator.occlusion_samples = occlusion_samples;
+        }
+        if let Some(occlusion_distance) = self.occlusion_distance {
+            integrator.occlusion_distance = occlusion_distance;
+        }
+    }
+
+    /// Override how 8 bit images are exposed and tone mapped
+    pub const fn apply_display(&self, display: &mut DisplayTransform) {
+        if let Some(exposure) = self.exposure {
+            display.exposure = exposure;
+        }
+        if let Some(tone_map) = self.tone_map {
+            display.tone_map = tone_map;
+        }
+        if let Some(white_point) = self.white_point {
+            display.white_point = white_point;
+        }
+    }
+}
+
+/// Counts stored as `isize` by the camera
+fn at_least_one() -> clap::builder::RangedI64ValueParser<isize> {
+    clap::builder::RangedI64ValueParser::new().range(1..)
+}
+
+fn triple(value: &str) -> Result<[f64; 3], String> {
+    let numbers = value
+        .split(',')
+        .map(|number| finite(number.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    numbers
+        .try_into()
+        .map_err(|_| "expected three comma separated numbers".to_string())
+}
+
+/// Four comma separated numbers
+fn quadruple<T>(value: &str) -> Result<[T; 4], String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<T>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| error.to_string())?;
+    numbers
+        .try_into()
+        .map_err(|_| "expected four comma separated numbers".to_string())
+}
+
+fn crop_pixels(value: &str) -> Result<CropWindow, String> {
+    CropWindow::pixels(quadruple(value)?)
+}
+
+fn crop_window(value: &str) -> Result<CropWindow, String> {
+    CropWindow::normalized(quadruple(value)?)
+}
+
+fn aov_file(value: &str) -> Result<(Aov, PathBuf), String> {
+    let (name, path) = value
+        .split_once('=')
+        .ok_or_else(|| "expected an AOV name and a file, eg. normal=normal.pfm".to_string())?;
+    let path = PathBuf::from(path);
+    aov::check_file(&path)?;
+    Ok((name.parse()?, path))
+}
+
+fn finite(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        Ok(_) => Err("must be finite".to_string()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn positive(value: &str) -> Result<f64, String> {
+    match finite(value)? {
+        number if number > 0.0 => Ok(number),
+        _ => Err("must be positive".to_string()),
+    }
+}
+
+fn seconds(value: &str) -> Result<Duration, String> {
+    let number = value.parse::<f64>().map_err(|error| error.to_string())?;
+    Duration::try_from_secs_f64(number).map_err(|_| "must be a number of seconds".to_string())
+}
+
+/// Angles are in degrees and must stay below a half turn
+fn angle(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if (0.0..180.0).contains(&number) => Ok(number),
+        Ok(_) => Err("must be between 0 and 180 degrees".to_string()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn field_of_view(value: &str) -> Result<f64, String> {
+    let number = angle(value)?;
+    if number > 0.0 {
+        Ok(number)
+    } else {
+        Err("must be more than 0 degrees".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::file::parse_scene;
+
+    fn scene() -> Scene {
+        parse_scene("[render]\nimage_width = 200\n", std::path::Path::new("")).unwrap()
+    }
+
+    fn apply(arguments: &[&str]) -> Result<Scene, String> {
+        let args = Args::try_parse_from(std::iter::once(&"environment").chain(arguments))
+            .map_err(|error| error.to_string())?;
+        let mut scene = scene();
+        args.apply(&mut scene)?;
+        Ok(scene)
+    }
+
+    #[test]
+    fn test_no_arguments_keep_the_scene() {
+        let args = Args::try_parse_from(["environment"]).unwrap();
+        assert_eq!(args.scene, PathBuf::from(DEFAULT_SCENE));
+        assert!(!args.verbose);
+        let scene = apply(&[]).unwrap();
+        assert_eq!(scene.camera.image_width, 200);
+        assert_eq!(scene.camera.seed, None);
+        assert_eq!(scene.camera.adaptive, None);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let scene = apply(&[
+            "-o",
+            "out.ppm",
+            "--format",
+            "ppm-ascii",
+            "--width",
+            "300",
+            "--height",
+            "100",
+            "-s",
+            "8",
+            "--sampler",
+            "stratified",
+            "--filter",
+            "lanczos",
+            "--tile-size",
+            "16",
+            "--tile-order",
+            "spiral",
+            "--crop-window",
+            "0.25,0.75,0,0.5",
+            "--crop-full-size",
+            "--max-samples",
+            "20",
+            "--heatmap",
+            "samples.png",
+            "--pass-samples",
+            "2",
+            "--snapshot-interval",
+            "10",
+            "-d",
+            "4",
+            "--roulette-depth",
+            "2",
+            "--integrator",
+            "normals",
+            "--seed",
+            "7",
+            "--verbose",
+            "--look-from",
+            "1,2,3",
+            "--focus-dist",
+            "2.5",
+            "--background",
+            "0.1,0.2,0.3",
+            "--exposure",
+            "-1.5",
+            "--tone-map",
+            "aces",
+            "--aov",
+            "depth=depth.pfm",
+            "--aov",
+            "albedo=albedo.pfm",
+            "--denoise",
+        ])
+        .unwrap();
+
+        assert_eq!(scene.output, PathBuf::from("out.ppm"));
+        assert_eq!(scene.camera.image_width, 300);
+        assert!((scene.camera.aspect_ratio - 3.0).abs() < 1e-12);
+        assert_eq!(scene.camera.samples_per_pixel, 8);
+        assert_eq!(scene.camera.sampler, SamplerKind::Stratified);
+        assert_eq!(scene.camera.filter, Filter::new(FilterKind::Lanczos));
+        assert_eq!(scene.camera.tiling.size, 16);
+        assert_eq!(scene.camera.tiling.order, TileOrder::Spiral);
+        assert_eq!(
+            scene.camera.crop,
+            Some(Crop {
+                window: CropWindow::Normalized {
+                    x_min: 0.25,
+                    x_max: 0.75,
+                    y_min: 0.0,
+                    y_max: 0.5,
+                },
+                full_size: true,
+            })
+        );
+        assert_eq!(
+            scene.camera.adaptive,
+            Some(AdaptiveSampling {
+                max_samples: Some(20),
+                ..AdaptiveSampling::default()
+            })
+        );
+        assert_eq!(scene.heatmap, Some(PathBuf::from("samples.png")));
+        assert_eq!(scene.camera.pass_samples, Some(2));
+        assert_eq!(scene.snapshot_interval, Duration::from_secs(10));
+        assert_eq!(scene.integrator.kind, IntegratorKind::Normals);
+        assert_eq!(scene.integrator.max_depth, 4);
+        assert_eq!(scene.integrator.roulette_depth, 2);
+        assert_eq!(scene.camera.seed, Some(7));
+        assert_eq!(scene.camera.lookfrom, Point::new(1.0, 2.0, 3.0));
+        assert!((scene.camera.focus_dist - 2.5).abs() < 1e-12);
+        assert!((scene.display.exposure + 1.5).abs() < 1e-12);
+        assert_eq!(scene.display.tone_map, ToneMap::Aces);
+        assert_eq!(scene.aovs.len(), 2);
+        assert_eq!(scene.aovs[&Aov::Depth], PathBuf::from("depth.pfm"));
+        assert!(scene.denoiser.is_some());
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        for arguments in [
+            &["--samples", "0"][..],
+            &["--adaptive-threshold", "0"],
+            &["--min-samples", "0"],
+            &["--pass-samples", "0"],
+            &["--sampler", "random"],
+            &["--filter", "sinc"],
+            &["--filter-radius", "0"],
+            &["--filter-radius", "inf"],
+            &["--tile-size", "0"],
+            &["--tile-order", "rows"],
+            &["--crop-pixels", "0,0,10"],
+            &["--crop-pixels", "0,0,10,0"],
+            &["--crop-window", "0,1.5,0,1"],
+            &["--crop-pixels", "0,0,4,4", "--crop-window", "0,1,0,1"],
+            &["--crop-full-size"],
+            &["--snapshot-interval", "-5"],
+            &["--look-at", "1,2"],
+            &["--look-at", "0,inf,0"],
+            &["--look-from", "nan,0,0"],
+            &["--aspect-ratio", "inf"],
+            &["--focus-dist", "-1"],
+            &["--focus-dist", "inf"],
+            &["--defocus-angle", "180"],
+            &["--vertical-fov", "0"],
+            &["--background", "-1,0,0"],
+            &["--background", "nan,0,0"],
+            &["--background", "1,1,1", "--environment", "sky.hdr"],
+            &["--look-from", "0,5,0", "--look-at", "0,0,0"],
+            &["--frobnicate"],
+            &["--format", "gif"],
+            &["--tone-map", "filmic"],
+            &["--integrator", "whitted"],
+            &["--occlusion-distance", "0"],
+            &["--aov", "normal"],
+            &["--aov", "normal=normal.png"],
+            &["--aov", "motion=motion.pfm"],
+            &["--denoise-image", "noisy.pfm"],
+            &["--normal-guide", "normal.pfm"],
+            &["--exposure", "inf"],
+            &["--white-point", "0"],
+        ] {
+            assert!(apply(arguments).is_err(), "{arguments:?}");
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Edge-avoiding à-trous wavelet denoising
+//!
+//! Each pass blurs the image with a 5 x 5 B3 spline kernel whose taps are spread
+//! twice as far apart as in the pass before, so a few passes cover a wide area at the
+//! cost of 25 taps per pixel each. Every tap is weighted by how much its pixel looks
+//! like the one being filtered, in color and in the normal and albedo of the surface
+//! seen, so the blur stops at edges and texture instead of smearing across them
+//! (Dammertz et al., "Edge-Avoiding À-Trous Wavelet Transform for fast Global
+//! Illumination Filtering", 2010).
+//!
+//! With an albedo guide the image is divided by the albedo before filtering and
+//! multiplied back afterwards, so only the lighting is blurred and surface colors
+//! stay sharp. Colors are compared after squeezing them into [0, 1) with
+//! `x / (1 + x)`, so the same relative noise counts about the same in shadows and
+//! in bright areas. Without an albedo guide nothing tells a light apart from the
+//! surface around it, so colors are compared on a log scale instead, which keeps
+//! very bright pixels from bleeding into their neighbours.
+
+use std::fmt;
+
+use rayon::prelude::*;
+
+use super::framebuffer::Framebuffer;
+use crate::ray_tracing::color::Color;
+
+/// B3 spline weights, applied along both axes
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+/// Albedo channels darker than this are left alone instead of divided out, so black
+/// surfaces don't blow up the lighting
+const MIN_ALBEDO: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenoiseError {
+    /// A guide doesn't have one pixel for every pixel of the image
+    GuideSizeMismatch {
+        guide: &'static str,
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+}
+
+impl fmt::Display for DenoiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GuideSizeMismatch {
+                guide,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the {guide} guide is {}x{} but the image is {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DenoiseError {}
+
+/// Feature images of the same view that tell edges apart from noise, see `Aov`
+///
+/// [`Aov`]: crate::ray_tracing::aov::Aov
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Guides<'a> {
+    pub normal: Option<&'a Framebuffer>,
+    pub albedo: Option<&'a Framebuffer>,
+}
+
+/// How strongly the filter smooths, larger sigmas blur across bigger differences
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    /// Filter passes, the last one reaches `2^iterations` pixels away
+    pub iterations: u32,
+    /// Color difference that still counts as noise, halved every pass
+    pub color_sigma: f64,
+    /// Normal difference that still counts as the same surface
+    pub normal_sigma: f64,
+    /// Albedo difference that still counts as the same material
+    pub albedo_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 0.75,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.2,
+        }
+    }
+}
+
+impl Denoiser {
+    /// A filtered copy of `image`
+    ///
+    /// # Errors
+    /// If a guide isn't the same size as the image
+    pub fn denoise(
+        &self,
+        image: &Framebuffer,
+        guides: Guides<'_>,
+    ) -> Result<Framebuffer, DenoiseError> {
+        for (guide, buffer) in [("normal", guides.normal), ("albedo", guides.albedo)] {
+            if let Some(buffer) = buffer
+                && (buffer.width(), buffer.height()) != (image.width(), image.height())
+            {
+                return Err(DenoiseError::GuideSizeMismatch {
+                    guide,
+                    expected: (image.width(), image.height()),
+                    found: (buffer.width(), buffer.height()),
+                });
+            }
+        }
+
+        let mut lighting = guides.albedo.map_or_else(
+            || image.clone(),
+            |albedo| {
+                map_pixels(image, albedo, |color, albedo| Color {
+                    red: color.red / demodulation(albedo.red),
+                    green: color.green / demodulation(albedo.green),
+                    blue: color.blue / demodulation(albedo.blue),
+                })
+            },
+        );
+        let compress = if guides.albedo.is_some() {
+            |channel: f64| channel / (1.0 + channel)
+        } else {
+            f64::ln_1p
+        };
+        let mut color_sigma = self.color_sigma;
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            lighting = self.filter_pass(&lighting, guides, step, color_sigma, compress);
+            color_sigma /= 2.0;
+        }
+
+        Ok(match guides.albedo {
+            Some(albedo) => map_pixels(&lighting, albedo, |color, albedo| Color {
+                red: color.red * demodulation(albedo.red),
+                green: color.green * demodulation(albedo.green),
+                blue: color.blue * demodulation(albedo.blue),
+            }),
+            None => lighting,
+        })
+    }
+
+    /// One blur with taps `step` pixels apart
+    #[allow(clippy::cast_possible_wrap)] // images are far smaller than isize::MAX
+    fn filter_pass(
+        &self,
+        image: &Framebuffer,
+        guides: Guides<'_>,
+        step: isize,
+        color_sigma: f64,
+        compress: fn(f64) -> f64,
+    ) -> Framebuffer {
+        let width = image.width();
+        let height = image.height();
+        let compressed: Vec<Color> = image
+            .pixels()
+            .iter()
+            .map(|color| Color {
+                red: compress(color.red.max(0.0)),
+                green: compress(color.green.max(0.0)),
+                blue: compress(color.blue.max(0.0)),
+            })
+            .collect();
+        let compressed = compressed.as_slice();
+        let pixels = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                (0..width).map(move |x| {
+                    let center = compressed[y * width + x];
+                    let mut total = Color::black();
+                    let mut total_weight = 0.0;
+                    for (dy, kernel_y) in (-2..=2).zip(KERNEL) {
+                        for (dx, kernel_x) in (-2..=2).zip(KERNEL) {
+                            let (Some(tap_x), Some(tap_y)) = (
+                                x.checked_add_signed(dx * step).filter(|&tap| tap < width),
+                                y.checked_add_signed(dy * step).filter(|&tap| tap < height),
+                            ) else {
+                                continue;
+                            };
+                            let tap = image.get(tap_x, tap_y);
+                            let mut distance =
+                                squared_distance(center, compressed[tap_y * width + tap_x])
+                                    / (color_sigma * color_sigma);
+                            if let Some(normal) = guides.normal {
+                                distance +=
+                                    squared_distance(normal.get(x, y), normal.get(tap_x, tap_y))
+                                        / (self.normal_sigma * self.normal_sigma);
+                            }
+                            if let Some(albedo) = guides.albedo {
+                                distance +=
+                                    squared_distance(albedo.get(x, y), albedo.get(tap_x, tap_y))
+                                        / (self.albedo_sigma * self.albedo_sigma);
+                            }
+                            let weight = kernel_x * kernel_y * (-distance).exp();
+                            total += tap * weight;
+                            total_weight += weight;
+                        }
+                    }
+                    // the center tap always counts fully, so the weight is never 0
+                    total * (1.0 / total_weight)
+                })
+            })
+            .collect();
+        Framebuffer::from_pixels(width, height, pixels)
+    }
+}
+
+const fn demodulation(albedo: f64) -> f64 {
+    if albedo > MIN_ALBEDO { albedo } else { 1.0 }
+}
+
+fn map_pixels(
+    image: &Framebuffer,
+    other: &Framebuffer,
+    map: impl Fn(Color, Color) -> Color,
+) -> Framebuffer {
+    Framebuffer::from_pixels(
+        image.width(),
+        image.height(),
+        image
+            .pixels()
+            .iter()
+            .zip(other.pixels())
+            .map(|(&color, &other)| map(color, other))
+            .collect(),
+    )
+}
+
+fn squared_distance(first: Color, second: Color) -> f64 {
+    let difference = first - second;
+    difference.blue.mul_add(
+        difference.blue,
+        difference
+            .red
+            .mul_add(difference.red, difference.green * difference.green),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn gray(value: f64) -> Color {
+        Color {
+            red: value,
+            green: value,
+            blue: value,
+        }
+    }
+
+    /// Left half dark and right half bright, with every other pixel nudged up and down
+    fn noisy_edge(width: usize, height: usize) -> Framebuffer {
+        let mut image = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let base = if x < width / 2 { 0.2 } else { 0.8 };
+                let noise = if (x + y) % 2 == 0 { 0.05 } else { -0.05 };
+                image.set(x, y, gray(base + noise));
+            }
+        }
+        image
+    }
+
+    fn halves(width: usize, height: usize, left: Color, right: Color) -> Framebuffer {
+        let mut image = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set(x, y, if x < width / 2 { left } else { right });
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_flat_images_stay_flat() {
+        let image = Framebuffer::from_pixels(7, 5, vec![gray(0.3); 35]);
+        let denoised = Denoiser::default()
+            .denoise(&image, Guides::default())
+            .unwrap();
+        for pixel in denoised.pixels() {
+            assert!((pixel.red - 0.3).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_noise_is_removed_but_edges_kept() {
+        let image = noisy_edge(16, 8);
+        let normal = halves(
+            16,
+            8,
+            Color {
+                red: 1.0,
+                ..Color::black()
+            },
+            Color {
+                green: 1.0,
+                ..Color::black()
+            },
+        );
+        let denoised = Denoiser::default()
+            .denoise(
+                &image,
+                Guides {
+                    normal: Some(&normal),
+                    albedo: None,
+                },
+            )
+            .unwrap();
+        for y in 0..8 {
+            for x in 0..16 {
+                let expected = if x < 8 { 0.2 } else { 0.8 };
+                let pixel = denoised.get(x, y).red;
+                assert!((pixel - expected).abs() < 0.01, "{x}, {y}: {pixel}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_albedo_stays_sharp() {
+        // the same light falls on a red and a blue surface
+        let red = Color::new(0.9, 0.1, 0.1).unwrap();
+        let blue = Color::new(0.1, 0.1, 0.9).unwrap();
+        let albedo = halves(8, 4, red, blue);
+        let image = map_pixels(&noisy_edge(8, 4), &albedo, |_, albedo| albedo * 0.5);
+        let denoised = Denoiser::default()
+            .denoise(
+                &image,
+                Guides {
+                    normal: None,
+                    albedo: Some(&albedo),
+                },
+            )
+            .unwrap();
+        assert!((denoised.get(3, 1).red - 0.45).abs() < 1e-9);
+        assert!((denoised.get(4, 1).blue - 0.45).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_guides_must_match_the_image() {
+        let image = Framebuffer::new(4, 4);
+        let normal = Framebuffer::new(4, 3);
+        let error = Denoiser::default()
+            .denoise(
+                &image,
+                Guides {
+                    normal: Some(&normal),
+                    albedo: None,
+                },
+            )
+            .unwrap_err();
+        assert_eq!(
+            error,
+            DenoiseError::GuideSizeMismatch {
+                guide: "normal",
+                expected: (4, 4),
+                found: (4, 3),
+            }
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! A rendered image held in memory
+
+use crate::ray_tracing::color::Color;
+
+/// Pixels in row-major order, starting at the top left
+///
+/// Colors are linear radiance and aren't clamped, so bright lights keep their full
+/// value until a `DisplayTransform` prepares them for an 8 bit image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    /// An all black image
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::from_pixels(width, height, vec![Color::black(); width * height])
+    }
+
+    /// # Panics
+    /// If there isn't exactly one pixel per `width` x `height`
+    #[must_use]
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "pixel count must match the size"
+        );
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    #[must_use]
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    #[must_use]
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Interleaved 8 bit red, green and blue, the layout most image formats store
+    ///
+    /// Channels are clamped to [0, 1] and stored as they are, so the image should
+    /// already be display ready.
+    #[must_use]
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| <[u8; 3]>::from(color.to_bytes_rgb()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_access() {
+        let mut image = Framebuffer::new(3, 2);
+        let red = Color::new(1.0, 0.0, 0.0).unwrap();
+        image.set(2, 1, red);
+        assert_eq!(image.get(2, 1), red);
+        assert_eq!(image.pixels()[5], red);
+        assert_eq!(image.get(0, 0), Color::black());
+        assert_eq!(image.to_rgb8()[15..], [255, 0, 0]);
+    }
+}
@@ -0,0 +1,402 @@
+//! Radiance `.hdr` (RGBE) images
+//!
+//! Every pixel is stored as an 8 bit mantissa per channel plus a shared 8 bit exponent,
+//! with each scanline optionally run-length encoded one channel at a time.
+
+use std::io::{BufRead, Read, Write};
+
+use super::{ImageError, framebuffer::Framebuffer, value_count};
+use crate::ray_tracing::color::Color;
+
+/// Shortest stretch of equal bytes worth storing as a run rather than literally
+const MIN_RUN_LENGTH: usize = 4;
+
+/// # Errors
+/// If the data can't be read or isn't a valid RGBE image
+pub fn read_hdr<R: BufRead>(mut reader: R) -> Result<Framebuffer, ImageError> {
+    let (width, height) = read_header(&mut reader)?;
+    let pixel_count = value_count(width, height, 1)?;
+
+    // the header can claim any size, so make sure the scanlines could fit in what's
+    // left of the file before making room for them
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if height
+        .checked_mul(shortest_scanline(width))
+        .is_none_or(|needed| needed > data.len())
+    {
+        return Err(ImageError::truncated(width, height));
+    }
+
+    let mut rest = data.as_slice();
+    let mut pixels = Vec::with_capacity(pixel_count);
+    let mut scanline = vec![[0_u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(&mut rest, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_color(rgbe)));
+    }
+
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+/// Write the image run-length encoded where the format allows it, which is for
+/// widths from 8 to 32767
+///
+/// # Errors
+/// If the image can't be written
+pub fn write_hdr<W: Write>(mut writer: W, image: &Framebuffer) -> Result<(), ImageError> {
+    let width = image.width();
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {width}\n",
+        image.height()
+    )?;
+
+    let run_length_encode = (8..0x8000).contains(&width);
+    let mut encoded = Vec::new();
+    for row in image.pixels().chunks_exact(width.max(1)) {
+        let scanline: Vec<[u8; 4]> = row.iter().map(|&color| color_to_rgbe(color)).collect();
+        if !run_length_encode {
+            writer.write_all(scanline.as_flattened())?;
+            continue;
+        }
+
+        encoded.clear();
+        encoded.extend([2, 2]);
+        encoded.extend(u16::try_from(width).unwrap_or_default().to_be_bytes());
+        for channel in 0..4 {
+            let values: Vec<u8> = scanline.iter().map(|rgbe| rgbe[channel]).collect();
+            encode_channel(&values, &mut encoded);
+        }
+        writer.write_all(&encoded)?;
+    }
+
+    Ok(writer.flush()?)
+}
+
+/// Store one channel of a scanline as runs of equal bytes and spans of literal bytes
+#[allow(clippy::cast_possible_truncation)] // counts are capped below 128
+fn encode_channel(values: &[u8], encoded: &mut Vec<u8>) {
+    const MAX_RUN: usize = 127;
+    const MAX_LITERALS: usize = 128;
+
+    let mut position = 0;
+    while position < values.len() {
+        // find where the next run worth encoding starts, if there is one
+        let mut run_start = position;
+        let mut run_length = 0;
+        while run_start < values.len() {
+            run_length = values[run_start..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|&&value| value == values[run_start])
+                .count();
+            if run_length >= MIN_RUN_LENGTH {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        for literals in values[position..run_start].chunks(MAX_LITERALS) {
+            encoded.push(literals.len() as u8);
+            encoded.extend_from_slice(literals);
+        }
+        if run_length >= MIN_RUN_LENGTH {
+            encoded.extend([128 + run_length as u8, values[run_start]]);
+        }
+        position = run_start + run_length;
+    }
+}
+
+/// Fewest bytes a scanline `width` pixels wide can be stored in
+fn shortest_scanline(width: usize) -> usize {
+    if (8..0x8000).contains(&width) {
+        // the marker, then a run of up to 127 pixels in 2 bytes for every channel
+        4 + 4 * 2 * width.div_ceil(127)
+    } else {
+        4 * width
+    }
+}
+
+/// Parse the text header and resolution line, returning `(width, height)`
+fn read_header<R: BufRead>(reader: &mut R) -> Result<(usize, usize), ImageError> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(ImageError::InvalidFormat(
+            "missing #? signature at the start of the file".to_string(),
+        ));
+    }
+
+    // header variables run until the first blank line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(ImageError::InvalidFormat(
+                "file ended inside the header".to_string(),
+            ));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=")
+            && format != "32-bit_rle_rgbe"
+        {
+            return Err(ImageError::InvalidFormat(format!(
+                "unsupported pixel format {format}"
+            )));
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields.as_slice() {
+        ["-Y", height, "+X", width] => match (width.parse(), height.parse()) {
+            (Ok(width), Ok(height)) => Ok((width, height)),
+            _ => Err(ImageError::InvalidFormat(format!(
+                "bad resolution line {}",
+                line.trim()
+            ))),
+        },
+        _ => Err(ImageError::InvalidFormat(format!(
+            "unsupported image orientation {}",
+            line.trim()
+        ))),
+    }
+}
+
+fn read_scanline<R: Read>(reader: &mut R, scanline: &mut [[u8; 4]]) -> Result<(), ImageError> {
+    let width = scanline.len();
+    let mut start = [0_u8; 4];
+    reader.read_exact(&mut start)?;
+
+    // run-length encoded lines start with 2, 2 and then the width, anything else is flat
+    let is_run_length_encoded = (8..0x8000).contains(&width)
+        && start[0] == 2
+        && start[1] == 2
+        && usize::from(start[2]) << 8 | usize::from(start[3]) == width;
+
+    if !is_run_length_encoded {
+        scanline[0] = start;
+        for pixel in &mut scanline[1..] {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    // each channel is stored separately as a mix of runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0_u8; 1];
+            reader.read_exact(&mut count)?;
+            let count = usize::from(count[0]);
+            if count > 128 {
+                let count = count - 128;
+                let mut value = [0_u8; 1];
+                reader.read_exact(&mut value)?;
+                if x + count > width {
+                    return Err(ImageError::InvalidFormat(
+                        "run overflows the scanline".to_string(),
+                    ));
+                }
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value[0];
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(ImageError::InvalidFormat(
+                        "bad literal span in scanline".to_string(),
+                    ));
+                }
+                let mut values = vec![0_u8; count];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode with the brightest channel setting the shared exponent, channels too dark
+/// to show at that exponent become 0
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // both are clamped to a byte
+fn color_to_rgbe(color: Color) -> [u8; 4] {
+    let brightest = color.red.max(color.green).max(color.blue);
+    if brightest.is_nan() || brightest < 1e-32 {
+        return [0; 4];
+    }
+
+    // brightest = fraction * 2^exponent with fraction in [0.5, 1)
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    if brightest / 2_f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / 2_f64.powi(exponent);
+    let mantissa = |channel: f64| (channel * scale).clamp(0.0, 255.0) as u8;
+
+    [
+        mantissa(color.red),
+        mantissa(color.green),
+        mantissa(color.blue),
+        (exponent + 128) as u8,
+    ]
+}
+
+/// Decode a shared-exponent pixel the way Radiance does, from the middle of each mantissa step
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::black();
+    }
+    let scale = 2_f64.powi(i32::from(rgbe[3]) - (128 + 8));
+    Color {
+        red: (f64::from(rgbe[0]) + 0.5) * scale,
+        green: (f64::from(rgbe[1]) + 0.5) * scale,
+        blue: (f64::from(rgbe[2]) + 0.5) * scale,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(width: usize, height: usize) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {height} +X {width}\n").into_bytes()
+    }
+
+    #[test]
+    fn test_read_flat() {
+        let mut data = header(2, 1);
+        // 0.5 in every channel, then black
+        data.extend([128, 128, 128, 128, 0, 0, 0, 0]);
+        let image = read_hdr(data.as_slice()).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert!((image.get(0, 0).red - 0.5).abs() < 0.01);
+        assert_eq!(image.get(1, 0), Color::black());
+    }
+
+    #[test]
+    fn test_read_run_length_encoded() {
+        let width = 10;
+        let mut data = header(width, 1);
+        data.extend([2, 2, 0, 10]);
+        // red: a run of 10, green: two literals then a run of 8, blue and exponent: runs
+        data.extend([128 + 10, 64]);
+        data.extend([2, 1, 2, 128 + 8, 3]);
+        data.extend([128 + 10, 0]);
+        data.extend([128 + 10, 129]);
+
+        let image = read_hdr(data.as_slice()).unwrap();
+        let pixels = image.pixels();
+        assert_eq!(pixels.len(), width);
+        assert!((pixels[0].red - 64.5 / 128.0).abs() < 1e-12);
+        assert!((pixels[1].green - 2.5 / 128.0).abs() < 1e-12);
+        assert!((pixels[9].green - 3.5 / 128.0).abs() < 1e-12);
+        assert!((pixels[5].blue - 0.5 / 128.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_rejects_bad_signature() {
+        let data = b"P3\n1 1\n255\n".to_vec();
+        assert!(matches!(
+            read_hdr(data.as_slice()),
+            Err(ImageError::InvalidFormat(_))
+        ));
+    }
+
+    /// Bright, dim and repeated pixels, so scanlines mix runs and literals
+    fn test_image(width: usize) -> Framebuffer {
+        let pixels = (0..width * 3)
+            .map(|index| {
+                let x = index % width;
+                if x < width / 2 {
+                    Color {
+                        red: 40.0,
+                        green: 0.25,
+                        blue: 0.0,
+                    }
+                } else {
+                    let shade = f64::from(u32::try_from(index).unwrap()) * 0.01;
+                    Color {
+                        red: shade,
+                        green: 2.0 * shade,
+                        blue: 1e-3,
+                    }
+                }
+            })
+            .collect();
+        Framebuffer::from_pixels(width, 3, pixels)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // too narrow to run-length encode, then wide enough
+        for width in [5, 40, 300] {
+            let image = test_image(width);
+            let mut data = Vec::new();
+            write_hdr(&mut data, &image).unwrap();
+
+            let read = read_hdr(data.as_slice()).unwrap();
+            assert_eq!((read.width(), read.height()), (width, 3));
+            for (read, original) in read.pixels().iter().zip(image.pixels()) {
+                // 8 bit mantissas keep about 1% of the brightest channel
+                let tolerance = original.red.max(original.green).max(original.blue) / 128.0;
+                assert!((read.red - original.red).abs() <= tolerance);
+                assert!((read.green - original.green).abs() <= tolerance);
+                assert!((read.blue - original.blue).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_runs_compress() {
+        let image = Framebuffer::new(200, 1);
+        let mut data = Vec::new();
+        write_hdr(&mut data, &image).unwrap();
+        assert!(data.len() < 100);
+    }
+
+    #[test]
+    fn test_rgbe_exponent() {
+        assert_eq!(color_to_rgbe(Color::black()), [0, 0, 0, 0]);
+        // 1.0 is 0.5 * 2^1
+        let white = Color::new(1.0, 1.0, 1.0).unwrap();
+        assert_eq!(color_to_rgbe(white), [128, 128, 128, 129]);
+        let decoded = rgbe_to_color(color_to_rgbe(Color {
+            red: 1000.0,
+            green: 1.0,
+            blue: 0.0,
+        }));
+        assert!((decoded.red - 1000.0).abs() < 1000.0 / 128.0);
+    }
+
+    #[test]
+    fn test_truncated_data_is_an_error() {
+        let mut data = header(2, 2);
+        data.extend([128, 128, 128, 128]);
+        assert!(matches!(read_hdr(data.as_slice()), Err(ImageError::Io(_))));
+    }
+
+    #[test]
+    fn test_size_is_checked_before_reading() {
+        // too many pixels to count
+        let data = header(1 << 40, 1 << 40);
+        assert!(matches!(
+            read_hdr(data.as_slice()),
+            Err(ImageError::InvalidFormat(_))
+        ));
+
+        // and far more than the few bytes that follow could hold
+        let mut data = header(30_000, 30_000);
+        data.extend([2, 2, 0x75, 0x30]);
+        assert!(matches!(read_hdr(data.as_slice()), Err(ImageError::Io(_))));
+    }
+}
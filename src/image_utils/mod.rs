@@ -0,0 +1,221 @@
+//! Reading and writing image files
+
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    str::FromStr,
+};
+
+use framebuffer::Framebuffer;
+use tone_map::DisplayTransform;
+
+pub mod denoise;
+pub mod framebuffer;
+pub mod hdr;
+pub mod pfm;
+pub mod png;
+pub mod ppm;
+pub mod tone_map;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    /// The data isn't laid out the way the format requires, with a description of why
+    InvalidFormat(String),
+    /// No known format goes with the file extension or name given
+    UnknownFormat(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::InvalidFormat(reason) => write!(f, "invalid image: {reason}"),
+            Self::UnknownFormat(name) => write!(
+                f,
+                "unknown image format {name:?}, expected one of {}",
+                ImageFormat::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::InvalidFormat(_) | Self::UnknownFormat(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl ImageError {
+    /// The data ends before the `width` by `height` image its header describes
+    fn truncated(width: usize, height: usize) -> Self {
+        Self::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("file is too short for a {width}x{height} image"),
+        ))
+    }
+}
+
+/// How many values a `width` by `height` image with `channels` values a pixel holds,
+/// its size coming straight from a file header that could claim anything
+fn value_count(width: usize, height: usize, channels: usize) -> Result<usize, ImageError> {
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(|| ImageError::InvalidFormat(format!("a {width}x{height} image is too large")))
+}
+
+/// File formats a finished image can be saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Binary P6 `.ppm`
+    Ppm,
+    /// ASCII P3 `.ppm`, easy to read but large
+    PpmAscii,
+    /// Radiance RGBE `.hdr`, keeps radiance above 1 at 8 bits of precision
+    Hdr,
+    /// Portable float map `.pfm`, 32 bit floats per channel
+    Pfm,
+}
+
+impl ImageFormat {
+    /// Names accepted by `from_str`
+    pub const NAMES: [&str; 5] = ["png", "ppm", "ppm-ascii", "hdr", "pfm"];
+
+    /// Pick the format that goes with a file's extension, `.ppm` files are binary
+    ///
+    /// # Errors
+    /// If the extension is missing or not one of ours
+    pub fn from_path(path: &Path) -> Result<Self, ImageError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "png" => Ok(Self::Png),
+            "ppm" | "pnm" => Ok(Self::Ppm),
+            "hdr" | "pic" => Ok(Self::Hdr),
+            "pfm" => Ok(Self::Pfm),
+            "" => Err(ImageError::UnknownFormat(path.display().to_string())),
+            _ => Err(ImageError::UnknownFormat(format!(".{extension}"))),
+        }
+    }
+
+    /// Write the image, passing it through `display` first unless the format keeps
+    /// linear radiance, which is left as rendered so it can be graded later
+    ///
+    /// # Errors
+    /// If the image can't be encoded or written
+    pub fn write<W: std::io::Write>(
+        self,
+        writer: W,
+        image: &Framebuffer,
+        display: &DisplayTransform,
+    ) -> Result<(), ImageError> {
+        match self {
+            Self::Png => png::write_png(writer, &display.apply_to_image(image)),
+            Self::Ppm => ppm::write_ppm_binary(writer, &display.apply_to_image(image)),
+            Self::PpmAscii => ppm::write_ppm_ascii(writer, &display.apply_to_image(image)),
+            Self::Hdr => hdr::write_hdr(writer, image),
+            Self::Pfm => pfm::write_pfm(writer, image),
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = ImageError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "png" => Ok(Self::Png),
+            "ppm" => Ok(Self::Ppm),
+            "ppm-ascii" => Ok(Self::PpmAscii),
+            "hdr" => Ok(Self::Hdr),
+            "pfm" => Ok(Self::Pfm),
+            _ => Err(ImageError::UnknownFormat(name.to_string())),
+        }
+    }
+}
+
+/// Save an image to `path`, see `ImageFormat::write`
+///
+/// # Errors
+/// If the file can't be created or written
+pub fn write_image(
+    path: &Path,
+    format: ImageFormat,
+    image: &Framebuffer,
+    display: &DisplayTransform,
+) -> Result<(), ImageError> {
+    format.write(BufWriter::new(File::create(path)?), image, display)
+}
+
+/// Load an image from `path`, picking the reader from the extension
+///
+/// PNG files can be written but not read back.
+///
+/// # Errors
+/// If the file can't be read or isn't a valid image of a readable format
+pub fn read_image(path: &Path) -> Result<Framebuffer, ImageError> {
+    let format = ImageFormat::from_path(path)?;
+    let reader = BufReader::new(File::open(path)?);
+    match format {
+        ImageFormat::Ppm | ImageFormat::PpmAscii => ppm::read_ppm(reader),
+        ImageFormat::Hdr => hdr::read_hdr(reader),
+        ImageFormat::Pfm => pfm::read_pfm(reader),
+        ImageFormat::Png => Err(ImageError::InvalidFormat(
+            "reading PNG images isn't supported".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ImageFormat::from_path(Path::new("render.PNG")).unwrap(),
+            ImageFormat::Png
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("out/render.ppm")).unwrap(),
+            ImageFormat::Ppm
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("sky.hdr")).unwrap(),
+            ImageFormat::Hdr
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("render.pfm")).unwrap(),
+            ImageFormat::Pfm
+        );
+        for path in ["render.jpg", "render"] {
+            assert!(matches!(
+                ImageFormat::from_path(Path::new(path)),
+                Err(ImageError::UnknownFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_format_names() {
+        for name in ImageFormat::NAMES {
+            assert!(name.parse::<ImageFormat>().is_ok());
+        }
+        assert!("gif".parse::<ImageFormat>().is_err());
+    }
+}
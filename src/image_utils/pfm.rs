@@ -0,0 +1,180 @@
+//! Portable float map (`.pfm`) images
+//!
+//! A short text header followed by raw 32 bit floats, three per pixel for color
+//! (`PF`) or one for grayscale (`Pf`). The sign of the scale in the header gives the
+//! byte order, negative for little endian, and its size multiplies every value. Rows
+//! run from the bottom up.
+
+use std::io::{BufRead, Read, Write};
+
+use super::{ImageError, framebuffer::Framebuffer, value_count};
+use crate::ray_tracing::color::Color;
+
+/// Write a little endian color image
+///
+/// # Errors
+/// If the image can't be written
+#[allow(clippy::cast_possible_truncation)] // the format only stores single precision
+pub fn write_pfm<W: Write>(mut writer: W, image: &Framebuffer) -> Result<(), ImageError> {
+    write!(writer, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for row in image.pixels().chunks_exact(image.width().max(1)).rev() {
+        for color in row {
+            for channel in [color.red, color.green, color.blue] {
+                writer.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(writer.flush()?)
+}
+
+/// Read a color or grayscale image in either byte order
+///
+/// # Errors
+/// If the data can't be read or isn't a valid float map
+pub fn read_pfm<R: BufRead>(mut reader: R) -> Result<Framebuffer, ImageError> {
+    let mut header = String::new();
+    // the header is three lines: type, size and scale
+    for _ in 0..3 {
+        if reader.read_line(&mut header)? == 0 {
+            return Err(ImageError::InvalidFormat(
+                "file ended inside the header".to_string(),
+            ));
+        }
+    }
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    let [kind, width, height, scale] = fields.as_slice() else {
+        return Err(ImageError::InvalidFormat(format!(
+            "bad header {}",
+            header.trim()
+        )));
+    };
+
+    let channels = match *kind {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => {
+            return Err(ImageError::InvalidFormat(format!(
+                "unsupported image type {kind}"
+            )));
+        }
+    };
+    let bad_value = |field: &str| ImageError::InvalidFormat(format!("bad header value {field}"));
+    let width: usize = width.parse().map_err(|_| bad_value(width))?;
+    let height: usize = height.parse().map_err(|_| bad_value(height))?;
+    let scale = match scale.parse::<f64>() {
+        Ok(number) if number.is_normal() => number,
+        _ => return Err(bad_value(scale)),
+    };
+    let little_endian = scale < 0.0;
+
+    // the header can claim any size, so the data is only taken as far as it goes
+    let byte_count = value_count(width, height, channels * 4)?;
+    let mut data = Vec::new();
+    reader
+        .take(u64::try_from(byte_count).unwrap_or(u64::MAX))
+        .read_to_end(&mut data)?;
+    if data.len() < byte_count {
+        return Err(ImageError::truncated(width, height));
+    }
+    let values: Vec<f64> = data
+        .chunks_exact(4)
+        .map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            let value = if little_endian {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            f64::from(value) * scale.abs()
+        })
+        .collect();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks_exact((width * channels).max(1)).rev() {
+        pixels.extend(row.chunks_exact(channels).map(|pixel| match pixel {
+            [red, green, blue] => Color {
+                red: *red,
+                green: *green,
+                blue: *blue,
+            },
+            _ => Color {
+                red: pixel[0],
+                green: pixel[0],
+                blue: pixel[0],
+            },
+        }));
+    }
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let pixels = (0..6_u8)
+            .map(|index| Color {
+                red: f64::from(index) * 10.0,
+                green: 0.125,
+                blue: 1e-3,
+            })
+            .collect();
+        let image = Framebuffer::from_pixels(3, 2, pixels);
+
+        let mut data = Vec::new();
+        write_pfm(&mut data, &image).unwrap();
+        assert!(data.starts_with(b"PF\n3 2\n-1.0\n"));
+        // the bottom row comes first
+        let first_red = f32::from_le_bytes(data[12..16].try_into().unwrap());
+        assert!((first_red - 30.0).abs() < f32::EPSILON);
+
+        let read = read_pfm(data.as_slice()).unwrap();
+        assert_eq!((read.width(), read.height()), (3, 2));
+        for (read, original) in read.pixels().iter().zip(image.pixels()) {
+            assert!((read.red - original.red).abs() < 1e-5);
+            assert!((read.green - original.green).abs() < 1e-9);
+            assert!((read.blue - original.blue).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_read_big_endian_grayscale() {
+        // with every value scaled by the size of the scale
+        let mut data = b"Pf\n2 1\n2.0\n".to_vec();
+        data.extend(2.5_f32.to_be_bytes());
+        data.extend(0.5_f32.to_be_bytes());
+        let image = read_pfm(data.as_slice()).unwrap();
+        assert_eq!(
+            image.get(0, 0),
+            Color {
+                red: 5.0,
+                green: 5.0,
+                blue: 5.0
+            }
+        );
+        assert!((image.get(1, 0).blue - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert!(matches!(
+            read_pfm(&b"P6\n1 1\n255\n"[..]),
+            Err(ImageError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            read_pfm(&b"PF\n2 2\n-1.0\n\x00\x00"[..]),
+            Err(ImageError::Io(_))
+        ));
+        for header in [
+            &b"PF\n1 1\n0.0\n"[..],
+            b"PF\n1 1\nnan\n",
+            b"PF\n4294967296 4294967296\n-1.0\n",
+        ] {
+            assert!(
+                matches!(read_pfm(header), Err(ImageError::InvalidFormat(_))),
+                "{header:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! PNG images, 8 bit RGB tagged as sRGB
+
+use std::io::Write;
+
+use super::{ImageError, framebuffer::Framebuffer};
+
+/// # Errors
+/// If the image can't be written or is too large for PNG
+pub fn write_png<W: Write>(writer: W, image: &Framebuffer) -> Result<(), ImageError> {
+    let too_large = |_| ImageError::InvalidFormat("image is too large for PNG".to_string());
+    let width = u32::try_from(image.width()).map_err(too_large)?;
+    let height = u32::try_from(image.height()).map_err(too_large)?;
+
+    let mut encoder = ::png::Encoder::new(writer, width, height);
+    encoder.set_color(::png::ColorType::Rgb);
+    encoder.set_depth(::png::BitDepth::Eight);
+    encoder.set_source_srgb(::png::SrgbRenderingIntent::Perceptual);
+
+    let mut png_writer = encoder.write_header().map_err(encoding_error)?;
+    png_writer
+        .write_image_data(&image.to_rgb8())
+        .map_err(encoding_error)?;
+    png_writer.finish().map_err(encoding_error)
+}
+
+fn encoding_error(error: ::png::EncodingError) -> ImageError {
+    match error {
+        ::png::EncodingError::IoError(error) => ImageError::Io(error),
+        error => ImageError::InvalidFormat(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_tracing::color::Color;
+
+    #[test]
+    fn test_round_trip() {
+        let pixels = (0..6_u8)
+            .map(|index| {
+                let shade = f64::from(index) / 5.0;
+                Color::new(shade, 0.25, 1.0 - shade).unwrap()
+            })
+            .collect();
+        let image = Framebuffer::from_pixels(3, 2, pixels);
+
+        let mut data = Vec::new();
+        write_png(&mut data, &image).unwrap();
+
+        let decoder = ::png::Decoder::new(std::io::Cursor::new(data));
+        let mut reader = decoder.read_info().unwrap();
+        assert!(reader.info().srgb.is_some());
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let frame = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((frame.width, frame.height), (3, 2));
+        assert_eq!(frame.color_type, ::png::ColorType::Rgb);
+        assert_eq!(pixels, image.to_rgb8());
+    }
+}
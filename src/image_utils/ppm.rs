@@ -0,0 +1,193 @@
+//! Netpbm `.ppm` images, as ASCII (P3) or binary (P6)
+//!
+//! Both store a short text header followed by 8 bit red, green and blue per pixel.
+//! P3 writes every value as decimal text, P6 as raw bytes which is about a quarter
+//! of the size.
+
+use std::io::{BufRead, Write};
+
+use super::{ImageError, framebuffer::Framebuffer, value_count};
+use crate::ray_tracing::color::Color;
+
+/// # Errors
+/// If the image can't be written
+pub fn write_ppm_ascii<W: Write>(mut writer: W, image: &Framebuffer) -> Result<(), ImageError> {
+    write!(writer, "P3\n{} {}\n255\n", image.width(), image.height())?;
+    for pixel in image.to_rgb8().chunks_exact(3) {
+        writeln!(writer, "{} {} {}", pixel[0], pixel[1], pixel[2])?;
+    }
+    Ok(writer.flush()?)
+}
+
+/// # Errors
+/// If the image can't be written
+pub fn write_ppm_binary<W: Write>(mut writer: W, image: &Framebuffer) -> Result<(), ImageError> {
+    write!(writer, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    writer.write_all(&image.to_rgb8())?;
+    Ok(writer.flush()?)
+}
+
+/// Read an 8 bit P3 or P6 image, with every channel scaled into [0, 1]
+///
+/// # Errors
+/// If the data can't be read or isn't an 8 bit P3 or P6 image
+pub fn read_ppm<R: BufRead>(mut reader: R) -> Result<Framebuffer, ImageError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    // the header is four whitespace separated fields, with comments running from # to
+    // the end of the line, and a single whitespace character before binary pixel data
+    let mut position = 0;
+    let mut next_field = || -> Result<String, ImageError> {
+        loop {
+            match data.get(position) {
+                Some(b'#') => {
+                    while data.get(position).is_some_and(|&byte| byte != b'\n') {
+                        position += 1;
+                    }
+                }
+                Some(byte) if byte.is_ascii_whitespace() => position += 1,
+                Some(_) => break,
+                None => {
+                    return Err(ImageError::InvalidFormat(
+                        "file ended inside the header".to_string(),
+                    ));
+                }
+            }
+        }
+        let start = position;
+        while data
+            .get(position)
+            .is_some_and(|byte| !byte.is_ascii_whitespace())
+        {
+            position += 1;
+        }
+        Ok(String::from_utf8_lossy(&data[start..position]).into_owned())
+    };
+
+    let magic = next_field()?;
+    let mut number = || -> Result<usize, ImageError> {
+        let field = next_field()?;
+        field
+            .parse()
+            .map_err(|_| ImageError::InvalidFormat(format!("bad header value {field}")))
+    };
+    let (width, height, max_value) = (number()?, number()?, number()?);
+    if max_value != 255 {
+        return Err(ImageError::InvalidFormat(format!(
+            "only 8 bit images are supported, not a maximum of {max_value}"
+        )));
+    }
+
+    let channel_count = value_count(width, height, 3)?;
+    let bytes = match magic.as_str() {
+        "P3" => String::from_utf8_lossy(&data[position..])
+            .split_whitespace()
+            .take(channel_count)
+            .map(|value| {
+                value
+                    .parse::<u8>()
+                    .map_err(|_| ImageError::InvalidFormat(format!("bad pixel value {value}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        "P6" => data
+            .get(position + 1..)
+            .and_then(|pixels| pixels.get(..channel_count))
+            .unwrap_or_default()
+            .to_vec(),
+        _ => {
+            return Err(ImageError::InvalidFormat(format!(
+                "unsupported image type {magic}"
+            )));
+        }
+    };
+    if bytes.len() != channel_count {
+        return Err(ImageError::InvalidFormat(
+            "file ended before the last pixel".to_string(),
+        ));
+    }
+
+    let pixels = bytes
+        .chunks_exact(3)
+        .map(|pixel| Color {
+            red: f64::from(pixel[0]) / 255.0,
+            green: f64::from(pixel[1]) / 255.0,
+            blue: f64::from(pixel[2]) / 255.0,
+        })
+        .collect();
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gradient with a distinct value in every channel
+    fn test_image() -> Framebuffer {
+        let (width, height) = (4, 3);
+        let pixels = (0..width * height)
+            .map(|index| {
+                let shade = f64::from(u8::try_from(index).unwrap()) / 12.0;
+                Color::new(shade, 1.0 - shade, 0.5).unwrap()
+            })
+            .collect();
+        Framebuffer::from_pixels(width, height, pixels)
+    }
+
+    fn assert_same_bytes(read: &Framebuffer, original: &Framebuffer) {
+        assert_eq!(
+            (read.width(), read.height()),
+            (original.width(), original.height())
+        );
+        let read_bytes: Vec<f64> = read
+            .pixels()
+            .iter()
+            .flat_map(|color| [color.red, color.green, color.blue])
+            .map(|channel| (channel * 255.0).round())
+            .collect();
+        let original_bytes: Vec<f64> = original.to_rgb8().into_iter().map(f64::from).collect();
+        assert_eq!(read_bytes, original_bytes);
+    }
+
+    #[test]
+    fn test_ascii_round_trip() {
+        let image = test_image();
+        let mut data = Vec::new();
+        write_ppm_ascii(&mut data, &image).unwrap();
+        assert!(data.starts_with(b"P3\n4 3\n255\n"));
+        assert_same_bytes(&read_ppm(data.as_slice()).unwrap(), &image);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let image = test_image();
+        let mut data = Vec::new();
+        write_ppm_binary(&mut data, &image).unwrap();
+        assert_eq!(data.len(), "P6\n4 3\n255\n".len() + 4 * 3 * 3);
+        assert_same_bytes(&read_ppm(data.as_slice()).unwrap(), &image);
+    }
+
+    #[test]
+    fn test_read_with_comments() {
+        let data = b"P3\n# made by hand\n2 1 # width and height\n255\n255 0 0  0 0 255\n";
+        let image = read_ppm(data.as_slice()).unwrap();
+        assert_eq!(image.get(0, 0), Color::new(1.0, 0.0, 0.0).unwrap());
+        assert_eq!(image.get(1, 0), Color::new(0.0, 0.0, 1.0).unwrap());
+    }
+
+    #[test]
+    fn test_read_errors() {
+        for data in [
+            &b"P5\n1 1\n255\n\x00"[..],
+            b"P3\n1 1\n65535\n0 0 0\n",
+            b"P3\n2 1\n255\n0 0 0\n",
+            b"P6\n2 1\n255\n\x00\x00\x00",
+            b"P3\n1",
+            // more pixels than can be counted
+            b"P6\n4294967296 4294967296\n255\n\x00\x00\x00",
+            b"P3\n4294967296 4294967296\n255\n0 0 0\n",
+        ] {
+            assert!(matches!(read_ppm(data), Err(ImageError::InvalidFormat(_))));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Turning linear radiance into colors a display can show
+//!
+//! Rendered images are linear and unbounded. Before they can be stored in 8 bits they
+//! are scaled by the exposure, squeezed into [0, 1] by a tone mapping operator, and
+//! encoded with the sRGB transfer function so the steps between bytes look even.
+
+use std::{fmt, str::FromStr};
+
+use super::framebuffer::Framebuffer;
+use crate::ray_tracing::color::Color;
+
+/// How radiance above 1 is brought into range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMap {
+    /// Cut every channel off at 1, bright areas blow out to flat color
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on luminance, never quite reaches white
+    Reinhard,
+    /// Reinhard stretched so luminance at the white point maps to exactly 1
+    ExtendedReinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with a gentle shoulder and toe
+    Aces,
+}
+
+impl ToneMap {
+    /// Names accepted by `from_str`
+    pub const NAMES: [&str; 4] = ["clamp", "reinhard", "extended-reinhard", "aces"];
+
+    /// Map a linear color into [0, 1], `white_point` is only used by `ExtendedReinhard`
+    #[must_use]
+    pub fn apply(self, color: Color, white_point: f64) -> Color {
+        match self {
+            Self::Clamp => map_channels(color, |channel| channel.clamp(0.0, 1.0)),
+            Self::Reinhard => scale_luminance(color, |luminance| luminance / (1.0 + luminance)),
+            Self::ExtendedReinhard => scale_luminance(color, |luminance| {
+                luminance * (1.0 + luminance / (white_point * white_point)) / (1.0 + luminance)
+            }),
+            Self::Aces => map_channels(color, |channel| {
+                // the fit is made for input already scaled down by 0.6
+                let x = 0.6 * channel;
+                (x * 2.51f64.mul_add(x, 0.03) / x.mul_add(2.43f64.mul_add(x, 0.59), 0.14))
+                    .clamp(0.0, 1.0)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = match self {
+            Self::Clamp => 0,
+            Self::Reinhard => 1,
+            Self::ExtendedReinhard => 2,
+            Self::Aces => 3,
+        };
+        f.write_str(Self::NAMES[index])
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "clamp" => Ok(Self::Clamp),
+            "reinhard" => Ok(Self::Reinhard),
+            "extended-reinhard" => Ok(Self::ExtendedReinhard),
+            "aces" => Ok(Self::Aces),
+            _ => Err(format!(
+                "unknown tone map {name:?}, expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Everything between the rendered radiance and the bytes of an 8 bit image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    /// Brightness change in stops, every stop doubles the light
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    /// Luminance that becomes pure white under `ToneMap::ExtendedReinhard`
+    pub white_point: f64,
+}
+
+/// No exposure change, clamped
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tone_map: ToneMap::default(),
+            white_point: 4.0,
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// Expose, tone map and sRGB encode a single color, giving channels in [0, 1]
+    #[must_use]
+    pub fn apply(&self, color: Color) -> Color {
+        let exposed = color * self.exposure.exp2();
+        map_channels(
+            self.tone_map.apply(exposed, self.white_point),
+            linear_to_srgb,
+        )
+    }
+
+    /// A copy of the image ready to be quantized, see `apply`
+    #[must_use]
+    pub fn apply_to_image(&self, image: &Framebuffer) -> Framebuffer {
+        Framebuffer::from_pixels(
+            image.width(),
+            image.height(),
+            image
+                .pixels()
+                .iter()
+                .map(|&color| self.apply(color))
+                .collect(),
+        )
+    }
+}
+
+/// The sRGB transfer function, linear near black and roughly a 2.2 power curve above
+#[must_use]
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear.max(0.0)
+    } else {
+        1.055f64.mul_add(linear.powf(1.0 / 2.4), -0.055)
+    }
+}
+
+fn map_channels(color: Color, map: impl Fn(f64) -> f64) -> Color {
+    Color {
+        red: map(color.red),
+        green: map(color.green),
+        blue: map(color.blue),
+    }
+}
+
+/// Rescale the whole color so its luminance follows `curve`, keeping its hue, then
+/// clamp whatever channels still poke out of range
+fn scale_luminance(color: Color, curve: impl Fn(f64) -> f64) -> Color {
+    let luminance = color.luminance();
+    if luminance <= 0.0 {
+        return Color::black();
+    }
+    map_channels(color * (curve(luminance) / luminance), |channel| {
+        channel.clamp(0.0, 1.0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn gray(value: f64) -> Color {
+        Color {
+            red: value,
+            green: value,
+            blue: value,
+        }
+    }
+
+    #[test]
+    fn test_srgb_transfer() {
+        assert!(linear_to_srgb(0.0).abs() < 1e-12);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        // middle gray sits well above the middle once encoded
+        assert!((linear_to_srgb(0.18) - 0.4614).abs() < 1e-4);
+        // the two pieces meet at the threshold
+        let below = linear_to_srgb(0.003_130_8);
+        let above = linear_to_srgb(0.003_130_8 + 1e-12);
+        assert!((below - above).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_operators_stay_in_range() {
+        for tone_map in [
+            ToneMap::Clamp,
+            ToneMap::Reinhard,
+            ToneMap::ExtendedReinhard,
+            ToneMap::Aces,
+        ] {
+            let mut previous = -1.0;
+            for value in [0.0, 0.01, 0.5, 1.0, 3.0, 100.0] {
+                let mapped = tone_map.apply(gray(value), 4.0);
+                assert!((0.0..=1.0).contains(&mapped.red), "{tone_map} {value}");
+                assert!(mapped.red >= previous, "{tone_map} isn't monotonic");
+                previous = mapped.red;
+            }
+        }
+    }
+
+    #[test]
+    fn test_operator_curves() {
+        assert_eq!(ToneMap::Clamp.apply(gray(3.0), 4.0), gray(1.0));
+        assert!((ToneMap::Reinhard.apply(gray(1.0), 4.0).red - 0.5).abs() < 1e-12);
+        assert!((ToneMap::ExtendedReinhard.apply(gray(4.0), 4.0).red - 1.0).abs() < 1e-12);
+        // hue is kept, red stays twice as bright as green
+        let mapped = ToneMap::Reinhard.apply(
+            Color {
+                red: 0.4,
+                green: 0.2,
+                blue: 0.0,
+            },
+            4.0,
+        );
+        assert!((mapped.red / mapped.green - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_exposure() {
+        let transform = DisplayTransform {
+            exposure: 1.0,
+            ..DisplayTransform::default()
+        };
+        assert!((transform.apply(gray(0.5)).green - 1.0).abs() < 1e-12);
+        assert_eq!(DisplayTransform::default().apply(gray(0.0)), Color::black());
+    }
+
+    #[test]
+    fn test_names() {
+        for name in ToneMap::NAMES {
+            assert_eq!(name.parse::<ToneMap>().unwrap().to_string(), name);
+        }
+        assert!("filmic".parse::<ToneMap>().is_err());
+    }
+}
@@ -1,94 +1,249 @@
 #![warn(clippy::pedantic)] // Enable the pedantic lint group as warnings
 #![warn(clippy::nursery)] // Enable the nursery lint group as warnings
 
+pub mod cli;
+pub mod image_utils;
 pub mod math_utils;
 pub mod ray_tracing;
+pub mod scene;
+
+use std::{fs, mem, path::Path, process::ExitCode, time::Instant};
+
+use clap::Parser;
+
+use crate::cli::Args;
+use crate::image_utils::denoise::{Denoiser, Guides};
+use crate::image_utils::framebuffer::Framebuffer;
+use crate::image_utils::tone_map::DisplayTransform;
+use crate::image_utils::{ImageError, ImageFormat, read_image, write_image};
+use crate::ray_tracing::aov::Aov;
+use crate::ray_tracing::camera::{camera::RenderOutput, tiles::Tile};
+use crate::ray_tracing::shapes::{flat_bvh::FlatBvh, group::Group};
+use crate::scene::file::{Scene, load_scene};
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    if let Some(threads) = args.threads
+        && let Err(error) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+    {
+        eprintln!("error: can't start {threads} threads: {error}");
+        return ExitCode::FAILURE;
+    }
+
+    // clap makes sure an output comes with an image to denoise
+    if let (Some(input), Some(output)) = (&args.denoise_image, &args.output) {
+        return denoise_image(&args, input, output);
+    }
+
+    let mut scene = match load_scene(&args.scene) {
+        Ok(scene) => scene,
+        Err(error) => {
+            eprintln!("error: can't load {}: {error}", args.scene.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(error) = args.apply(&mut scene) {
+        eprintln!("error: {error}");
+        return ExitCode::FAILURE;
+    }
+
+    // settle the format before rendering, rather than find out it's unknown afterwards
+    let format = match args
+        .format
+        .map_or_else(|| ImageFormat::from_path(&scene.output), Ok)
+    {
+        Ok(format) => format,
+        Err(error) => {
+            eprintln!("error: can't write {}: {error}", scene.output.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let heatmap = match scene
+        .heatmap
+        .as_deref()
+        .map(ImageFormat::from_path)
+        .transpose()
+    {
+        Ok(format) => scene.heatmap.as_deref().zip(format),
+        Err(error) => {
+            eprintln!("error: can't write the heatmap: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let camera = scene.camera.make_camera();
+    // a window that misses the image would trace nothing and save an empty image
+    let Some(region) = camera.region() else {
+        eprintln!("error: the crop window is outside the image");
+        return ExitCode::FAILURE;
+    };
+
+    let world = FlatBvh::from(mem::replace(&mut scene.world, Group::new(Vec::new())));
+    if args.verbose {
+        print_settings(&scene, &world, region);
+    }
+
+    let integrator = scene.integrator.build();
+    let mut aovs: Vec<Aov> = scene.aovs.keys().copied().collect();
+    if scene.denoiser.is_some() {
+        // the denoiser needs its guides whether or not they're saved
+        for guide in [Aov::Normal, Aov::Albedo] {
+            if !aovs.contains(&guide) {
+                aovs.push(guide);
+            }
+        }
+    }
+    let progressive = scene.camera.pass_samples.is_some();
+    let mut last_snapshot = Instant::now();
+    let output = camera.render(&world, &scene.lights, integrator.as_ref(), &aovs, |pass| {
+        if !progressive || last_snapshot.elapsed() < scene.snapshot_interval {
+            return;
+        }
+        last_snapshot = Instant::now();
+        // a failed snapshot is no reason to throw the render away
+        if let Err(error) =
+            write_snapshot(&scene.output, format, &pass.output().image, &scene.display)
+        {
+            eprintln!(
+                "warning: can't write a snapshot to {}: {error}",
+                scene.output.display()
+            );
+        }
+    });
+    if let Err(error) = save(&scene, output, format, heatmap) {
+        eprintln!("error: {error}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Tell what the render is about to do, for `--verbose`
+fn print_settings(scene: &Scene, world: &FlatBvh, region: Tile) {
+    eprintln!("BVH: {}", world.stats());
+    eprintln!("Lights: {}", scene.lights.len());
+    eprintln!("Integrator: {}", scene.integrator.kind);
+    eprintln!("Sampler: {}", scene.camera.sampler);
+    eprintln!(
+        "Filter: {}, radius {}",
+        scene.camera.filter.kind, scene.camera.filter.radius
+    );
+    if scene.camera.crop.is_some() {
+        eprintln!(
+            "Crop: {}x{} pixels from ({}, {})",
+            region.width, region.height, region.x, region.y
+        );
+    }
+    if let Some(adaptive) = &scene.camera.adaptive {
+        eprintln!(
+            "Adaptive sampling: threshold {}, at least {} samples a pixel",
+            adaptive.threshold, adaptive.min_samples
+        );
+    }
+}
+
+/// Save the finished image, denoised if the scene asks for it, with its heatmap and
+/// AOVs
+fn save(
+    scene: &Scene,
+    output: RenderOutput,
+    format: ImageFormat,
+    heatmap: Option<(&Path, ImageFormat)>,
+) -> Result<(), String> {
+    if let Some((path, format)) = heatmap {
+        // the counts are already display colors, so they're saved untouched
+        let display = DisplayTransform::default();
+        write_image(path, format, &output.heatmap(), &display)
+            .map_err(|error| format!("can't write {}: {error}", path.display()))?;
+    }
+
+    let image = match &scene.denoiser {
+        Some(denoiser) => {
+            let guides = Guides {
+                normal: output.aovs.get(&Aov::Normal),
+                albedo: output.aovs.get(&Aov::Albedo),
+            };
+            denoiser
+                .denoise(&output.image, guides)
+                .map_err(|error| format!("can't denoise: {error}"))?
+        }
+        None => output.image,
+    };
+    write_image(&scene.output, format, &image, &scene.display)
+        .map_err(|error| format!("can't write {}: {error}", scene.output.display()))?;
+    for (aov, path) in &scene.aovs {
+        write_image(path, ImageFormat::Pfm, &output.aovs[aov], &scene.display)
+            .map_err(|error| format!("can't write {aov} to {}: {error}", path.display()))?;
+    }
+
+    Ok(())
+}
 
-use std::sync::Arc;
-
-use crate::math_utils::point::Point;
-use crate::math_utils::vector::Vec3;
-use crate::ray_tracing::color::Color;
-use crate::ray_tracing::materials::dielectric::DielectricMaterial;
-use crate::ray_tracing::materials::lambertian::LambertianMaterial;
-use crate::ray_tracing::materials::material::Scatterer;
-use crate::ray_tracing::materials::metal::MetalMaterial;
-use crate::ray_tracing::shapes::hittable::Hittable;
-use crate::ray_tracing::{
-    camera::camera::CameraBuilder,
-    shapes::{group::Group, sphere::Sphere},
-};
-
-fn main() {
-    // get the objects we're rendering in the world
-    let world = make_world();
-
-    let camera = CameraBuilder::default()
-        .set_look_from(-2.0, 2.0,1.0)
-        .set_defocus_angle(10.0)
-        .set_focus_dist(3.4)
-        .make_camera();
-
-    camera.render(world);
+/// Save an unfinished image over `path`, by way of a file next to it so a render
+/// stopped halfway through a write still leaves a whole image behind
+fn write_snapshot(
+    path: &Path,
+    format: ImageFormat,
+    image: &Framebuffer,
+    display: &DisplayTransform,
+) -> Result<(), ImageError> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    write_image(Path::new(&partial), format, image, display)?;
+    fs::rename(&partial, path)?;
+    Ok(())
 }
 
-fn make_world() -> Group {
-    // green ground
-
-    // let material_left = LambertianMaterial::new(Color::new(0.0, 0.0, 1.0).unwrap());
-    // let material_left = Arc::new(material_left);
-    // let material_right = LambertianMaterial::new(Color::new(1.0, 0.0, 0.0).unwrap());
-    // let material_right = Arc::new(material_right);
-
-    // let radius = (std::f64::consts::PI/4.0).cos();
-    // let left_sphere = Box::new(Sphere::new(
-    //     Point::new(-radius, 0.0, -1.0), radius, material_left
-    // ));
-    // let right_sphere = Box::new(Sphere::new(
-    //     Point::new(radius, 0.0, -1.0), radius, material_right
-    // ));
-
-    let material_ground = LambertianMaterial::new(Color::new(0.8, 0.8, 0.0).unwrap());
-    let material_ground = Arc::new(material_ground);
-
-    let material_center = LambertianMaterial::new(Color::new(0.1, 0.2, 0.5).unwrap());
-    let material_center = Arc::new(material_center);
-
-    let material_left = DielectricMaterial::new(1.50);
-    let material_left = Arc::new(material_left);
-
-    let material_bubble = DielectricMaterial::new(1.00 / 1.50);
-    let material_bubble = Arc::new(material_bubble);
-
-    let material_right = MetalMaterial::new(Color::new(0.8, 0.6, 0.2).unwrap());
-    let material_right = Arc::new(material_right);
-
-    let world_elements: Vec<(f64, f64, f64, f64, Arc<dyn Scatterer + Send + Sync>)> = vec![
-        (0.0, -100.5, -1.0, 100.0, material_ground),
-        // (0.0, 0.0, -1.2, 0.5, material_center),
-        (-1.0, 0.0, -1.0, 0.5, material_left),
-        (-1.0, 0.0, -1.0, 0.4, material_bubble),
-        (1.0, 0.0, -1.0, 0.5, material_right),
-    ];
-
-    let mut world_elements: Vec<Box<dyn Hittable + Send + Sync>> = world_elements
-        .into_iter()
-        .map(|(px, py, pz, radius, material)| {
-            Box::new(Sphere::new(Point::new(px, py, pz), radius, material))
-                as Box<dyn Hittable + Send + Sync>
-        }).collect();
-
-    // make the center one moving
-    let moving_center = Box::new(
-        Sphere::new_with_velocity(
-            Point::new(0.0, 0.0, -1.2),
-            0.5,
-            material_center,
-            Vec3::new(0.10, 0.10, 0.10)
-        )
-    ) as Box<dyn Hittable + Send + Sync>;
-
-    world_elements.push(moving_center);
-
-    Group::new(world_elements)
+/// Denoise a saved image into `--output`, guided by whichever AOVs were given
+fn denoise_image(args: &Args, input: &Path, output: &Path) -> ExitCode {
+    let format = match args
+        .format
+        .map_or_else(|| ImageFormat::from_path(output), Ok)
+    {
+        Ok(format) => format,
+        Err(error) => {
+            eprintln!("error: can't write {}: {error}", output.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let read = |path: &Path| {
+        read_image(path).map_err(|error| format!("can't read {}: {error}", path.display()))
+    };
+    let images = read(input).and_then(|image| {
+        let normal = args.normal_guide.as_deref().map(read).transpose()?;
+        let albedo = args.albedo_guide.as_deref().map(read).transpose()?;
+        Ok((image, normal, albedo))
+    });
+    let (image, normal, albedo) = match images {
+        Ok(images) => images,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let guides = Guides {
+        normal: normal.as_ref(),
+        albedo: albedo.as_ref(),
+    };
+    let image = match Denoiser::default().denoise(&image, guides) {
+        Ok(image) => image,
+        Err(error) => {
+            eprintln!("error: can't denoise {}: {error}", input.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut display = DisplayTransform::default();
+    args.apply_display(&mut display);
+    if let Err(error) = write_image(output, format, &image, &display) {
+        eprintln!("error: can't write {}: {error}", output.display());
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
 }
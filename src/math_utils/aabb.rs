@@ -0,0 +1,210 @@
+//! Axis-aligned bounding box built from one interval per axis
+
+use super::{interval::Interval, point::Point, ray::Ray};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    #[must_use]
+    pub const fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Self { x, y, z }
+    }
+
+    /// A box containing nothing, useful as the starting point when growing a box
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self::new(Interval::empty(), Interval::empty(), Interval::empty())
+    }
+
+    /// Box spanned by two opposite corners (in any order)
+    #[must_use]
+    pub const fn from_points(a: Point<f64>, b: Point<f64>) -> Self {
+        Self::new(
+            Interval::new(a.x.min(b.x), a.x.max(b.x)),
+            Interval::new(a.y.min(b.y), a.y.max(b.y)),
+            Interval::new(a.z.min(b.z), a.z.max(b.z)),
+        )
+    }
+
+    /// Smallest box that contains both `a` and `b`
+    #[must_use]
+    pub const fn surrounding(a: &Self, b: &Self) -> Self {
+        Self::new(
+            Interval::enclosing(&a.x, &b.x),
+            Interval::enclosing(&a.y, &b.y),
+            Interval::enclosing(&a.z, &b.z),
+        )
+    }
+
+    /// Widen any side thinner than `delta` to exactly `delta`, so flat objects
+    /// still have a volume the slab test can hit
+    #[must_use]
+    pub fn pad_to_minimums(&self, delta: f64) -> Self {
+        let pad = |interval: Interval| {
+            if interval.size() < delta {
+                interval.expand(delta - interval.size())
+            } else {
+                interval
+            }
+        };
+        Self::new(pad(self.x), pad(self.y), pad(self.z))
+    }
+
+    /// Interval along axis `n` where 0 is x, 1 is y and anything else is z
+    #[must_use]
+    pub const fn axis_interval(&self, n: usize) -> &Interval {
+        match n {
+            0 => &self.x,
+            1 => &self.y,
+            _ => &self.z,
+        }
+    }
+
+    /// Index of the axis the box is widest along
+    #[must_use]
+    pub const fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    #[must_use]
+    pub const fn centroid(&self) -> Point<f64> {
+        Point::new(
+            f64::midpoint(self.x.min, self.x.max),
+            f64::midpoint(self.y.min, self.y.max),
+            f64::midpoint(self.z.min, self.z.max),
+        )
+    }
+
+    /// Total area of the six faces, zero for an empty box
+    #[must_use]
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
+        }
+        2.0 * dy.mul_add(dz, dx.mul_add(dy, dx * dz))
+    }
+
+    /// Slab test: does the ray pass through the box anywhere inside `ray_interval`
+    #[must_use]
+    pub fn hit(&self, ray: &Ray<f64>, ray_interval: Interval) -> bool {
+        let origin = ray.get_origin();
+        let direction = ray.get_direction();
+        let mut t_min = ray_interval.min;
+        let mut t_max = ray_interval.max;
+
+        for (slab, origin, direction) in [
+            (&self.x, origin.x, direction.x),
+            (&self.y, origin.y, direction.y),
+            (&self.z, origin.z, direction.z),
+        ] {
+            // an empty slab would come out as running from -inf to inf below
+            if slab.size() < 0.0 {
+                return false;
+            }
+            // a ray parallel to the slab is either always or never inside it
+            if direction == 0.0 {
+                if slab.contains(origin) {
+                    continue;
+                }
+                return false;
+            }
+
+            let inverse_direction = 1.0 / direction;
+            let t0 = (slab.min - origin) * inverse_direction;
+            let t1 = (slab.max - origin) * inverse_direction;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_utils::vector::Vec3;
+
+    fn unit_box() -> Aabb {
+        Aabb::from_points(Point::new(1.0, 1.0, 1.0), Point::new(0.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn test_from_points_orders_corners() {
+        let bbox = unit_box();
+        assert_eq!(bbox.x, Interval::new(0.0, 1.0));
+        assert_eq!(bbox.y, Interval::new(0.0, 1.0));
+        assert_eq!(bbox.z, Interval::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn test_surrounding() {
+        let other = Aabb::from_points(Point::new(2.0, -1.0, 0.5), Point::new(3.0, 0.5, 0.6));
+        let bbox = Aabb::surrounding(&unit_box(), &other);
+        assert_eq!(bbox.x, Interval::new(0.0, 3.0));
+        assert_eq!(bbox.y, Interval::new(-1.0, 1.0));
+        assert_eq!(bbox.z, Interval::new(0.0, 1.0));
+        assert_eq!(bbox.longest_axis(), 0);
+    }
+
+    #[test]
+    fn test_pad_to_minimums() {
+        let flat = Aabb::from_points(Point::new(0.0, 0.0, 1.0), Point::new(2.0, 3.0, 1.0));
+        let padded = flat.pad_to_minimums(0.5);
+        assert_eq!(padded.x, flat.x);
+        assert_eq!(padded.z, Interval::new(0.75, 1.25));
+    }
+
+    #[test]
+    fn test_surface_area() {
+        assert!((unit_box().surface_area() - 6.0).abs() < 1e-12);
+        let flat = Aabb::from_points(Point::new(0.0, 0.0, 0.0), Point::new(2.0, 3.0, 0.0));
+        assert!((flat.surface_area() - 12.0).abs() < 1e-12);
+        assert!(Aabb::empty().surface_area().abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_hit() {
+        let ray = Ray::new(Point::new(0.5, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(unit_box().hit(&ray, Interval::new(0.0, f64::INFINITY)));
+        // box is behind the allowed range of the ray
+        assert!(!unit_box().hit(&ray, Interval::new(0.0, 4.0)));
+    }
+
+    #[test]
+    fn test_empty_box_never_hits() {
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(1.0, 2.0, -3.0));
+        assert!(!Aabb::empty().hit(&ray, Interval::universe()));
+        let axis_parallel = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!Aabb::empty().hit(&axis_parallel, Interval::universe()));
+    }
+
+    #[test]
+    fn test_parallel_ray_on_face_hits() {
+        let ray = Ray::new(Point::new(1.0, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(unit_box().hit(&ray, Interval::universe()));
+    }
+
+    #[test]
+    fn test_miss_parallel_to_slab() {
+        let ray = Ray::new(Point::new(2.0, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!unit_box().hit(&ray, Interval::universe()));
+    }
+}
@@ -2,6 +2,7 @@
 
 use core::f64;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -20,6 +21,24 @@ impl Interval {
         Interval::new(f64::INFINITY, -f64::INFINITY)
     }
 
+    /// Smallest interval that contains both `a` and `b`
+    #[must_use]
+    pub const fn enclosing(a: &Self, b: &Self) -> Self {
+        Self::new(a.min.min(b.min), a.max.max(b.max))
+    }
+
+    #[must_use]
+    pub const fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    /// Grow the interval by `delta` in total, split evenly on both ends
+    #[must_use]
+    pub const fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.0;
+        Self::new(self.min - padding, self.max + padding)
+    }
+
     /// Checks if value is in [min, max] (aka inclusive)
     pub const fn contains(&self, val: f64) -> bool {
         self.min <= val && val <= self.max
@@ -55,6 +74,22 @@ mod tests {
         assert!(!i1.surrounds(0.1));
     }
 
+    #[test]
+    fn test_enclosing() {
+        let a = Interval::new(0.0, 1.0);
+        let b = Interval::new(2.0, 3.0);
+        let c = Interval::enclosing(&a, &b);
+        assert_eq!(c, Interval::new(0.0, 3.0));
+        assert_eq!(Interval::enclosing(&Interval::empty(), &a), a);
+    }
+
+    #[test]
+    fn test_expand() {
+        let i = Interval::new(1.0, 2.0).expand(1.0);
+        assert_eq!(i, Interval::new(0.5, 2.5));
+        assert!((i.size() - 2.0).abs() < 1e-12);
+    }
+
     #[test]
     fn test_empty() {
         let empty_int = Interval::empty();
@@ -1,6 +1,11 @@
+pub mod aabb;
 pub mod interval;
+pub mod onb;
+pub mod pdf;
 pub mod point;
+pub mod random;
 pub mod ray;
+pub mod samplers;
 pub mod vector;
 
 
@@ -0,0 +1,65 @@
+//! Orthonormal basis, for working in a frame built around a surface normal
+
+use super::vector::Vec3;
+
+/// Three perpendicular unit vectors, with `w` along the direction it was built from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Onb {
+    pub u: Vec3<f64>,
+    pub v: Vec3<f64>,
+    pub w: Vec3<f64>,
+}
+
+impl Onb {
+    /// Basis with `w` pointing along `normal`, which must not be zero
+    #[must_use]
+    pub fn new(normal: &Vec3<f64>) -> Self {
+        let w = normal
+            .normalize()
+            .unwrap_or_else(|_| Vec3::new(0.0, 0.0, 1.0));
+        // any axis not too close to w will do for building the other two
+        let helper = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w
+            .cross(&helper)
+            .normalize()
+            .unwrap_or_else(|_| Vec3::zero());
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    /// Convert coordinates in this basis to world space
+    #[must_use]
+    pub fn transform(&self, local: &Vec3<f64>) -> Vec3<f64> {
+        local.x * self.u + local.y * self.v + local.z * self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basis_is_orthonormal() {
+        for normal in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-2.0, 3.0, 0.5),
+        ] {
+            let basis = Onb::new(&normal);
+            for axis in [basis.u, basis.v, basis.w] {
+                assert!((axis.magnitude() - 1.0).abs() < 1e-12);
+            }
+            assert!(basis.u.dot(&basis.v).abs() < 1e-12);
+            assert!(basis.v.dot(&basis.w).abs() < 1e-12);
+            assert!(basis.w.dot(&basis.u).abs() < 1e-12);
+            assert!((basis.w.dot(&normal) - normal.magnitude()).abs() < 1e-12);
+
+            let up = basis.transform(&Vec3::new(0.0, 0.0, 2.0));
+            assert!((up - 2.0 * basis.w).near_zero());
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Probability densities over directions, for importance sampling
+//!
+//! A path tracer can send a bounce in any direction it likes, as long as it divides
+//! what comes back by how likely that direction was. Picking directions that carry
+//! more light, eg. close to the normal of a matte surface, cuts the noise for the
+//! same number of samples.
+
+use std::f64::consts::PI;
+
+use super::{onb::Onb, random::Rng, vector::Vec3};
+
+pub trait Pdf {
+    /// Density of picking `direction`, per unit solid angle
+    fn value(&self, direction: &Vec3<f64>) -> f64;
+
+    /// A random direction picked with this density, not necessarily of unit length
+    fn generate(&self, rng: &mut Rng<'_>) -> Vec3<f64>;
+}
+
+/// Every direction equally likely
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3<f64>) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, rng: &mut Rng<'_>) -> Vec3<f64> {
+        Vec3::random_unit_vector(rng)
+    }
+}
+
+/// Directions in the hemisphere around a normal, more likely the closer they are to
+/// it, which matches how a Lambertian surface scatters
+pub struct CosinePdf {
+    basis: Onb,
+}
+
+impl CosinePdf {
+    #[must_use]
+    pub fn new(normal: &Vec3<f64>) -> Self {
+        Self {
+            basis: Onb::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3<f64>) -> f64 {
+        let Ok(direction) = direction.normalize() else {
+            return 0.0;
+        };
+        (direction.dot(&self.basis.w) / PI).max(0.0)
+    }
+
+    fn generate(&self, rng: &mut Rng<'_>) -> Vec3<f64> {
+        self.basis.transform(&random_cosine_direction(rng))
+    }
+}
+
+/// Picks from one of two densities, the first with probability `weight`
+pub struct MixturePdf<'a> {
+    first: &'a dyn Pdf,
+    second: &'a dyn Pdf,
+    weight: f64,
+}
+
+impl<'a> MixturePdf<'a> {
+    /// # Panics
+    /// If `weight` isn't in [0, 1]
+    #[must_use]
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf, weight: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&weight),
+            "mixture weight must be in [0, 1]"
+        );
+        Self {
+            first,
+            second,
+            weight,
+        }
+    }
+
+    /// Even odds for both
+    #[must_use]
+    pub fn half(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        Self::new(first, second, 0.5)
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3<f64>) -> f64 {
+        self.weight.mul_add(
+            self.first.value(direction),
+            (1.0 - self.weight) * self.second.value(direction),
+        )
+    }
+
+    fn generate(&self, rng: &mut Rng<'_>) -> Vec3<f64> {
+        if rng.get_1d() < self.weight {
+            self.first.generate(rng)
+        } else {
+            self.second.generate(rng)
+        }
+    }
+}
+
+/// Unit vector around +z with density `cos(theta) / pi`
+fn random_cosine_direction(rng: &mut Rng<'_>) -> Vec3<f64> {
+    let [u, radius_squared] = rng.get_2d();
+    let phi = 2.0 * PI * u;
+    let radius = radius_squared.sqrt();
+    Vec3::new(
+        radius * phi.cos(),
+        radius * phi.sin(),
+        (1.0 - radius_squared).sqrt(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Monte Carlo estimate of the integral of `pdf` over the sphere, should be 1
+    fn integrate(pdf: &dyn Pdf, rng: &mut Rng<'_>) -> f64 {
+        const SAMPLES: u32 = 200_000;
+        let sphere = SpherePdf;
+        let total: f64 = (0..SAMPLES)
+            .map(|_| {
+                let direction = sphere.generate(rng);
+                pdf.value(&direction) / sphere.value(&direction)
+            })
+            .sum();
+        total / f64::from(SAMPLES)
+    }
+
+    #[test]
+    fn test_densities_integrate_to_one() {
+        let mut rng = Rng::new(1);
+        let cosine = CosinePdf::new(&Vec3::new(0.0, 2.0, 0.0));
+        for pdf in [
+            &SpherePdf as &dyn Pdf,
+            &cosine,
+            &MixturePdf::new(&SpherePdf, &cosine, 0.3),
+        ] {
+            let integral = integrate(pdf, &mut rng);
+            assert!((integral - 1.0).abs() < 0.02, "{integral}");
+        }
+    }
+
+    #[test]
+    fn test_cosine_stays_in_hemisphere() {
+        let mut rng = Rng::new(2);
+        let normal = Vec3::new(1.0, -1.0, 0.5);
+        let pdf = CosinePdf::new(&normal);
+        for _ in 0..1000 {
+            let direction = pdf.generate(&mut rng);
+            assert!(direction.dot(&normal) >= 0.0);
+            assert!((direction.magnitude() - 1.0).abs() < 1e-9);
+            assert!(pdf.value(&direction) > 0.0);
+        }
+        assert!(pdf.value(&-normal).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_mixture_value() {
+        let cosine = CosinePdf::new(&Vec3::new(0.0, 0.0, 1.0));
+        let mixture = MixturePdf::half(&SpherePdf, &cosine);
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let expected = 0.5 * (1.0 / (4.0 * PI) + 1.0 / PI);
+        assert!((mixture.value(&up) - expected).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,250 @@
+//! Seedable random numbers, handed to everything that samples
+//!
+//! Nothing draws from a shared generator. The camera gives every sample of every
+//! pixel its own `Rng`, seeded from the render's seed, the pixel and the index of the
+//! sample, and passes it down to the materials, lights and shapes the path meets. A
+//! seeded render then comes out the same however many threads trace it.
+//!
+//! The choices that matter most for noise, like where in the pixel a sample lands or
+//! which light it aims at, take their numbers from the render's `Sampler` through
+//! `get_1d` and `get_2d` instead, so they are spread evenly over the pixel's samples.
+//! Those numbers are laid out by dimension the way pbrt does it: the camera takes the
+//! first `CAMERA_DIMENSIONS`, then every bounce gets `BounceStage::DIMENSIONS` more,
+//! split between its stages. A choice at one bounce then always reads the same
+//! dimension, whatever the bounces before it did.
+
+use super::samplers::Sampler;
+use rand::{
+    Rng as _, SeedableRng,
+    distr::{Distribution, StandardUniform, uniform::SampleRange, uniform::SampleUniform},
+    rngs::SmallRng,
+};
+
+/// Random numbers for one camera sample, or anything else that needs a repeatable
+/// sequence
+pub struct Rng<'a> {
+    generator: SmallRng,
+    sampler: Option<&'a dyn Sampler>,
+    pixel: u64,
+    index: u64,
+    /// The next dimension of the sample `get_1d` or `get_2d` hands out
+    dimension: u32,
+}
+
+/// Dimensions the camera takes for every sample: the position in the pixel, the
+/// position on the lens and the time
+pub const CAMERA_DIMENSIONS: u32 = 5;
+
+/// The parts of a bounce that draw from the sampler, each with its own share of the
+/// bounce's dimensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BounceStage {
+    /// Picking a light and a direction towards it, three dimensions
+    Light,
+    /// Sampling the material, three dimensions
+    Scatter,
+    /// Russian roulette, one dimension
+    Roulette,
+}
+
+impl BounceStage {
+    /// Dimensions every bounce gets
+    pub const DIMENSIONS: u32 = 7;
+
+    /// Where the stage's share starts within its bounce
+    const fn offset(self) -> u32 {
+        match self {
+            Self::Light => 0,
+            Self::Scatter => 3,
+            Self::Roulette => 6,
+        }
+    }
+}
+
+impl Rng<'_> {
+    /// A sequence fixed by `seed`, without a sampler to spread `get_1d` and `get_2d`
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            generator: SmallRng::seed_from_u64(seed),
+            sampler: None,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// A random value in [0, 1), from the next dimension of the sample
+    pub fn get_1d(&mut self) -> f64 {
+        match self.sampler {
+            Some(sampler) => {
+                let value = sampler.get_1d(self.pixel, self.index, self.dimension);
+                self.dimension = self.dimension.saturating_add(1);
+                value
+            }
+            None => self.generator.random(),
+        }
+    }
+
+    /// A random point in the unit square, from the next two dimensions of the sample
+    pub fn get_2d(&mut self) -> [f64; 2] {
+        match self.sampler {
+            Some(sampler) => {
+                let value = sampler.get_2d(self.pixel, self.index, self.dimension);
+                self.dimension = self.dimension.saturating_add(2);
+                value
+            }
+            None => [self.generator.random(), self.generator.random()],
+        }
+    }
+
+    /// Move `get_1d` and `get_2d` on to the dimensions of `stage` at bounce number
+    /// `bounce`, counting from 0 for the first surface the camera's ray hits
+    pub fn start_stage(&mut self, bounce: usize, stage: BounceStage) {
+        // past some 600 million bounces every stage shares the last dimension
+        self.dimension = u32::try_from(bounce)
+            .ok()
+            .and_then(|bounce| bounce.checked_mul(BounceStage::DIMENSIONS))
+            .and_then(|start| start.checked_add(CAMERA_DIMENSIONS + stage.offset()))
+            .unwrap_or(u32::MAX);
+    }
+
+    /// A random value, in [0, 1) for floats
+    #[must_use]
+    pub fn random<T>(&mut self) -> T
+    where
+        StandardUniform: Distribution<T>,
+    {
+        self.generator.random()
+    }
+
+    /// A random value in `range`
+    ///
+    /// # Panics
+    /// If `range` is empty
+    #[must_use]
+    pub fn random_range<T, R>(&mut self, range: R) -> T
+    where
+        T: SampleUniform,
+        R: SampleRange<T>,
+    {
+        self.generator.random_range(range)
+    }
+}
+
+impl<'a> Rng<'a> {
+    /// The sequence for sample number `sample` of pixel number `pixel`, unrelated to
+    /// the sequence of any other sample for the same `seed`, with `get_1d` and
+    /// `get_2d` taken from `sampler`
+    #[must_use]
+    pub fn for_sample(sampler: &'a dyn Sampler, seed: u64, pixel: u64, sample: u64) -> Self {
+        Self {
+            sampler: Some(sampler),
+            pixel,
+            index: sample,
+            ..Rng::new(mix(mix(mix(seed) ^ pixel) ^ sample))
+        }
+    }
+}
+
+/// The `SplitMix64` finalizer, which spreads consecutive numbers all over the range so
+/// neighbouring pixels and samples don't get similar seeds
+#[must_use]
+pub const fn mix(value: u64) -> u64 {
+    let value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_utils::samplers::SamplerKind;
+
+    fn sequence(rng: &mut Rng<'_>) -> Vec<f64> {
+        (0..8).map(|_| rng.random()).collect()
+    }
+
+    fn sample(seed: u64, pixel: u64, sample: u64) -> Vec<f64> {
+        let sampler = SamplerKind::Independent.build(seed, 16);
+        sequence(&mut Rng::for_sample(sampler.as_ref(), seed, pixel, sample))
+    }
+
+    #[test]
+    fn test_same_seed_repeats_sequence() {
+        let first = sequence(&mut Rng::new(42));
+        assert_eq!(first, sequence(&mut Rng::new(42)));
+        assert_eq!(sample(42, 3, 1), sample(42, 3, 1));
+    }
+
+    #[test]
+    fn test_samples_differ() {
+        let first = sample(42, 3, 1);
+        for (seed, pixel, index) in [(43, 3, 1), (42, 4, 1), (42, 3, 2), (42, 1, 3)] {
+            assert_ne!(
+                first,
+                sample(seed, pixel, index),
+                "{seed}, {pixel}, {index}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_dimensions_come_from_the_sampler() {
+        let sampler = SamplerKind::Sobol.build(1, 4);
+        let mut rng = Rng::for_sample(sampler.as_ref(), 1, 5, 2);
+        let [x, y] = rng.get_2d();
+        let time = rng.get_1d();
+        let [expected_x, expected_y] = sampler.get_2d(5, 2, 0);
+        assert!((x - expected_x).abs() < f64::EPSILON && (y - expected_y).abs() < f64::EPSILON);
+        assert!((time - sampler.get_1d(5, 2, 2)).abs() < f64::EPSILON);
+        // and without one they are plain random numbers
+        let mut rng = Rng::new(3);
+        assert!((0.0..1.0).contains(&rng.get_1d()));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // values are in [0, 1)
+    fn test_bounce_stages_stay_stratified() {
+        let sample_count = 16;
+        let sampler = SamplerKind::Sobol.build(4, sample_count);
+        let mut cells: Vec<usize> = (0..sample_count)
+            .map(|index| {
+                let mut rng = Rng::for_sample(sampler.as_ref(), 4, 9, index);
+                // earlier bounces take more or fewer numbers depending on what they hit
+                for bounce in 0..2 {
+                    rng.start_stage(bounce, BounceStage::Scatter);
+                    for _ in 0..index % 3 {
+                        rng.get_1d();
+                    }
+                }
+                rng.start_stage(2, BounceStage::Roulette);
+                let value = rng.get_1d();
+                assert!(
+                    (value
+                        - sampler.get_1d(
+                            9,
+                            index,
+                            CAMERA_DIMENSIONS + 2 * BounceStage::DIMENSIONS + 6
+                        ))
+                    .abs()
+                        < f64::EPSILON
+                );
+                (value * 16.0) as usize
+            })
+            .collect();
+        // one value in every sixteenth of [0, 1)
+        cells.sort_unstable();
+        assert_eq!(cells, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..100 {
+            let value: f64 = rng.random_range(-0.5..0.5);
+            assert!((-0.5..0.5).contains(&value));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! The Halton sequence, scrambled for every pixel
+
+use super::{ONE_MINUS_EPSILON, Sampler, hash, independent::IndependentSampler};
+use crate::math_utils::random::mix;
+
+/// Bases of the dimensions covered, later ones get ever worse distributed and fall
+/// back to independent random numbers
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Dimension `d` of sample `i` mirrors the digits of `i` in the `d`th prime around the
+/// decimal point, so consecutive samples keep filling the biggest gaps left
+///
+/// The digits are shuffled with an Owen scramble seeded per pixel and dimension,
+/// which keeps the even spread but hides the patterns the plain sequence makes
+/// between neighbouring pixels and dimensions.
+pub struct HaltonSampler {
+    seed: u64,
+    fallback: IndependentSampler,
+}
+
+impl HaltonSampler {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            fallback: IndependentSampler::new(seed),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn get_1d(&self, pixel: u64, index: u64, dimension: u32) -> f64 {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => scrambled_radical_inverse(
+                base,
+                index,
+                hash(&[self.seed, pixel, u64::from(dimension)]),
+            ),
+            None => self.fallback.get_1d(pixel, index, dimension),
+        }
+    }
+}
+
+/// `index` mirrored around the point in `base`, with every digit permuted depending on
+/// the digits before it, continuing past the last digit of `index` so even the
+/// zeros beyond it are scrambled (pbrt-v4's `OwenScrambledRadicalInverse`)
+#[allow(clippy::cast_precision_loss)] // the digits are cut off at 53 bits
+fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let limit = u64::MAX / base - base;
+    let mut reversed_digits = 0;
+    let mut scale = 1.0;
+    while 1.0 - scale < 1.0 && reversed_digits < limit {
+        let digit = index % base;
+        index /= base;
+        let digit_seed = mix(seed ^ reversed_digits);
+        let digit = permutation_element(digit, base, digit_seed);
+        reversed_digits = reversed_digits * base + digit;
+        scale *= inverse_base;
+    }
+    (scale * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
+
+/// `permutation_element` for digits, which always fit
+#[allow(clippy::cast_possible_truncation)] // digits and bases are below 2^32
+fn permutation_element(digit: u64, base: u64, seed: u64) -> u64 {
+    u64::from(super::permutation_element(digit as u32, base as u32, seed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )] // tiny numbers
+    fn test_digits_are_mirrored() {
+        // with the digits left alone, 6 = 110 in base 2 becomes 0.011
+        let unscrambled = |base: u64, mut index: u64| {
+            let mut value = 0.0;
+            let mut scale = 1.0;
+            while index > 0 {
+                scale /= base as f64;
+                value += (index % base) as f64 * scale;
+                index /= base;
+            }
+            value
+        };
+        assert!((unscrambled(2, 6) - 0.375).abs() < 1e-12);
+
+        // scrambling permutes the same strata, so the first base^k samples still
+        // land in different intervals of width 1 / base^k
+        for base in [2, 3, 5] {
+            let count = base * base;
+            let mut strata: Vec<u64> = (0..count)
+                .map(|index| {
+                    let value = scrambled_radical_inverse(base, index, 11);
+                    (value * count as f64) as u64
+                })
+                .collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..count).collect::<Vec<_>>(), "{base}");
+        }
+    }
+}
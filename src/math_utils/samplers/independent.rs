@@ -0,0 +1,22 @@
+//! Uniform random numbers with nothing to spread them out
+
+use super::{Sampler, hash, to_unit};
+
+/// Every value independent of every other, the baseline the other samplers improve
+/// on, and the fallback for dimensions they don't cover
+pub struct IndependentSampler {
+    seed: u64,
+}
+
+impl IndependentSampler {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn get_1d(&self, pixel: u64, index: u64, dimension: u32) -> f64 {
+        to_unit(hash(&[self.seed, pixel, index, u64::from(dimension)]))
+    }
+}
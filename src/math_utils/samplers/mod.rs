@@ -0,0 +1,246 @@
+//! Sample points that cover the pixel and every random choice along a path evenly
+//!
+//! Independent random numbers clump and leave gaps, so the noise in a pixel only
+//! falls with the square root of the samples taken. A `Sampler` hands out the random
+//! numbers of a path by dimension instead: the pixel position, lens position, time and
+//! the choices at each bounce each get their own dimensions, and the values of one
+//! dimension are spread evenly over the samples of a pixel.
+//!
+//! Samplers don't keep any state, every value is worked out from the pixel, the
+//! sample's index in it and the dimension, so samples can be taken in any order on
+//! any thread. `Rng` keeps track of the next dimension for each sample, and moves it
+//! to the fixed dimensions of each bounce as the path goes on.
+//!
+//! [`Rng`]: super::random::Rng
+
+use std::{fmt, str::FromStr};
+
+use super::random::mix;
+
+pub mod halton;
+pub mod independent;
+pub mod sobol;
+pub mod stratified;
+
+/// Largest `f64` below 1, so scaled integers never round up to 1
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+pub trait Sampler: Sync {
+    /// Dimension `dimension` of sample number `index` in pixel number `pixel`, in
+    /// [0, 1)
+    fn get_1d(&self, pixel: u64, index: u64, dimension: u32) -> f64;
+
+    /// Dimensions `dimension` and `dimension + 1` of a sample, spread over the unit
+    /// square together rather than each on its own
+    fn get_2d(&self, pixel: u64, index: u64, dimension: u32) -> [f64; 2] {
+        [
+            self.get_1d(pixel, index, dimension),
+            self.get_1d(pixel, index, dimension + 1),
+        ]
+    }
+}
+
+/// Samplers that can be picked by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplerKind {
+    /// Plain random numbers, see `independent::IndependentSampler`
+    Independent,
+    Stratified,
+    Halton,
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Names accepted by `from_str`
+    pub const NAMES: [&str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+    /// A sampler whose values are fixed by `seed`, for pixels that get
+    /// `samples_per_pixel` samples
+    #[must_use]
+    pub fn build(self, seed: u64, samples_per_pixel: u64) -> Box<dyn Sampler> {
+        match self {
+            Self::Independent => Box::new(independent::IndependentSampler::new(seed)),
+            Self::Stratified => {
+                Box::new(stratified::StratifiedSampler::new(seed, samples_per_pixel))
+            }
+            Self::Halton => Box::new(halton::HaltonSampler::new(seed)),
+            Self::Sobol => Box::new(sobol::SobolSampler::new(seed, samples_per_pixel)),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = match self {
+            Self::Independent => 0,
+            Self::Stratified => 1,
+            Self::Halton => 2,
+            Self::Sobol => 3,
+        };
+        f.write_str(Self::NAMES[index])
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "independent" => Ok(Self::Independent),
+            "stratified" => Ok(Self::Stratified),
+            "halton" => Ok(Self::Halton),
+            "sobol" => Ok(Self::Sobol),
+            _ => Err(format!(
+                "unknown sampler {name:?}, expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// One well mixed number from several, eg. a seed, pixel and dimension
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |hash, &value| mix(hash ^ value))
+}
+
+/// The top 53 bits of `bits` as a number in [0, 1)
+#[allow(clippy::cast_precision_loss)] // 53 bits fit a f64 exactly
+fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 * (1.0 / (1_u64 << 53) as f64)
+}
+
+/// Where `index` ends up in a random permutation of `0..length` picked by `seed`,
+/// without building the permutation (Kensler, "Correlated Multi-Jittered Sampling",
+/// 2013)
+#[allow(clippy::cast_possible_truncation)] // seeds only need their low bits
+const fn permutation_element(index: u32, length: u32, seed: u64) -> u32 {
+    let seed = seed as u32;
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // permute the next power of two up and skip whatever lands past the end
+    let mut element = index;
+    loop {
+        element ^= seed;
+        element = element.wrapping_mul(0xe170_893d);
+        element ^= seed >> 16;
+        element ^= (element & mask) >> 4;
+        element ^= seed >> 8;
+        element = element.wrapping_mul(0x0929_eb3f);
+        element ^= seed >> 23;
+        element ^= (element & mask) >> 1;
+        element = element.wrapping_mul(1 | seed >> 27);
+        element = element.wrapping_mul(0x6935_fa69);
+        element ^= (element & mask) >> 11;
+        element = element.wrapping_mul(0x74dc_b303);
+        element ^= (element & mask) >> 2;
+        element = element.wrapping_mul(0x9e50_1cc3);
+        element ^= (element & mask) >> 2;
+        element = element.wrapping_mul(0xc860_a3df);
+        element &= mask;
+        element ^= element >> 5;
+        if element < length {
+            break;
+        }
+    }
+    (element.wrapping_add(seed)) % length
+}
+
+/// Sample counts are far below 2^32, but adaptive sampling can go past the count a
+/// pixel was planned for, so indices beyond it start a new round
+#[allow(clippy::cast_possible_truncation)] // see above
+const fn split_index(index: u64, samples_per_pixel: u64) -> (u64, u32) {
+    (
+        index / samples_per_pixel,
+        (index % samples_per_pixel) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for name in SamplerKind::NAMES {
+            assert_eq!(name.parse::<SamplerKind>().unwrap().to_string(), name);
+        }
+        assert!("random".parse::<SamplerKind>().is_err());
+    }
+
+    #[test]
+    fn test_permutation() {
+        for length in [1, 5, 16, 100] {
+            let mut elements: Vec<u32> = (0..length)
+                .map(|index| permutation_element(index, length, 42))
+                .collect();
+            let shuffled = elements.clone();
+            elements.sort_unstable();
+            assert_eq!(elements, (0..length).collect::<Vec<_>>());
+            if length > 4 {
+                assert_ne!(shuffled, elements, "{length}");
+            }
+        }
+    }
+
+    /// Mean squared error of estimating the integral of a disk of radius 0.5 over the
+    /// unit square, over many pixels
+    fn disk_error(kind: SamplerKind, count: u64) -> f64 {
+        let sampler = kind.build(7, count);
+        let exact = std::f64::consts::PI / 4.0;
+        let pixels = 200;
+        let total: f64 = (0..pixels)
+            .map(|pixel| {
+                let inside = (0..count)
+                    .filter(|&index| {
+                        let [x, y] = sampler.get_2d(pixel, index, 0);
+                        (x - 0.5).hypot(y - 0.5) < 0.5
+                    })
+                    .count();
+                #[allow(clippy::cast_precision_loss)] // tiny counts
+                let estimate = inside as f64 / count as f64;
+                (estimate - exact).powi(2)
+            })
+            .sum();
+        total / 200.0
+    }
+
+    #[test]
+    fn test_even_samplers_beat_random() {
+        let random = disk_error(SamplerKind::Independent, 64);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let error = disk_error(kind, 64);
+            assert!(error < random / 2.0, "{kind}: {error} vs {random}");
+        }
+    }
+
+    #[test]
+    fn test_values_stay_in_range() {
+        for kind in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let sampler = kind.build(3, 16);
+            // past the planned count and the dimensions some samplers cover
+            for index in [0, 1, 15, 16, 40] {
+                for dimension in [0, 1, 2, 7, 100, 1000] {
+                    let value = sampler.get_1d(9, index, dimension);
+                    assert!((0.0..1.0).contains(&value), "{kind} {index} {dimension}");
+                    for value in sampler.get_2d(9, index, dimension) {
+                        assert!((0.0..1.0).contains(&value), "{kind} {index} {dimension}");
+                    }
+                }
+            }
+        }
+    }
+}
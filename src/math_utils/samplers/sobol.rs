@@ -0,0 +1,146 @@
+//! The Sobol sequence, Owen scrambled for every pixel
+
+use super::{Sampler, hash, permutation_element, split_index};
+use crate::math_utils::random::mix;
+
+/// The first two dimensions of the Sobol sequence, padded to as many dimensions as
+/// needed (pbrt-v4's `PaddedSobolSampler`)
+///
+/// Every power of two of consecutive points in those two dimensions forms a (0, 2)-net:
+/// however the unit square is cut into that many equal rectangles, each one holds a
+/// point. Every dimension or pair of dimensions hands the pixel's samples these points
+/// in its own shuffled order, and scrambles the points with its own Owen scramble,
+/// which keeps the net but breaks up the structure between pixels and dimensions.
+pub struct SobolSampler {
+    seed: u64,
+    samples_per_pixel: u64,
+}
+
+impl SobolSampler {
+    #[must_use]
+    pub fn new(seed: u64, samples_per_pixel: u64) -> Self {
+        Self {
+            seed,
+            samples_per_pixel: samples_per_pixel.max(1),
+        }
+    }
+
+    /// Which point of the sequence sample `index` takes, with the scramble seed for
+    /// its dimensions
+    fn point(&self, pixel: u64, index: u64, dimension: u32) -> (u32, u64) {
+        let (round, index) = split_index(index, self.samples_per_pixel);
+        let seed = hash(&[self.seed, pixel, u64::from(dimension), round]);
+        #[allow(clippy::cast_possible_truncation)] // sample counts are far below 2^32
+        let point = permutation_element(index, self.samples_per_pixel as u32, seed);
+        (point, seed)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn get_1d(&self, pixel: u64, index: u64, dimension: u32) -> f64 {
+        let (point, seed) = self.point(pixel, index, dimension);
+        to_unit(owen_scramble(point.reverse_bits(), mix(seed)))
+    }
+
+    fn get_2d(&self, pixel: u64, index: u64, dimension: u32) -> [f64; 2] {
+        let (point, seed) = self.point(pixel, index, dimension);
+        [
+            to_unit(owen_scramble(point.reverse_bits(), mix(seed))),
+            to_unit(owen_scramble(second_dimension(point), mix(seed ^ 1))),
+        ]
+    }
+}
+
+/// Dimension 1 of the Sobol sequence, whose generator matrix is Pascal's triangle
+/// modulo 2, as the bits of a fraction
+const fn second_dimension(mut point: u32) -> u32 {
+    let mut value = 0;
+    let mut direction = 1 << 31;
+    while point != 0 {
+        if point & 1 != 0 {
+            value ^= direction;
+        }
+        point >>= 1;
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+/// Flips each bit of the fraction `value` depending on the bits above it, which
+/// shuffles the halves of [0, 1), then the halves of each half, and so on
+const fn owen_scramble(value: u32, seed: u64) -> u32 {
+    let mut scrambled = value;
+    if seed & 1 != 0 {
+        scrambled ^= 1 << 31;
+    }
+    let mut bit = 1;
+    while bit < 32 {
+        let above = value & (u32::MAX << (32 - bit));
+        if mix(above as u64 ^ seed) & (1 << bit) != 0 {
+            scrambled ^= 1 << (31 - bit);
+        }
+        bit += 1;
+    }
+    scrambled
+}
+
+/// The bits of a fraction as a number in [0, 1)
+fn to_unit(value: u32) -> f64 {
+    f64::from(value) / 2.0_f64.powi(32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether every `columns` by `rows` grid over the unit square has one of the
+    /// points in each cell, for `columns * rows` equal to their number
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )] // tiny numbers
+    fn is_net(points: &[[f64; 2]]) -> bool {
+        let count = points.len();
+        (0..=count.trailing_zeros()).all(|log| {
+            let columns = 1 << log;
+            let rows = count / columns;
+            let mut cells: Vec<usize> = points
+                .iter()
+                .map(|[x, y]| {
+                    let column = (x * columns as f64) as usize;
+                    let row = (y * rows as f64) as usize;
+                    row * columns + column
+                })
+                .collect();
+            cells.sort_unstable();
+            cells == (0..count).collect::<Vec<_>>()
+        })
+    }
+
+    #[test]
+    fn test_points_form_nets() {
+        let unscrambled: Vec<[f64; 2]> = (0..16_u32)
+            .map(|point| {
+                [
+                    to_unit(point.reverse_bits()),
+                    to_unit(second_dimension(point)),
+                ]
+            })
+            .collect();
+        let close =
+            |[x, y]: [f64; 2], [a, b]: [f64; 2]| (x - a).abs() < 1e-12 && (y - b).abs() < 1e-12;
+        assert!(close(unscrambled[1], [0.5, 0.5]));
+        assert!(close(unscrambled[2], [0.25, 0.75]));
+        assert!(is_net(&unscrambled));
+
+        let sampler = SobolSampler::new(5, 16);
+        for pixel in 0..10 {
+            let points: Vec<[f64; 2]> = (0..16)
+                .map(|index| sampler.get_2d(pixel, index, 3))
+                .collect();
+            assert!(is_net(&points), "{pixel}");
+            assert_ne!(points, unscrambled);
+        }
+    }
+}
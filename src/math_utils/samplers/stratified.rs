@@ -0,0 +1,56 @@
+//! Jittered grids
+
+use super::{Sampler, hash, permutation_element, split_index, to_unit};
+use crate::math_utils::random::mix;
+
+/// Splits each dimension into as many strata as the pixel gets samples, and each pair
+/// of dimensions into a grid of about as many cells, with one sample at a random spot
+/// in each
+///
+/// Which sample gets which stratum is shuffled differently for every pixel and
+/// dimension, so the dimensions of a sample don't line up with each other.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u64,
+}
+
+impl StratifiedSampler {
+    #[must_use]
+    pub fn new(seed: u64, samples_per_pixel: u64) -> Self {
+        Self {
+            seed,
+            samples_per_pixel: samples_per_pixel.max(1),
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    #[allow(clippy::cast_possible_truncation)] // sample counts are far below 2^32
+    fn get_1d(&self, pixel: u64, index: u64, dimension: u32) -> f64 {
+        let (round, index) = split_index(index, self.samples_per_pixel);
+        let key = [self.seed, pixel, u64::from(dimension), round];
+        let stratum = permutation_element(index, self.samples_per_pixel as u32, hash(&key));
+        let jitter = to_unit(hash(&[hash(&key), u64::from(index)]));
+        (f64::from(stratum) + jitter) / f64::from(self.samples_per_pixel as u32)
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )] // sample counts are far below 2^32
+    fn get_2d(&self, pixel: u64, index: u64, dimension: u32) -> [f64; 2] {
+        let (round, index) = split_index(index, self.samples_per_pixel);
+        // a grid at least as big as the sample count, with the spare cells left out
+        // at random
+        let columns = (self.samples_per_pixel as f64).sqrt() as u32;
+        let rows = (self.samples_per_pixel as u32).div_ceil(columns);
+        let key = [self.seed, pixel, u64::from(dimension), round];
+        let cell = permutation_element(index, columns * rows, hash(&key));
+        let jitter = hash(&[hash(&key), u64::from(index)]);
+        [
+            (f64::from(cell % columns) + to_unit(jitter)) / f64::from(columns),
+            (f64::from(cell / columns) + to_unit(mix(jitter))) / f64::from(rows),
+        ]
+    }
+}
@@ -2,6 +2,8 @@ use num_traits::{Float, Zero};
 use std::cmp::{PartialEq, PartialOrd};
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
+use super::random::Rng;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum VectorError {
     InvalidOperation,
@@ -125,14 +127,14 @@ impl<T: Float> Vec3<T> {
 }
 
 impl Vec3<f64> {
-    pub fn random() -> Vec3<f64> {
-        Vec3::new(rand::random(), rand::random(), rand::random())
+    pub fn random(rng: &mut Rng<'_>) -> Vec3<f64> {
+        Vec3::new(rng.random(), rng.random(), rng.random())
     }
 
-    pub fn random_in_unit_dist() -> Vec3<f64> {
+    pub fn random_in_unit_dist(rng: &mut Rng<'_>) -> Vec3<f64> {
         loop {
-            let x: f64 = rand::random(); // required for type inference
-            let y: f64 = rand::random(); // required for type inference
+            let x: f64 = rng.random(); // required for type inference
+            let y: f64 = rng.random(); // required for type inference
             let p = Vec3::<f64>::new(2.0 * x - 1.0, 2.0 * y - 1.0, 0.);
             if p.magnitude_squared() < 1.0 {
                 return p;
@@ -140,18 +142,18 @@ impl Vec3<f64> {
         }
     }
 
-    pub fn random_in_range(min: f64, max: f64) -> Vec3<f64> {
+    pub fn random_in_range(min: f64, max: f64, rng: &mut Rng<'_>) -> Vec3<f64> {
         Vec3::new(
-            rand::random_range(min..max),
-            rand::random_range(min..max),
-            rand::random_range(min..max),
+            rng.random_range(min..max),
+            rng.random_range(min..max),
+            rng.random_range(min..max),
         )
     }
 
-    pub fn random_unit_vector() -> Vec3<f64> {
+    pub fn random_unit_vector(rng: &mut Rng<'_>) -> Vec3<f64> {
         const FLOAT_EPSILON: f64 = 1e-160;
         loop {
-            let p = Vec3::random();
+            let p = Vec3::random_in_range(-1.0, 1.0, rng);
             let lensq = p.magnitude_squared();
             if lensq <= 1.0 && lensq > FLOAT_EPSILON {
                 return p / lensq.sqrt();
@@ -159,8 +161,11 @@ impl Vec3<f64> {
         }
     }
 
-    pub fn random_unit_vector_same_hemisphere(reference_vector: &Vec3<f64>) -> Vec3<f64> {
-        let rand_unit_vector = Vec3::random_unit_vector();
+    pub fn random_unit_vector_same_hemisphere(
+        reference_vector: &Vec3<f64>,
+        rng: &mut Rng<'_>,
+    ) -> Vec3<f64> {
+        let rand_unit_vector = Vec3::random_unit_vector(rng);
 
         if rand_unit_vector.dot(reference_vector) > 0.0 {
             rand_unit_vector
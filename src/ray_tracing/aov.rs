@@ -0,0 +1,194 @@
+//! Arbitrary output variables, images of what the camera rays hit first
+//!
+//! Next to the rendered image the camera can fill one framebuffer per `Aov`, for
+//! compositing or as guides for a denoiser. Values are stored as they are, so a
+//! normal keeps its negative components and a depth its distance, which only a
+//! `.pfm` file can hold.
+
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::{
+    image_utils::ImageFormat,
+    math_utils::ray::Ray,
+    ray_tracing::{background::Background, color::Color, shapes::hittable::HitRecord},
+};
+
+/// A property of the first surface hit, stored in the color channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Aov {
+    /// World space surface normal facing the camera, black where rays escape
+    Normal,
+    /// Reflectance of the material, or the background for rays that escape
+    Albedo,
+    /// Distance from the camera in every channel, 0 where rays escape
+    Depth,
+    /// World space hit point, black where rays escape
+    Position,
+    /// Index of the shape in the scene plus one in every channel, 0 where rays
+    /// escape
+    ObjectId,
+}
+
+impl Aov {
+    /// Names accepted by `from_str`
+    pub const NAMES: [&str; 5] = ["normal", "albedo", "depth", "position", "object-id"];
+
+    /// Value of one sample along `ray`, which hit `hit_record` first if anything
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)] // scenes have far fewer than 2^52 shapes
+    pub fn sample(
+        self,
+        ray: &Ray<f64>,
+        hit_record: Option<&HitRecord>,
+        background: &dyn Background,
+    ) -> Color {
+        let Some(hit_record) = hit_record else {
+            return match self {
+                Self::Albedo => background.color(ray).clamped(),
+                _ => Color::black(),
+            };
+        };
+        match self {
+            Self::Normal => Color {
+                red: hit_record.normal.x,
+                green: hit_record.normal.y,
+                blue: hit_record.normal.z,
+            },
+            Self::Albedo => hit_record.material.albedo(hit_record),
+            Self::Depth => gray(hit_record.t * ray.get_direction().magnitude()),
+            Self::Position => Color {
+                red: hit_record.point.x,
+                green: hit_record.point.y,
+                blue: hit_record.point.z,
+            },
+            Self::ObjectId => gray((hit_record.object_id + 1) as f64),
+        }
+    }
+
+    /// Whether samples can be averaged over a pixel, ids can't be blended into
+    /// something meaningful so the pixel keeps its first one
+    #[must_use]
+    pub const fn is_averaged(self) -> bool {
+        !matches!(self, Self::ObjectId)
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = match self {
+            Self::Normal => 0,
+            Self::Albedo => 1,
+            Self::Depth => 2,
+            Self::Position => 3,
+            Self::ObjectId => 4,
+        };
+        f.write_str(Self::NAMES[index])
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "normal" => Ok(Self::Normal),
+            "albedo" => Ok(Self::Albedo),
+            "depth" => Ok(Self::Depth),
+            "position" => Ok(Self::Position),
+            "object-id" => Ok(Self::ObjectId),
+            _ => Err(format!(
+                "unknown AOV {name:?}, expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Make sure an AOV can be saved to `path` without losing values
+///
+/// # Errors
+/// If the file isn't a `.pfm`
+pub fn check_file(path: &Path) -> Result<(), String> {
+    match ImageFormat::from_path(path) {
+        Ok(ImageFormat::Pfm) => Ok(()),
+        _ => Err(format!(
+            "can't save an AOV as {}, only .pfm files keep its values",
+            path.display()
+        )),
+    }
+}
+
+const fn gray(value: f64) -> Color {
+    Color {
+        red: value,
+        green: value,
+        blue: value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        math_utils::{interval::Interval, point::Point, vector::Vec3},
+        ray_tracing::{
+            background::SolidBackground,
+            materials::lambertian::LambertianMaterial,
+            shapes::{group::Group, hittable::Hittable, sphere::Sphere},
+        },
+    };
+
+    #[test]
+    fn test_first_hit_values() {
+        let red = Color::new(0.8, 0.1, 0.1).unwrap();
+        let mut world = Group::new(Vec::new());
+        for x in [-2.0, 2.0] {
+            world.add(Box::new(Sphere::new(
+                Point::new(x, 0.0, -5.0),
+                1.0,
+                Arc::new(LambertianMaterial::new(red)),
+            )));
+        }
+        let sky = Color::new(0.5, 0.7, 1.0).unwrap();
+        let background = SolidBackground::new(sky);
+
+        // twice as long a direction still measures depth in world units
+        let ray = Ray::new(Point::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0));
+        let hit_record = world.hit(&ray, Interval::new(0.01, f64::INFINITY));
+        let sample = |aov: Aov| aov.sample(&ray, hit_record.as_ref(), &background);
+        assert_eq!(
+            sample(Aov::Normal),
+            Color {
+                blue: 1.0,
+                ..Color::black()
+            }
+        );
+        assert_eq!(sample(Aov::Albedo), red);
+        assert!((sample(Aov::Depth).red - 4.0).abs() < 1e-12);
+        assert!((sample(Aov::Position).blue + 4.0).abs() < 1e-12);
+        assert_eq!(sample(Aov::ObjectId), gray(2.0));
+
+        assert_eq!(Aov::Albedo.sample(&ray, None, &background), sky);
+        for aov in [Aov::Normal, Aov::Depth, Aov::Position, Aov::ObjectId] {
+            assert_eq!(aov.sample(&ray, None, &background), Color::black());
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        for name in Aov::NAMES {
+            assert_eq!(name.parse::<Aov>().unwrap().to_string(), name);
+        }
+        assert!("motion".parse::<Aov>().is_err());
+    }
+
+    #[test]
+    fn test_only_float_files() {
+        assert!(check_file(Path::new("out/normal.pfm")).is_ok());
+        for path in ["normal.png", "normal.hdr", "normal"] {
+            assert!(check_file(Path::new(path)).is_err(), "{path}");
+        }
+    }
+}
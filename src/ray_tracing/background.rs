@@ -0,0 +1,190 @@
+//! What a ray sees when it leaves the scene without hitting anything
+//!
+//! The background is the only light source in scenes without emissive materials,
+//! so it doubles as the environment lighting.
+
+use std::path::Path;
+
+use crate::{
+    image_utils::{ImageError, framebuffer::Framebuffer, read_image},
+    math_utils::ray::Ray,
+    ray_tracing::color::Color,
+};
+
+pub trait Background: Sync {
+    /// Light arriving from the direction the ray escaped in
+    fn color(&self, ray: &Ray<f64>) -> Color;
+}
+
+/// The same color in every direction, eg. black for indoor scenes
+pub struct SolidBackground {
+    color: Color,
+}
+
+impl SolidBackground {
+    #[must_use]
+    pub const fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Background for SolidBackground {
+    fn color(&self, _ray: &Ray<f64>) -> Color {
+        self.color
+    }
+}
+
+/// Linear fade on the vertical direction of the ray, from `bottom` straight down
+/// to `top` straight up
+pub struct GradientBackground {
+    bottom: Color,
+    top: Color,
+}
+
+impl GradientBackground {
+    #[must_use]
+    pub const fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+}
+
+/// White to sky blue
+impl Default for GradientBackground {
+    fn default() -> Self {
+        Self::new(
+            Color::new(1.0, 1.0, 1.0).unwrap(),
+            Color::new(0.5, 0.7, 1.0).unwrap(),
+        )
+    }
+}
+
+impl Background for GradientBackground {
+    fn color(&self, ray: &Ray<f64>) -> Color {
+        let Ok(unit_direction) = ray.get_direction().normalize() else {
+            return self.bottom;
+        };
+        let a = 0.5 * (unit_direction.y + 1.0);
+        (1.0 - a) * self.bottom + a * self.top
+    }
+}
+
+/// Equirectangular (latitude/longitude) image wrapped around the scene
+///
+/// The top row of the image is straight up and the center column looks down -z.
+pub struct EnvironmentMap {
+    image: Framebuffer,
+}
+
+impl EnvironmentMap {
+    #[must_use]
+    pub const fn new(image: Framebuffer) -> Self {
+        Self { image }
+    }
+
+    /// Load the map from a Radiance `.hdr`, `.pfm` or `.ppm` file
+    ///
+    /// # Errors
+    /// If the file can't be read, isn't a valid image or is empty
+    pub fn from_file(path: &Path) -> Result<Self, ImageError> {
+        let image = read_image(path)?;
+        if image.width() == 0 || image.height() == 0 {
+            return Err(ImageError::InvalidFormat(
+                "environment map is empty".to_string(),
+            ));
+        }
+        Ok(Self::new(image))
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Color {
+        self.image.get(x, y)
+    }
+}
+
+impl Background for EnvironmentMap {
+    #[allow(clippy::cast_precision_loss)] // image sizes are far below 2^52
+    fn color(&self, ray: &Ray<f64>) -> Color {
+        let Ok(direction) = ray.get_direction().normalize() else {
+            return Color::black();
+        };
+
+        // longitude around the y axis and latitude down from +y, both in [0, 1]
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * std::f64::consts::PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / std::f64::consts::PI;
+
+        // bilinear filter between pixel centers, wrapping around horizontally
+        let (width, height) = (self.image.width(), self.image.height());
+        let x = u.mul_add(width as f64, -0.5);
+        let y = v.mul_add(height as f64, -0.5);
+        let (x0, tx) = (x.floor(), x - x.floor());
+        let (y0, ty) = (y.floor(), y - y.floor());
+
+        let column = |offset: f64| wrap_index(x0 + offset, width);
+        let row = |offset: f64| clamp_index(y0 + offset, height);
+
+        let top =
+            (1.0 - tx) * self.pixel(column(0.0), row(0.0)) + tx * self.pixel(column(1.0), row(0.0));
+        let bottom =
+            (1.0 - tx) * self.pixel(column(0.0), row(1.0)) + tx * self.pixel(column(1.0), row(1.0));
+        (1.0 - ty) * top + ty * bottom
+    }
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn wrap_index(index: f64, size: usize) -> usize {
+    index.rem_euclid(size as f64) as usize % size
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn clamp_index(index: f64, size: usize) -> usize {
+    index.clamp(0.0, (size - 1) as f64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_utils::{point::Point, vector::Vec3};
+
+    const fn ray_towards(x: f64, y: f64, z: f64) -> Ray<f64> {
+        Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(x, y, z))
+    }
+
+    #[test]
+    fn test_gradient_ends() {
+        let background = GradientBackground::default();
+        assert_eq!(
+            background.color(&ray_towards(0.0, 5.0, 0.0)),
+            Color::new(0.5, 0.7, 1.0).unwrap()
+        );
+        assert_eq!(
+            background.color(&ray_towards(0.0, -2.0, 0.0)),
+            Color::new(1.0, 1.0, 1.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_environment_map_directions() {
+        // top half red, bottom half blue, with a green column in the middle of the bottom
+        let red = Color::new(1.0, 0.0, 0.0).unwrap();
+        let blue = Color::new(0.0, 0.0, 1.0).unwrap();
+        let green = Color::new(0.0, 1.0, 0.0).unwrap();
+        let width = 8;
+        let mut pixels = vec![red; width * 2];
+        pixels.extend(vec![blue; width * 2]);
+        pixels[3 * width + 3] = green;
+        pixels[3 * width + 4] = green;
+        let map = EnvironmentMap::new(Framebuffer::from_pixels(width, 4, pixels));
+
+        assert_eq!(map.color(&ray_towards(0.0, 1.0, 0.0)), red);
+        // nudged towards -z so the longitude is well defined
+        assert_eq!(map.color(&ray_towards(0.0, -1.0, -1e-9)), green);
+        assert_eq!(map.color(&ray_towards(1.0, -1.0, 0.0)), blue);
+    }
+}
@@ -0,0 +1,141 @@
+//! Spending samples where the image is still noisy
+//!
+//! Every pixel starts with a few samples. After that, pixels whose mean has settled
+//! stop, and the samples they didn't use go to the ones that are still noisy, so
+//! flat areas like the sky finish early while shadows and caustics get more.
+
+use crate::ray_tracing::color::Color;
+
+/// Mean luminance below which a pixel's noise is judged against this instead, so
+/// dark pixels don't have to be sampled forever to reach a relative error
+const DARK_LUMINANCE: f64 = 0.05;
+
+/// When a pixel counts as converged and how many samples it may get
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Standard error of a pixel's mean luminance, relative to that mean, at which
+    /// it stops being sampled
+    pub threshold: f64,
+    /// Samples every pixel gets before its noise is judged, and how many more a
+    /// noisy pixel gets at a time unless the camera sets its own `pass_samples`
+    pub min_samples: isize,
+    /// Most samples a single pixel can get, `None` for 4 times the average
+    pub max_samples: Option<isize>,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self {
+            threshold: 0.05,
+            min_samples: 16,
+            max_samples: None,
+        }
+    }
+}
+
+/// Running mean and variance of the samples of one pixel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelStats {
+    count: usize,
+    color_sum: Color,
+    /// Mean luminance and sum of squared differences from it, updated with Welford's
+    /// method so long runs of samples don't lose precision
+    luminance_mean: f64,
+    luminance_m2: f64,
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            color_sum: Color::black(),
+            luminance_mean: 0.0,
+            luminance_m2: 0.0,
+        }
+    }
+}
+
+impl PixelStats {
+    #[allow(clippy::cast_precision_loss)] // sample counts are far below 2^52
+    pub fn add(&mut self, color: Color) {
+        self.count += 1;
+        self.color_sum += color;
+        let luminance = color.luminance();
+        let delta = luminance - self.luminance_mean;
+        self.luminance_mean += delta / self.count as f64;
+        self.luminance_m2 += delta * (luminance - self.luminance_mean);
+    }
+
+    #[must_use]
+    pub const fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Average of the samples so far, black before the first one
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)] // sample counts are far below 2^52
+    pub fn mean(&self) -> Color {
+        if self.count == 0 {
+            Color::black()
+        } else {
+            self.color_sum * (1.0 / self.count as f64)
+        }
+    }
+
+    /// Whether the standard error of the mean luminance is within `threshold` of the
+    /// mean, which takes at least two samples to tell
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)] // sample counts are far below 2^52
+    pub fn is_converged(&self, threshold: f64) -> bool {
+        if self.count < 2 {
+            return false;
+        }
+        let count = self.count as f64;
+        let variance = self.luminance_m2 / (count - 1.0);
+        let standard_error = (variance / count).sqrt();
+        standard_error <= threshold * self.luminance_mean.max(DARK_LUMINANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn gray(value: f64) -> Color {
+        Color {
+            red: value,
+            green: value,
+            blue: value,
+        }
+    }
+
+    #[test]
+    fn test_mean_and_variance() {
+        let mut stats = PixelStats::default();
+        assert_eq!(stats.mean(), Color::black());
+        for value in [0.2, 0.4, 0.6, 0.8] {
+            stats.add(gray(value));
+        }
+        assert_eq!(stats.count(), 4);
+        assert!((stats.mean().red - 0.5).abs() < 1e-12);
+        // sample variance of the luminances, which equal the gray values
+        assert!((stats.luminance_m2 / 3.0 - 0.2 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_convergence() {
+        let mut flat = PixelStats::default();
+        flat.add(gray(0.7));
+        assert!(!flat.is_converged(0.01), "one sample says nothing");
+        flat.add(gray(0.7));
+        assert!(flat.is_converged(0.01));
+
+        // standard error of 0.5 / sqrt(n) against a mean of 0.5
+        let mut noisy = PixelStats::default();
+        for index in 0..100 {
+            noisy.add(gray(if index % 2 == 0 { 0.0 } else { 1.0 }));
+        }
+        assert!(!noisy.is_converged(0.05));
+        assert!(noisy.is_converged(0.11));
+    }
+}
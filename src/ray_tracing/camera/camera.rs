@@ -1,19 +1,304 @@
 //! The camera class constructs and dispatches rays and uses the results to generate an image
 
 use crate::{
-    math_utils::{interval::Interval, point::Point, ray::Ray, vector::Vec3},
+    image_utils::framebuffer::Framebuffer,
+    math_utils::{
+        point::Point,
+        random::Rng,
+        ray::Ray,
+        samplers::{Sampler, SamplerKind},
+        vector::Vec3,
+    },
     ray_tracing::{
+        aov::Aov,
+        background::{Background, GradientBackground},
+        camera::{
+            adaptive::{AdaptiveSampling, PixelStats},
+            crop::Crop,
+            filter::Filter,
+            tiles::{Tile, Tiling},
+        },
         color::Color,
-        shapes::{group::Group, hittable::Hittable},
+        integrators::{Integrator, SceneView},
+        lights::LightList,
+        shapes::hittable::Hittable,
     },
 };
 use core::f64;
-use indicatif::ParallelProgressIterator;
+use indicatif::ProgressBar;
 use rayon::prelude::*;
-use std::io::Write;
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    f64::consts::{FRAC_PI_2, FRAC_PI_4},
+    sync::Arc,
+};
+
+/// Everything a render produces
+pub struct RenderOutput {
+    /// Linear radiance seen through every pixel
+    pub image: Framebuffer,
+    /// One image for each AOV asked for
+    pub aovs: BTreeMap<Aov, Framebuffer>,
+    /// How many samples each pixel got, row by row
+    pub samples: Vec<usize>,
+}
+
+impl RenderOutput {
+    /// Samples spent on every pixel, from blue for none through green to red for the
+    /// most any pixel got
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)] // sample counts are far below 2^52
+    pub fn heatmap(&self) -> Framebuffer {
+        let most = self.samples.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let pixels = self
+            .samples
+            .iter()
+            .map(|&count| {
+                let t = count as f64 / most;
+                if t < 0.5 {
+                    Color {
+                        red: 0.0,
+                        green: 2.0 * t,
+                        blue: 2.0f64.mul_add(-t, 1.0),
+                    }
+                } else {
+                    Color {
+                        red: 2.0f64.mul_add(t, -1.0),
+                        green: 2.0f64.mul_add(-t, 2.0),
+                        blue: 0.0,
+                    }
+                }
+            })
+            .collect();
+        Framebuffer::from_pixels(self.image.width(), self.image.height(), pixels)
+    }
+}
 
+/// A render part way through, handed out between passes
+pub struct Pass<'a> {
+    /// Passes finished so far, starting at 1
+    pub number: usize,
+    /// Samples taken so far over the whole image
+    pub samples: usize,
+    /// Samples the whole render will take at most
+    pub budget: usize,
+    region: Tile,
+    frame: Tile,
+    aovs: &'a [Aov],
+    state: &'a RenderState,
+}
+
+impl Pass<'_> {
+    /// The image as it stands
+    #[must_use]
+    pub fn output(&self) -> RenderOutput {
+        self.state.output(self.region, self.frame, self.aovs)
+    }
+}
+
+/// What one pixel has gathered so far
+#[derive(Clone)]
+struct PixelState {
+    stats: PixelStats,
+    /// Running sums of the averaged AOVs, or the first sample of the others
+    aov_sums: Vec<Color>,
+    /// Whether the pixel still takes samples
+    active: bool,
+}
+
+/// The samples a pixel got from itself and its neighbours, weighted by the filter
 #[derive(Clone, Copy)]
+struct FilmPixel {
+    color_sum: Color,
+    weight_sum: f64,
+}
+
+impl FilmPixel {
+    const EMPTY: Self = Self {
+        color_sum: Color::black(),
+        weight_sum: 0.0,
+    };
+
+    fn add(&mut self, color: Color, weight: f64) {
+        self.color_sum += color * weight;
+        self.weight_sum += weight;
+    }
+
+    /// The weighted average, or `fallback` while the weights don't add up to anything
+    fn value(&self, fallback: Color) -> Color {
+        if self.weight_sum <= 0.0 {
+            return fallback;
+        }
+        let color = self.color_sum * (1.0 / self.weight_sum);
+        // negative lobes can pull a pixel next to a bright edge below black
+        Color {
+            red: color.red.max(0.0),
+            green: color.green.max(0.0),
+            blue: color.blue.max(0.0),
+        }
+    }
+}
+
+/// What every sample of a render needs besides the camera
+struct SampleContext<'a> {
+    scene: SceneView<'a>,
+    integrator: &'a dyn Integrator,
+    aovs: &'a [Aov],
+    sampler: &'a dyn Sampler,
+    seed: u64,
+    /// Most samples any pixel gets
+    max_samples: usize,
+}
+
+/// Everything the traced pixels have gathered so far, kept for the whole render
+struct RenderState {
+    /// The pixels that get samples, the region and a margin around it
+    traced: Tile,
+    /// How far past its own pixel a sample counts
+    reach: usize,
+    /// Row by row within `traced`
+    pixels: Vec<PixelState>,
+    /// The samples taken, on a film reaching `reach` pixels past the edges of
+    /// `traced` so the samples along them have somewhere to go
+    film: Vec<FilmPixel>,
+}
+
+/// A tile's share of the `RenderState`, which no other tile rendered at the same
+/// time touches
+struct TileView<'a> {
+    tile: Tile,
+    /// The tile's pixels, row by row
+    pixels: Vec<&'a mut [PixelState]>,
+    /// The film under the tile, reaching as far past its edges as the filter does
+    film: Vec<&'a mut [FilmPixel]>,
+}
+
+impl RenderState {
+    fn new(traced: Tile, aovs: usize, reach: usize) -> Self {
+        Self {
+            traced,
+            reach,
+            pixels: vec![
+                PixelState {
+                    stats: PixelStats::default(),
+                    aov_sums: vec![Color::black(); aovs],
+                    active: true,
+                };
+                traced.width * traced.height
+            ],
+            film: vec![FilmPixel::EMPTY; (traced.width + 2 * reach) * (traced.height + 2 * reach)],
+        }
+    }
+
+    /// The share of each of `tiles`, which have to be at least `2 * reach` pixels
+    /// apart so their films don't overlap
+    fn views(&mut self, tiles: &[Tile]) -> Vec<TileView<'_>> {
+        let (traced, reach) = (self.traced, self.reach);
+        let in_traced: Vec<Tile> = tiles
+            .iter()
+            .map(|tile| Tile {
+                x: tile.x - traced.x,
+                y: tile.y - traced.y,
+                ..*tile
+            })
+            .collect();
+        // the film starts `reach` pixels above and left of the traced pixels, so a
+        // tile's film starts where the tile itself does among them
+        let on_film: Vec<Tile> = in_traced
+            .iter()
+            .map(|tile| Tile {
+                width: tile.width + 2 * reach,
+                height: tile.height + 2 * reach,
+                ..*tile
+            })
+            .collect();
+        let pixels = split_rectangles(&mut self.pixels, traced.width, &in_traced);
+        let film = split_rectangles(&mut self.film, traced.width + 2 * reach, &on_film);
+        tiles
+            .iter()
+            .zip(pixels.into_iter().zip(film))
+            .map(|(&tile, (pixels, film))| TileView { tile, pixels, film })
+            .collect()
+    }
+
+    /// Turn the samples gathered so far within `region` into images of `frame`, which
+    /// holds the region and is black everywhere else
+    #[allow(clippy::cast_precision_loss)] // sample counts are far below 2^52
+    fn output(&self, region: Tile, frame: Tile, aovs: &[Aov]) -> RenderOutput {
+        let (traced, reach) = (self.traced, self.reach);
+        let film_width = traced.width + 2 * reach;
+        let (width, height) = (frame.width, frame.height);
+        let mut image = vec![Color::black(); width * height];
+        let mut aov_images = vec![vec![Color::black(); width * height]; aovs.len()];
+        let mut samples = vec![0; width * height];
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                let (column, row) = (x - traced.x, y - traced.y);
+                let pixel = &self.pixels[row * traced.width + column];
+                let film_pixel = self.film[(row + reach) * film_width + column + reach];
+                let at = (y - frame.y) * width + x - frame.x;
+                image[at] = film_pixel.value(pixel.stats.mean());
+                for ((aov, aov_image), &sum) in
+                    aovs.iter().zip(&mut aov_images).zip(&pixel.aov_sums)
+                {
+                    aov_image[at] = if aov.is_averaged() {
+                        sum * (1.0 / pixel.stats.count() as f64)
+                    } else {
+                        sum
+                    };
+                }
+                samples[at] = pixel.stats.count();
+            }
+        }
+
+        RenderOutput {
+            image: Framebuffer::from_pixels(width, height, image),
+            aovs: aovs
+                .iter()
+                .zip(aov_images)
+                .map(|(&aov, pixels)| (aov, Framebuffer::from_pixels(width, height, pixels)))
+                .collect(),
+            samples,
+        }
+    }
+}
+
+/// The rows of each of `rectangles` in `buffer`, whose rows are `width` long, the
+/// rectangles not overlapping
+fn split_rectangles<'a, T>(
+    buffer: &'a mut [T],
+    width: usize,
+    rectangles: &[Tile],
+) -> Vec<Vec<&'a mut [T]>> {
+    let mut views: Vec<Vec<&mut [T]>> = rectangles
+        .iter()
+        .map(|rectangle| Vec::with_capacity(rectangle.height))
+        .collect();
+    let mut by_top: Vec<usize> = (0..rectangles.len()).collect();
+    by_top.sort_unstable_by_key(|&index| rectangles[index].y);
+    let mut by_top = by_top.into_iter().peekable();
+    // the rectangles crossing the current row, from left to right
+    let mut crossing: Vec<usize> = Vec::new();
+    for (y, mut row) in buffer.chunks_mut(width.max(1)).enumerate() {
+        crossing.retain(|&index| y < rectangles[index].y + rectangles[index].height);
+        while let Some(index) = by_top.next_if(|&index| rectangles[index].y == y) {
+            crossing.push(index);
+        }
+        crossing.sort_unstable_by_key(|&index| rectangles[index].x);
+        let mut row_start = 0;
+        for &index in &crossing {
+            let rectangle = rectangles[index];
+            let (_, rest) = std::mem::take(&mut row).split_at_mut(rectangle.x - row_start);
+            let (inside, rest) = rest.split_at_mut(rectangle.width);
+            views[index].push(inside);
+            row = rest;
+            row_start = rectangle.x + rectangle.width;
+        }
+    }
+    views
+}
+
+#[derive(Clone)]
 pub struct Camera {
     center: Point<f64>,
     // aspect_ratio: f64,
@@ -23,47 +308,63 @@ pub struct Camera {
     pixel_delta_u: Vec3<f64>,
     pixel_delta_v: Vec3<f64>,
     samples_per_pixel: isize,
-    max_depth: isize,
     defocus_dist_u: Vec3<f64>,
     defocus_dist_v: Vec3<f64>,
     defocus_angle: f64,
+    background: Arc<dyn Background + Send + Sync>,
+    seed: Option<u64>,
+    adaptive: Option<AdaptiveSampling>,
+    pass_samples: Option<isize>,
+    sampler: SamplerKind,
+    filter: Filter,
+    tiling: Tiling,
+    crop: Option<Crop>,
     // basis_vectors: Vec3<Vec3<f64>>,
     // vertical_fov: f64,
 }
 
 /// Make it a bit easier to create a camera by letting a user
 /// set some elements in a stream-like manner
+#[derive(Clone)]
 pub struct CameraBuilder {
     pub image_width: isize,
     pub aspect_ratio: f64,
     pub samples_per_pixel: isize,
-    pub max_depth: isize,
     pub vertical_fov: f64,
     pub lookfrom: Point<f64>,
     pub lookat: Point<f64>,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    /// What rays that escape the scene see
+    pub background: Arc<dyn Background + Send + Sync>,
+    /// Fixes the random numbers so a render can be repeated exactly, `None` for a
+    /// different image every run
+    pub seed: Option<u64>,
+    /// Spend the samples where the image is noisiest, `None` for the same number on
+    /// every pixel
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Refine the whole image this many samples a pixel at a time, so it can be looked
+    /// at before it's done, `None` to take every sample in one go
+    pub pass_samples: Option<isize>,
+    /// Where the random numbers for the pixel, lens, time and each bounce come from
+    pub sampler: SamplerKind,
+    /// How samples are shared between the pixels around them
+    pub filter: Filter,
+    /// How the image is split up between the threads
+    pub tiling: Tiling,
+    /// Only render this part of the image, `None` for all of it
+    pub crop: Option<Crop>,
 }
 
 impl CameraBuilder {
     pub fn make_camera(&self) -> Camera {
-        Camera::new(
-            self.image_width,
-            self.aspect_ratio,
-            self.samples_per_pixel,
-            self.max_depth,
-            self.vertical_fov,
-            self.lookfrom,
-            self.lookat,
-            self.defocus_angle,
-            self.focus_dist,
-        )
+        Camera::new(self)
     }
 
     pub fn set_look_at(&self, look_at: Point<f64>) -> Self {
         CameraBuilder {
             lookat: look_at,
-            ..(*self)
+            ..self.clone()
         }
     }
 
@@ -71,21 +372,37 @@ impl CameraBuilder {
         let look_from = Point::new(x, y, z);
         CameraBuilder {
             lookfrom: look_from,
-            ..(*self)
+            ..self.clone()
         }
     }
 
     pub fn set_defocus_angle(&self, defocus_angle: f64) -> Self {
         CameraBuilder {
             defocus_angle,
-            ..(*self)
+            ..self.clone()
         }
     }
 
     pub fn set_focus_dist(&self, focus_dist: f64) -> Self {
         CameraBuilder {
             focus_dist,
-            ..(*self)
+            ..self.clone()
+        }
+    }
+
+    #[must_use]
+    pub fn set_background(&self, background: Arc<dyn Background + Send + Sync>) -> Self {
+        Self {
+            background,
+            ..self.clone()
+        }
+    }
+
+    #[must_use]
+    pub fn set_seed(&self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self.clone()
         }
     }
 }
@@ -95,7 +412,6 @@ impl Default for CameraBuilder {
         let image_width = 500;
         let aspect_ratio = 16.0 / 9.0;
         let samples_per_pixel = 100;
-        let max_depth = 50;
 
         let vertical_fov: f64 = 20.;
 
@@ -106,16 +422,25 @@ impl Default for CameraBuilder {
         let defocus_angle = 0.0;
         let focus_dist = 10.0;
 
+        let background = Arc::new(GradientBackground::default());
+
         CameraBuilder {
             image_width,
             aspect_ratio,
             samples_per_pixel,
-            max_depth,
             vertical_fov,
             lookfrom,
             lookat,
             defocus_angle,
             focus_dist,
+            background,
+            seed: None,
+            adaptive: None,
+            pass_samples: None,
+            sampler: SamplerKind::default(),
+            filter: Filter::default(),
+            tiling: Tiling::default(),
+            crop: None,
         }
     }
 }
@@ -127,17 +452,32 @@ impl Default for Camera {
 }
 
 impl Camera {
-    pub fn new(
-        image_width: isize,
-        aspect_ratio: f64,
-        samples_per_pixel: isize,
-        max_depth: isize,
-        vertical_fov: f64,
-        lookfrom: Point<f64>,
-        lookat: Point<f64>,
-        defocus_angle: f64,
-        focus_dist: f64,
-    ) -> Camera {
+    /// Set a camera up from the settings gathered in `settings`
+    ///
+    /// # Panics
+    ///
+    /// If `lookfrom` and `lookat` are the same point, or the camera looks straight up
+    /// or down
+    #[must_use]
+    pub fn new(settings: &CameraBuilder) -> Self {
+        let CameraBuilder {
+            image_width,
+            aspect_ratio,
+            samples_per_pixel,
+            vertical_fov,
+            lookfrom,
+            lookat,
+            defocus_angle,
+            focus_dist,
+            ref background,
+            seed,
+            adaptive,
+            pass_samples,
+            sampler,
+            filter,
+            tiling,
+            crop,
+        } = *settings;
         let image_height = (image_width as f64 / aspect_ratio).round() as isize;
         // clamp height to 1 at a minimum
         let image_height = if image_height < 1 { 1 } else { image_height };
@@ -182,141 +522,590 @@ impl Camera {
             pixel_delta_u: pixel_du,
             pixel_delta_v: pixel_dv,
             samples_per_pixel,
-            max_depth,
             defocus_dist_u,
             defocus_dist_v,
             defocus_angle, // basis_vectors: Vec3 { x: u, y: v, z: w }
+            background: Arc::clone(background),
+            seed,
+            adaptive,
+            pass_samples,
+            sampler,
+            filter,
+            tiling,
+            crop,
         }
     }
 
-    pub fn render(&self, world: Group) {
-        let pixel_sample_scale = 1.0 / (self.samples_per_pixel as f64);
-        let world_rc = Arc::new(world);
-
-        // main Render loop!
-        // All done in parallel, so each row is performed in parallel using rayon
-        let pixels: Vec<Vec<Color>> = (0..self.image_height)
-            .into_par_iter()
-            // And get a nice progress bar!
-            .progress_count(self.image_height.try_into().unwrap())
-            .map(|j| {
-                // now, for each column in the row
-                (0..self.image_width)
-                    .map(|i| {
-                        let row = j as f64;
-                        let col = i as f64;
-
-                        // get a black color
-                        let mut color = Color::new(0.0, 0.0, 0.0).unwrap();
-
-                        // then for each pixel, take a bunch of samples and add what we see
-                        for _ in 0..self.samples_per_pixel {
-                            let ray = self.get_ray(col, row);
-                            color = color
-                                + self.get_ray_color(
-                                    ray.clone(),
-                                    Arc::clone(&world_rc),
-                                    self.max_depth,
-                                );
-                        }
-                        // scale because we do't want to overly sample a pixel
-                        color * pixel_sample_scale
-                    })
-                    .collect()
-            })
-            .collect(); // get everything back into the maian thread
-
-        // now that we're back in the main thread, open and edit our file
-        let mut file_handle = std::fs::File::create("./image.ppm").unwrap();
-        // for the PPM formt, write a header
-        let header = format!("P3\n{} {}\n255\n", self.image_width, self.image_height).into_bytes();
-        file_handle.write_all(&header).unwrap();
-
-        // write each pixel as RGB values for PPM formt
-        pixels.into_iter().for_each(|row| {
-            row.into_iter()
-                .for_each(|pixel_col| write(&mut file_handle, pixel_col));
-        });
+    /// The pixels that get rendered, the whole image unless it's cropped, `None` if
+    /// the crop window misses the image
+    #[must_use]
+    pub fn region(&self) -> Option<Tile> {
+        let width = self.image_width.unsigned_abs();
+        let height = self.image_height.unsigned_abs();
+        self.crop.map_or(
+            Some(Tile {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }),
+            |crop| crop.window.region(width, height),
+        )
     }
 
-    fn get_ray_color<T>(&self, ray: Ray<f64>, world: Arc<T>, remaining_bounces: isize) -> Color
+    /// Render the world into an image, asking `integrator` for the light carried by
+    /// every camera ray, along with an image of each of `aovs`
+    ///
+    /// The image, or the part of it the crop window covers, is sampled in passes.
+    /// Without adaptive sampling or `pass_samples` the first pass takes every sample,
+    /// otherwise each pass gives the pixels that still need it a few more until the
+    /// budget of `samples_per_pixel` a pixel runs out. `on_pass` sees the image after
+    /// every pass but the last, whose result is returned.
+    ///
+    /// The pixels just outside a crop window are sampled as well, as the filter
+    /// spreads their samples into it, so its edges come out as in the whole image.
+    #[allow(clippy::cast_precision_loss)] // image sizes are far below 2^52
+    pub fn render<T>(
+        &self,
+        world: &T,
+        lights: &LightList,
+        integrator: &dyn Integrator,
+        aovs: &[Aov],
+        mut on_pass: impl FnMut(&Pass<'_>),
+    ) -> RenderOutput
     where
-        T: Hittable,
+        T: Hittable + Send + Sync,
     {
-        const MIN_HIT_DISTANCE: f64 = 0.01;
+        let width = self.image_width.unsigned_abs();
+        let empty = Tile {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        };
+        let region = self.region().unwrap_or(empty);
+        // the image written out is either the region or all of it
+        let frame = match self.crop {
+            Some(crop) if !crop.full_size => region,
+            _ => Tile {
+                width,
+                height: self.image_height.unsigned_abs(),
+                ..empty
+            },
+        };
+        let traced = self.traced(region);
+        let samples_per_pixel = self.samples_per_pixel.unsigned_abs();
+        let budget = samples_per_pixel * traced.width * traced.height;
+        // an unseeded render still needs one seed to derive every sample's from
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let sampler = self.sampler.build(seed, samples_per_pixel as u64);
+        let context = SampleContext {
+            scene: SceneView {
+                world,
+                lights,
+                background: self.background.as_ref(),
+            },
+            integrator,
+            aovs,
+            sampler: sampler.as_ref(),
+            seed,
+            max_samples: self.adaptive.map_or(samples_per_pixel, |adaptive| {
+                adaptive
+                    .max_samples
+                    .map_or(4 * samples_per_pixel, isize::unsigned_abs)
+            }),
+        };
+
+        let reach = self.filter.reach();
+        let groups = self.tiling.groups(traced, reach);
+        let mut state = RenderState::new(traced, aovs.len(), reach);
+        // And get a nice progress bar!
+        let progress = ProgressBar::new(budget as u64);
+        // adaptive sampling judges every pixel after its first few samples, and then
+        // hands out more in passes like a progressive render
+        let step = self.pass_samples.map_or_else(
+            || {
+                self.adaptive.map_or(samples_per_pixel, |adaptive| {
+                    adaptive.min_samples.unsigned_abs()
+                })
+            },
+            isize::unsigned_abs,
+        );
+        let mut batch = self
+            .adaptive
+            .map_or(step, |adaptive| adaptive.min_samples.unsigned_abs())
+            .min(samples_per_pixel);
+        let mut spent = 0;
 
-        if remaining_bounces <= 0 {
-            return Color::new(0.0, 0.0, 0.0).unwrap();
+        // main Render loop!
+        for round in 0_usize.. {
+            spent += self.render_pass(&context, &groups, &mut state, batch, &progress);
+
+            let active = state.pixels.iter().filter(|pixel| pixel.active).count();
+            if active == 0 {
+                break;
+            }
+            // share what's left evenly, a few samples at a time so the pixels that
+            // converge along the way leave more for the rest
+            batch = step.min((budget - spent) / active);
+            if batch == 0 {
+                break;
+            }
+            on_pass(&Pass {
+                number: round + 1,
+                samples: spent,
+                budget,
+                region,
+                frame,
+                aovs,
+                state: &state,
+            });
         }
+        progress.finish();
 
-        match world.hit(&ray, Interval::new(MIN_HIT_DISTANCE, f64::INFINITY)) {
-            // if we hit something
-            Some(hit_record) => {
-                // then scatter off that something
-                match hit_record.material.scatter(ray, &hit_record) {
-                    // if the scatterer produces a valid scatter
-                    Some((scattered_ray, attenuation)) => {
-                        let scatter_result =
-                            self.get_ray_color(scattered_ray, world, remaining_bounces - 1);
-                        Color::new(
-                            attenuation.red * scatter_result.red,
-                            attenuation.green * scatter_result.green,
-                            attenuation.blue * scatter_result.blue,
-                        )
-                        .unwrap()
-                    }
-                    // else no way to scatter so return black
-                    None => Color::new(0.0, 0.0, 0.0).unwrap(),
+        state.output(region, frame, aovs)
+    }
+
+    /// `region` and the pixels around it the filter reaches from, whose samples
+    /// spill into it
+    fn traced(&self, region: Tile) -> Tile {
+        if region.width == 0 {
+            return region;
+        }
+        // a sample counts towards the pixels up to `reach` away from its own
+        let reach = self.filter.reach();
+        let (x, y) = (
+            region.x.saturating_sub(reach),
+            region.y.saturating_sub(reach),
+        );
+        Tile {
+            x,
+            y,
+            width: (region.x + region.width + reach).min(self.image_width.unsigned_abs()) - x,
+            height: (region.y + region.height + reach).min(self.image_height.unsigned_abs()) - y,
+        }
+    }
+
+    /// Give every pixel that still takes samples up to `batch` more, all done in
+    /// parallel a group of tiles at a time, returns how many samples that took
+    fn render_pass(
+        &self,
+        context: &SampleContext<'_>,
+        groups: &[Vec<Tile>],
+        state: &mut RenderState,
+        batch: usize,
+        progress: &ProgressBar,
+    ) -> usize {
+        let mut spent = 0;
+        // the tiles of a group share no pixels, and the groups go one after the
+        // other, so the samples are added up in the same order on any thread
+        for group in groups {
+            spent += state
+                .views(group)
+                .par_iter_mut()
+                .with_min_len(1)
+                .map(|view| {
+                    let tile_spent = self.render_tile(context, view, batch);
+                    progress.inc(tile_spent as u64);
+                    tile_spent
+                })
+                .sum::<usize>();
+        }
+        spent
+    }
+
+    /// Give the pixels of a tile that still take samples up to `batch` more, returns
+    /// how many samples that took
+    fn render_tile(
+        &self,
+        context: &SampleContext<'_>,
+        view: &mut TileView<'_>,
+        batch: usize,
+    ) -> usize {
+        let mut spent = 0;
+        for (row, pixels) in view.pixels.iter_mut().enumerate() {
+            for (column, pixel) in pixels.iter_mut().enumerate() {
+                if !pixel.active {
+                    continue;
                 }
+                let new_samples =
+                    batch.min(context.max_samples.saturating_sub(pixel.stats.count()));
+                for _ in 0..new_samples {
+                    self.render_sample(context, pixel, &mut view.film, view.tile, (column, row));
+                }
+                spent += new_samples;
+                pixel.active = pixel.stats.count() < context.max_samples
+                    && !self
+                        .adaptive
+                        .is_some_and(|adaptive| pixel.stats.is_converged(adaptive.threshold));
             }
-            // hit nothing, so grab thge background color (diffuse light source)
-            None => {
-                // default blue to white fade
-                let unit_direction = (*ray.get_direction()).normalize().unwrap();
-                let a = 0.5 * (unit_direction.y + 1.0);
-                let start_fade = (1.0 - a) * Color::new(1.0, 1.0, 1.0).unwrap();
-                let end_fade = a * Color::new(0.5, 0.7, 1.0).unwrap();
-                start_fade + end_fade
+        }
+        spent
+    }
+
+    /// Take the next sample of pixel `(column, row)` of `tile`, adding it to the
+    /// pixel's AOVs and to the tile's `film`
+    #[allow(clippy::cast_precision_loss)] // image sizes are far below 2^52
+    fn render_sample(
+        &self,
+        context: &SampleContext<'_>,
+        pixel: &mut PixelState,
+        film: &mut [&mut [FilmPixel]],
+        tile: Tile,
+        (column, row): (usize, usize),
+    ) {
+        let (i, j) = (tile.x + column, tile.y + row);
+        let scene = &context.scene;
+        // every sample has its own random numbers, so it comes out the same whichever
+        // thread or pass takes it
+        let mut rng = Rng::for_sample(
+            context.sampler,
+            context.seed,
+            (j * self.image_width.unsigned_abs() + i) as u64,
+            pixel.stats.count() as u64,
+        );
+        let offset = Self::sample_square(&mut rng);
+        let ray = self.get_ray(i as f64, j as f64, offset, &mut rng);
+        if !context.aovs.is_empty() {
+            let hit_record = scene.hit(&ray);
+            for (aov, sum) in context.aovs.iter().zip(&mut pixel.aov_sums) {
+                let value = aov.sample(&ray, hit_record.as_ref(), scene.background);
+                if aov.is_averaged() {
+                    *sum += value;
+                } else if pixel.stats.count() == 0 {
+                    *sum = value;
+                }
             }
         }
+        let radiance = context.integrator.radiance(ray, scene, &mut rng);
+        pixel.stats.add(radiance);
+        self.splat(film, (column, row), offset, radiance);
     }
 
-    fn get_ray(&self, i: f64, j: f64) -> Ray<f64> {
-        let offset = Camera::sample_square();
+    /// Add a sample taken `offset` from the center of pixel `(column, row)` of a tile
+    /// to the pixels around it, on the tile's `film`
+    fn splat(
+        &self,
+        film: &mut [&mut [FilmPixel]],
+        (column, row): (usize, usize),
+        offset: Vec3<f64>,
+        radiance: Color,
+    ) {
+        let reach = self.filter.reach();
+        for (dy, weight_y) in self.filter.weights(offset.y) {
+            // the film has room for `reach` pixels past every edge of the tile
+            let film_row = &mut film[(row + reach).wrapping_add_signed(dy)];
+            for (dx, weight_x) in self.filter.weights(offset.x) {
+                film_row[(column + reach).wrapping_add_signed(dx)]
+                    .add(radiance, weight_x * weight_y);
+            }
+        }
+    }
+
+    fn get_ray(&self, i: f64, j: f64, offset: Vec3<f64>, rng: &mut Rng<'_>) -> Ray<f64> {
+        // the lens sample is taken either way, so the dimensions after it don't shift
+        // with the defocus angle
+        let lens = rng.get_2d();
         // let ray_origin = self.center;
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(lens)
         };
         let pixel_center = self.pixel_00_loc
             + ((i + offset.x) * self.pixel_delta_u)
             + ((j + offset.y) * self.pixel_delta_v);
         let ray_direction = pixel_center - ray_origin;
 
-        let ray_time: f64= rand::random();
+        let ray_time = rng.get_1d();
 
         Ray::new_at_time(ray_origin, ray_direction, ray_time)
     }
 
-    fn defocus_disk_sample(&self) -> Point<f64> {
-        let p = Vec3::random_in_unit_dist();
+    fn defocus_disk_sample(&self, lens: [f64; 2]) -> Point<f64> {
+        let p = concentric_disk(lens);
 
         self.center + (p.x * self.defocus_dist_u) + (p.y * self.defocus_dist_v)
     }
 
-    fn sample_square() -> Vec3<f64> {
-        Vec3::new(
-            rand::random_range(-0.5..0.5),
-            rand::random_range(-0.5..0.5),
-            0.0,
-        )
+    fn sample_square(rng: &mut Rng<'_>) -> Vec3<f64> {
+        let [x, y] = rng.get_2d();
+        Vec3::new(x - 0.5, y - 0.5, 0.0)
+    }
+}
+
+/// Maps the unit square onto the unit disk, squares around the center onto rings, so
+/// evenly spread points stay evenly spread (Shirley and Chiu, 1997)
+fn concentric_disk([x, y]: [f64; 2]) -> Vec3<f64> {
+    let (x, y) = (2.0_f64.mul_add(x, -1.0), 2.0_f64.mul_add(y, -1.0));
+    if x == 0.0 && y == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
     }
+    let (radius, theta) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, FRAC_PI_4.mul_add(-x / y, FRAC_PI_2))
+    };
+    Vec3::new(radius * theta.cos(), radius * theta.sin(), 0.0)
 }
 
-/// Utility function to write a color to the file in RGB format
-fn write(file_handle: &mut std::fs::File, color: Color) {
-    let (rbyte, gbyte, bbyte) = color.to_bytes_rgb();
-    let output_row = format!("{} {} {}\n", rbyte, gbyte, bbyte).into_bytes();
-    file_handle.write_all(&output_row).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_tracing::{
+        background::SolidBackground,
+        camera::{crop::CropWindow, filter::FilterKind, tiles::TileOrder},
+        integrators::IntegratorSettings,
+        materials::lambertian::LambertianMaterial,
+        shapes::{group::Group, sphere::Sphere},
+    };
+
+    const SKY: Color = Color {
+        red: 0.2,
+        green: 0.4,
+        blue: 0.6,
+    };
+
+    /// A small view of nothing but a flat sky
+    fn builder() -> CameraBuilder {
+        CameraBuilder {
+            image_width: 8,
+            aspect_ratio: 2.0,
+            samples_per_pixel: 6,
+            background: Arc::new(SolidBackground::new(SKY)),
+            seed: Some(1),
+            ..CameraBuilder::default()
+        }
+    }
+
+    fn render(camera: &Camera, on_pass: impl FnMut(&Pass<'_>)) -> RenderOutput {
+        render_world(camera, &Group::new(Vec::new()), on_pass)
+    }
+
+    fn render_world(
+        camera: &Camera,
+        world: &Group,
+        on_pass: impl FnMut(&Pass<'_>),
+    ) -> RenderOutput {
+        let integrator = IntegratorSettings::default().build();
+        camera.render(
+            world,
+            &LightList::default(),
+            integrator.as_ref(),
+            &[Aov::Depth],
+            on_pass,
+        )
+    }
+
+    #[test]
+    fn test_passes_add_up_to_the_samples_per_pixel() {
+        let camera = CameraBuilder {
+            pass_samples: Some(4),
+            ..builder()
+        }
+        .make_camera();
+        let mut passes = Vec::new();
+        let output = render(&camera, |pass| {
+            assert_eq!(pass.output().samples, vec![4 * pass.number; 32]);
+            passes.push((pass.number, pass.samples, pass.budget));
+        });
+
+        // the second and last pass only has 2 samples left to take
+        assert_eq!(passes, [(1, 128, 192)]);
+        assert_eq!(output.samples, vec![6; 32]);
+        for pixel in output.image.pixels() {
+            assert!((pixel.blue - SKY.blue).abs() < 1e-12);
+        }
+        assert_eq!(output.aovs[&Aov::Depth], Framebuffer::new(8, 4));
+    }
+
+    #[test]
+    fn test_converged_pixels_stop_early() {
+        let camera = CameraBuilder {
+            adaptive: Some(AdaptiveSampling {
+                min_samples: 2,
+                ..AdaptiveSampling::default()
+            }),
+            ..builder()
+        }
+        .make_camera();
+        let output = render(&camera, |_| panic!("a flat sky converges in one pass"));
+        assert_eq!(output.samples, vec![2; 32]);
+        assert!(
+            output
+                .heatmap()
+                .pixels()
+                .iter()
+                .all(|pixel| pixel.red > 0.9)
+        );
+    }
+
+    /// A matte ball for the random bounces to land on
+    fn ball() -> Group {
+        let mut world = Group::new(Vec::new());
+        world.add(Box::new(Sphere::new(
+            Point::new(0.0, 0.0, -3.0),
+            1.0,
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap())),
+        )));
+        world
+    }
+
+    #[test]
+    fn test_seeded_renders_repeat() {
+        let world = ball();
+        let camera = builder().make_camera();
+        let image = render_world(&camera, &world, |_| {}).image;
+
+        // the same with more threads and the samples split over passes
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+        let progressive = CameraBuilder {
+            pass_samples: Some(4),
+            ..builder()
+        }
+        .make_camera();
+        assert_eq!(
+            image,
+            pool.install(|| render_world(&progressive, &world, |_| {}).image)
+        );
+
+        let reseeded = builder().set_seed(2).make_camera();
+        assert_ne!(image, render_world(&reseeded, &world, |_| {}).image);
+    }
+
+    #[test]
+    fn test_filters() {
+        let world = ball();
+        let boxed = render_world(&builder().make_camera(), &world, |_| {}).image;
+        for kind in FilterKind::NAMES {
+            let camera = CameraBuilder {
+                filter: Filter::new(kind.parse().unwrap()),
+                ..builder()
+            }
+            .make_camera();
+            // the weights are normalized, also at the edges of the image
+            for pixel in render(&camera, |_| {}).image.pixels() {
+                assert!((pixel.blue - SKY.blue).abs() < 1e-9, "{kind}");
+            }
+
+            // samples shared between rows are still added up in the same order
+            // however many threads take them
+            let image = render_world(&camera, &world, |_| {}).image;
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(3)
+                .build()
+                .unwrap();
+            assert_eq!(
+                image,
+                pool.install(|| render_world(&camera, &world, |_| {}).image),
+                "{kind}"
+            );
+            assert_eq!(image == boxed, kind == "box", "{kind}");
+        }
+    }
+
+    #[test]
+    fn test_tiles_leave_the_image_alone() {
+        let world = ball();
+        for filter in [Filter::default(), Filter::new(FilterKind::Tent)] {
+            let tiled = |size, order| {
+                let camera = CameraBuilder {
+                    filter,
+                    tiling: Tiling { size, order },
+                    ..builder()
+                }
+                .make_camera();
+                render_world(&camera, &world, |_| {})
+            };
+            let whole = tiled(100, TileOrder::Hilbert);
+            for (size, order) in [(1, TileOrder::Hilbert), (3, TileOrder::Spiral)] {
+                let output = tiled(size, order);
+                assert_eq!(output.samples, whole.samples);
+                // samples spilling into other tiles are added in another order
+                for (a, b) in output.image.pixels().iter().zip(whole.image.pixels()) {
+                    assert!((a.red - b.red).abs() < 1e-12, "{filter:?} {size}");
+                }
+                assert_eq!(output.aovs, whole.aovs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_crop_window() {
+        let world = ball();
+        let inside = |x: usize, y: usize| (3..7).contains(&x) && (1..3).contains(&y);
+        // the pixels next to the window are sampled in other tiles than in the whole
+        // image, so their samples are added in another order
+        let close = |a: Color, b: Color| {
+            (a.red - b.red).abs() < 1e-12
+                && (a.green - b.green).abs() < 1e-12
+                && (a.blue - b.blue).abs() < 1e-12
+        };
+        for kind in FilterKind::NAMES {
+            let filter = Filter::new(kind.parse().unwrap());
+            let whole = render_world(
+                &CameraBuilder {
+                    filter,
+                    ..builder()
+                }
+                .make_camera(),
+                &world,
+                |_| {},
+            );
+            let cropped = |full_size| {
+                let camera = CameraBuilder {
+                    filter,
+                    crop: Some(Crop {
+                        window: CropWindow::pixels([3, 1, 4, 2]).unwrap(),
+                        full_size,
+                    }),
+                    ..builder()
+                }
+                .make_camera();
+                render_world(&camera, &world, |_| {})
+            };
+
+            // every pixel comes out as in the whole image, also along the edges where
+            // the filter reaches past the window
+            let output = cropped(false);
+            assert_eq!((output.image.width(), output.image.height()), (4, 2));
+            assert_eq!(output.samples, vec![6; 8]);
+            for y in 0..2 {
+                for x in 0..4 {
+                    let (a, b) = (output.image.get(x, y), whole.image.get(x + 3, y + 1));
+                    assert!(close(a, b), "{kind} {x} {y}");
+                    assert_eq!(
+                        output.aovs[&Aov::Depth].get(x, y),
+                        whole.aovs[&Aov::Depth].get(x + 3, y + 1)
+                    );
+                }
+            }
+
+            let output = cropped(true);
+            assert_eq!((output.image.width(), output.image.height()), (8, 4));
+            for y in 0..4 {
+                for x in 0..8 {
+                    let pixel = output.image.get(x, y);
+                    if inside(x, y) {
+                        assert!(close(pixel, whole.image.get(x, y)), "{kind} {x} {y}");
+                        assert_eq!(output.samples[y * 8 + x], 6);
+                    } else {
+                        assert_eq!(pixel, Color::black());
+                        assert_eq!(output.samples[y * 8 + x], 0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_pass_by_default() {
+        let output = render(&builder().make_camera(), |_| {
+            panic!("no passes were asked for")
+        });
+        assert_eq!(output.samples, vec![6; 32]);
+    }
 }
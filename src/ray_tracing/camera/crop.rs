@@ -0,0 +1,186 @@
+//! Rendering only part of the image
+//!
+//! A crop window picks the pixels that get traced, either as a rectangle of pixels or
+//! as fractions of the image's width and height, which stay put when the resolution
+//! changes. The render is written either as just that region or at the full size,
+//! with every pixel outside the window left black.
+
+use super::tiles::Tile;
+
+/// The part of the image to render
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CropWindow {
+    /// A rectangle of pixels, whatever of it lies outside the image is dropped
+    Pixels {
+        /// Column of the left edge
+        x: usize,
+        /// Row of the top edge
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    /// Edges as fractions of the image's width and height, from the top left
+    Normalized {
+        x_min: f64,
+        x_max: f64,
+        y_min: f64,
+        y_max: f64,
+    },
+}
+
+impl CropWindow {
+    /// A rectangle of pixels from `[x, y, width, height]`
+    ///
+    /// # Errors
+    ///
+    /// If it's empty
+    pub fn pixels([x, y, width, height]: [usize; 4]) -> Result<Self, String> {
+        if width == 0 || height == 0 {
+            return Err("needs a width and height of at least 1".to_string());
+        }
+        Ok(Self::Pixels {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// Fractions of the image from `[x_min, x_max, y_min, y_max]`
+    ///
+    /// # Errors
+    ///
+    /// Unless every edge is within 0 to 1 and each minimum below its maximum
+    pub fn normalized([x_min, x_max, y_min, y_max]: [f64; 4]) -> Result<Self, String> {
+        let valid = |min: f64, max: f64| 0.0 <= min && min < max && max <= 1.0;
+        if !valid(x_min, x_max) || !valid(y_min, y_max) {
+            return Err("needs edges between 0 and 1, each minimum below its maximum".to_string());
+        }
+        Ok(Self::Normalized {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+        })
+    }
+
+    /// The pixels of a `width` by `height` image the window covers, `None` if it
+    /// misses the image altogether
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )] // image sizes are far below 2^52
+    pub fn region(&self, width: usize, height: usize) -> Option<Tile> {
+        let (x_range, y_range) = match *self {
+            Self::Pixels {
+                x,
+                y,
+                width: crop_width,
+                height: crop_height,
+            } => (
+                (x, x.saturating_add(crop_width)),
+                (y, y.saturating_add(crop_height)),
+            ),
+            Self::Normalized {
+                x_min,
+                x_max,
+                y_min,
+                y_max,
+            } => {
+                // a pixel is in when its center is, so windows that meet share none
+                let edge = |fraction: f64, size: usize| {
+                    fraction.mul_add(size as f64, -0.5).ceil() as usize
+                };
+                (
+                    (edge(x_min, width), edge(x_max, width)),
+                    (edge(y_min, height), edge(y_max, height)),
+                )
+            }
+        };
+        let (x_start, x_end) = (x_range.0.min(width), x_range.1.min(width));
+        let (y_start, y_end) = (y_range.0.min(height), y_range.1.min(height));
+        (x_start < x_end && y_start < y_end).then_some(Tile {
+            x: x_start,
+            y: y_start,
+            width: x_end - x_start,
+            height: y_end - y_start,
+        })
+    }
+}
+
+/// A crop window and how its render is written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub window: CropWindow,
+    /// Write the whole image with everything outside the window black, rather than
+    /// just the window
+    pub full_size: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_windows() {
+        let window = CropWindow::pixels([2, 1, 3, 4]).unwrap();
+        assert_eq!(
+            window.region(10, 10),
+            Some(Tile {
+                x: 2,
+                y: 1,
+                width: 3,
+                height: 4,
+            })
+        );
+        // cut down to the image
+        assert_eq!(
+            window.region(4, 3),
+            Some(Tile {
+                x: 2,
+                y: 1,
+                width: 2,
+                height: 2,
+            })
+        );
+        assert_eq!(window.region(2, 10), None);
+        assert!(CropWindow::pixels([0, 0, 0, 5]).is_err());
+    }
+
+    #[test]
+    fn test_normalized_windows() {
+        let whole = CropWindow::normalized([0.0, 1.0, 0.0, 1.0]).unwrap();
+        assert_eq!(
+            whole.region(7, 5),
+            Some(Tile {
+                x: 0,
+                y: 0,
+                width: 7,
+                height: 5,
+            })
+        );
+
+        // the halves of an odd width split it without a gap or overlap
+        let left = CropWindow::normalized([0.0, 0.5, 0.25, 0.75]).unwrap();
+        let right = CropWindow::normalized([0.5, 1.0, 0.25, 0.75]).unwrap();
+        let (left, right) = (left.region(7, 4).unwrap(), right.region(7, 4).unwrap());
+        assert_eq!(left.x + left.width, right.x);
+        assert_eq!(left.width + right.width, 7);
+        assert_eq!((left.y, left.height), (1, 2));
+
+        // too thin to cover a pixel center
+        let sliver = CropWindow::normalized([0.5, 0.51, 0.0, 1.0]).unwrap();
+        assert_eq!(sliver.region(10, 10), None);
+
+        for edges in [
+            [0.5, 0.5, 0.0, 1.0],
+            [-0.1, 0.5, 0.0, 1.0],
+            [0.0, 1.0, 0.2, 1.5],
+            [0.0, f64::NAN, 0.0, 1.0],
+        ] {
+            assert!(CropWindow::normalized(edges).is_err(), "{edges:?}");
+        }
+    }
+}
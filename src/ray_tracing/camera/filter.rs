@@ -0,0 +1,248 @@
+//! Reconstruction filters, which decide how much each sample counts towards the
+//! pixels around it
+//!
+//! A sample is added to every pixel whose center lies within the filter's radius,
+//! weighted by the filter at the distance between them, and every pixel ends up as
+//! the weighted average of the samples it got. The default box filter of radius 0.5
+//! keeps each sample in its own pixel. Wider filters blend neighbouring pixels, which
+//! trades a little sharpness for smoother, less jagged edges. Mitchell and Lanczos
+//! dip below zero away from the center, which sharpens edges again but can ring
+//! around very bright ones.
+
+use std::{f64::consts::PI, fmt, str::FromStr};
+
+/// The shapes of filter that can be picked by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterKind {
+    /// Every sample within the radius counts the same
+    #[default]
+    Box,
+    /// Falls off linearly to zero at the radius
+    Tent,
+    /// A bell curve with a standard deviation of a third of the radius, shifted down
+    /// to reach zero at the radius
+    Gaussian,
+    /// The Mitchell-Netravali cubic with B = C = 1/3
+    Mitchell,
+    /// A sinc windowed by a wider sinc that reaches zero at the radius
+    Lanczos,
+}
+
+impl FilterKind {
+    /// Names accepted by `from_str`
+    pub const NAMES: [&str; 5] = ["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+    /// Radius in pixels the filter gets unless it's given one
+    #[must_use]
+    pub const fn default_radius(self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 1.5,
+            Self::Mitchell => 2.0,
+            Self::Lanczos => 3.0,
+        }
+    }
+}
+
+impl fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = match self {
+            Self::Box => 0,
+            Self::Tent => 1,
+            Self::Gaussian => 2,
+            Self::Mitchell => 3,
+            Self::Lanczos => 4,
+        };
+        f.write_str(Self::NAMES[index])
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "box" => Ok(Self::Box),
+            "tent" => Ok(Self::Tent),
+            "gaussian" => Ok(Self::Gaussian),
+            "mitchell" => Ok(Self::Mitchell),
+            "lanczos" => Ok(Self::Lanczos),
+            _ => Err(format!(
+                "unknown filter {name:?}, expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// A filter shape and how far it reaches, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::default())
+    }
+}
+
+impl Filter {
+    /// `kind` with its default radius
+    #[must_use]
+    pub const fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    /// How many pixels on either side of the one a sample was taken in it can reach
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // small radii
+    pub fn reach(&self) -> usize {
+        // a sample is at most half a pixel from its pixel's center
+        ((self.radius + 0.5).ceil() as usize).saturating_sub(1)
+    }
+
+    /// The pixels along one axis a sample `offset` from its pixel's center counts
+    /// towards, relative to that pixel, with its weight in each
+    ///
+    /// Pixels exactly the radius away on the negative side are left out, so with the
+    /// box filter of radius 0.5 a sample on the border between two pixels only counts
+    /// towards one of them.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_precision_loss)] // small radii
+    pub fn weights(&self, offset: f64) -> impl Iterator<Item = (isize, f64)> {
+        let reach = self.reach() as isize;
+        (-reach..=reach).filter_map(move |delta| {
+            let distance = delta as f64 - offset;
+            (distance > -self.radius && distance <= self.radius)
+                .then(|| (delta, self.evaluate(distance)))
+        })
+    }
+
+    /// The filter along one axis at `distance` pixels from its center, all filters
+    /// here being the product of this along both axes
+    #[must_use]
+    pub fn evaluate(&self, distance: f64) -> f64 {
+        let distance = distance.abs();
+        if distance > self.radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - distance,
+            FilterKind::Gaussian => {
+                let sigma = self.radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(distance) - gaussian(self.radius)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * distance / self.radius),
+            FilterKind::Lanczos => sinc(distance) * sinc(distance / self.radius),
+        }
+    }
+}
+
+/// The Mitchell-Netravali cubic with B = C = 1/3, which reaches zero at 2
+fn mitchell(x: f64) -> f64 {
+    let x = x.abs();
+    // (12 - 9B - 6C) x^3 + (-18 + 12B + 6C) x^2 + (6 - 2B) in the middle and
+    // (-B - 6C) x^3 + (6B + 30C) x^2 + (-12B - 48C) x + (8B + 24C) around it
+    let value = if x <= 1.0 {
+        (x * x).mul_add(7.0f64.mul_add(x, -12.0), 16.0 / 3.0)
+    } else if x <= 2.0 {
+        (-7.0f64 / 3.0)
+            .mul_add(x, 12.0)
+            .mul_add(x, -20.0)
+            .mul_add(x, 32.0 / 3.0)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
+
+/// `sin(pi x) / (pi x)`, 1 at 0
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for name in FilterKind::NAMES {
+            let kind: FilterKind = name.parse().unwrap();
+            assert_eq!(kind.to_string(), name);
+        }
+        assert!("sinc".parse::<FilterKind>().is_err());
+    }
+
+    #[test]
+    fn test_shapes() {
+        for name in FilterKind::NAMES {
+            let filter = Filter::new(name.parse().unwrap());
+            // highest at the center and gone past the radius
+            let center = filter.evaluate(0.0);
+            assert!(center > 0.0, "{name}");
+            for step in 1..20 {
+                let distance = f64::from(step) * 0.1;
+                assert!(filter.evaluate(distance) <= center, "{name} {distance}");
+                assert!(
+                    (filter.evaluate(distance) - filter.evaluate(-distance)).abs() < 1e-12,
+                    "{name} {distance}"
+                );
+            }
+            assert!(
+                filter.evaluate(filter.radius + 0.01).abs() < 1e-12,
+                "{name}"
+            );
+        }
+        // the tails reach zero smoothly rather than stopping at a step
+        for kind in [
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+            FilterKind::Lanczos,
+        ] {
+            let filter = Filter::new(kind);
+            assert!(filter.evaluate(filter.radius - 1e-6).abs() < 1e-4, "{kind}");
+        }
+        // the negative lobes
+        assert!(Filter::new(FilterKind::Mitchell).evaluate(1.5) < 0.0);
+        assert!(Filter::new(FilterKind::Lanczos).evaluate(1.5) < 0.0);
+    }
+
+    #[test]
+    fn test_weights() {
+        // the box keeps every sample in its own pixel, even on the border
+        let box_filter = Filter::default();
+        assert_eq!(box_filter.reach(), 0);
+        for offset in [-0.5, -0.2, 0.0, 0.49] {
+            assert_eq!(box_filter.weights(offset).collect::<Vec<_>>(), [(0, 1.0)]);
+        }
+
+        let tent = Filter::new(FilterKind::Tent);
+        assert_eq!(tent.reach(), 1);
+        let weights: Vec<(isize, f64)> = tent.weights(0.25).collect();
+        assert_eq!(weights.len(), 2);
+        assert_eq!(weights[0].0, 0);
+        assert!((weights[0].1 - 0.75).abs() < 1e-12);
+        assert_eq!(weights[1].0, 1);
+        assert!((weights[1].1 - 0.25).abs() < 1e-12);
+
+        let wide = Filter {
+            kind: FilterKind::Gaussian,
+            radius: 2.0,
+        };
+        assert_eq!(wide.reach(), 2);
+        // and leaves out the far pixel on one side
+        assert_eq!(wide.weights(0.0).count(), 4);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod adaptive;
 /// TODO: look at the default trait thing
 /// `impl Default for Camera`
 /**
@@ -8,3 +9,6 @@
  * }
  */
 pub mod camera;
+pub mod crop;
+pub mod filter;
+pub mod tiles;
@@ -0,0 +1,264 @@
+//! Splitting the image into tiles, the units of work handed to the threads
+//!
+//! Neighbouring pixels tend to look at the same objects, so a thread that stays
+//! within a small square of the image keeps the same few BVH nodes and triangles in
+//! its cache, where a whole row would cross the entire scene. The tiles are handed
+//! out in an order that keeps consecutive tiles close together as well.
+//!
+//! A sample also counts towards the pixels around its own, so tiles next to each
+//! other add to the same pixels along their edges. The tiles are rendered in groups
+//! far enough apart that this never happens within a group, so each tile writes
+//! straight into the image and the sums come out the same on any number of threads.
+
+use std::{fmt, str::FromStr};
+
+/// The order tiles are handed out in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    /// Along a Hilbert curve, which never jumps far from the previous tile
+    #[default]
+    Hilbert,
+    /// Ring by ring outwards from the center, where the subject usually is
+    Spiral,
+}
+
+impl TileOrder {
+    /// Names accepted by `from_str`
+    pub const NAMES: [&str; 2] = ["hilbert", "spiral"];
+}
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = match self {
+            Self::Hilbert => 0,
+            Self::Spiral => 1,
+        };
+        f.write_str(Self::NAMES[index])
+    }
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "hilbert" => Ok(Self::Hilbert),
+            "spiral" => Ok(Self::Spiral),
+            _ => Err(format!(
+                "unknown tile order {name:?}, expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// A rectangle of pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    /// Column of the left edge
+    pub x: usize,
+    /// Row of the top edge
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// How the image is split into tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tiling {
+    /// Width and height of a tile in pixels, the tiles along the right and bottom
+    /// edges can be smaller
+    pub size: isize,
+    pub order: TileOrder,
+}
+
+impl Default for Tiling {
+    fn default() -> Self {
+        Self {
+            size: 32,
+            order: TileOrder::default(),
+        }
+    }
+}
+
+impl Tiling {
+    /// The tiles covering `region`, in the order they should be rendered in
+    #[must_use]
+    pub fn tiles(&self, region: Tile) -> Vec<Tile> {
+        let size = self.size.unsigned_abs().max(1);
+        let columns = region.width.div_ceil(size);
+        let rows = region.height.div_ceil(size);
+        let mut grid: Vec<(usize, usize)> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect();
+        match self.order {
+            TileOrder::Hilbert => {
+                let curve_width = columns.max(rows).next_power_of_two();
+                grid.sort_by_key(|&(column, row)| hilbert_index(curve_width, column, row));
+            }
+            TileOrder::Spiral => {
+                grid.sort_by(|&a, &b| {
+                    spiral_key(a, columns, rows).total_cmp(&spiral_key(b, columns, rows))
+                });
+            }
+        }
+        grid.into_iter()
+            .map(|(column, row)| {
+                let x = column * size;
+                let y = row * size;
+                Tile {
+                    x: region.x + x,
+                    y: region.y + y,
+                    width: size.min(region.width - x),
+                    height: size.min(region.height - y),
+                }
+            })
+            .collect()
+    }
+
+    /// The tiles covering `region`, split into groups to render one after the other
+    ///
+    /// Within a group the tiles are at least `2 * reach` pixels apart, so no pixel is
+    /// within `reach` of two of them. Each group keeps the order of `tiles`.
+    #[must_use]
+    pub fn groups(&self, region: Tile, reach: usize) -> Vec<Vec<Tile>> {
+        let size = self.size.unsigned_abs().max(1);
+        // tiles this many apart have whole tiles between them to keep them clear
+        let spacing = 1 + (2 * reach).div_ceil(size);
+        let mut groups = vec![Vec::new(); spacing * spacing];
+        for tile in self.tiles(region) {
+            let (column, row) = ((tile.x - region.x) / size, (tile.y - region.y) / size);
+            groups[(row % spacing) * spacing + column % spacing].push(tile);
+        }
+        groups.retain(|group| !group.is_empty());
+        groups
+    }
+}
+
+/// How far along the Hilbert curve filling a `side` by `side` grid the cell at
+/// `(x, y)` is, `side` being a power of two
+const fn hilbert_index(side: usize, mut x: usize, mut y: usize) -> usize {
+    let mut index = 0;
+    let mut scale = side / 2;
+    while scale > 0 {
+        let right = (x & scale) != 0;
+        let bottom = (y & scale) != 0;
+        index += scale * scale * ((3 * right as usize) ^ bottom as usize);
+        // turn the quadrant so the curve through it starts where the last one ended
+        if !bottom {
+            if right {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            (x, y) = (y, x);
+        }
+        scale /= 2;
+    }
+    index
+}
+
+/// Sorts tiles by the ring around the center they are on, and by angle within a ring
+#[allow(clippy::cast_precision_loss)] // tile counts are far below 2^52
+fn spiral_key((column, row): (usize, usize), columns: usize, rows: usize) -> f64 {
+    let dx = column as f64 - (columns as f64 - 1.0) / 2.0;
+    let dy = row as f64 - (rows as f64 - 1.0) / 2.0;
+    let ring = dx.abs().max(dy.abs()).round();
+    // angles fall in [-pi, pi], so every ring stays clear of the next
+    ring * 10.0 + dy.atan2(dx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles(order: TileOrder, width: usize, height: usize) -> Vec<Tile> {
+        Tiling { size: 4, order }.tiles(Tile {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        })
+    }
+
+    #[test]
+    fn test_names() {
+        for name in TileOrder::NAMES {
+            assert_eq!(name.parse::<TileOrder>().unwrap().to_string(), name);
+        }
+        assert!("rows".parse::<TileOrder>().is_err());
+    }
+
+    #[test]
+    fn test_tiles_cover_the_image_once() {
+        for order in [TileOrder::Hilbert, TileOrder::Spiral] {
+            let mut covered = vec![0; 30 * 17];
+            for tile in tiles(order, 30, 17) {
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        covered[y * 30 + x] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&count| count == 1), "{order}");
+        }
+    }
+
+    #[test]
+    fn test_groups_keep_tiles_apart() {
+        let tiling = Tiling {
+            size: 3,
+            order: TileOrder::Hilbert,
+        };
+        let region = Tile {
+            x: 2,
+            y: 1,
+            width: 20,
+            height: 11,
+        };
+        assert_eq!(tiling.groups(region, 0), vec![tiling.tiles(region)]);
+        for reach in [1, 2, 4] {
+            let groups = tiling.groups(region, reach);
+            assert_eq!(
+                groups.iter().map(Vec::len).sum::<usize>(),
+                tiling.tiles(region).len()
+            );
+            for group in &groups {
+                for (index, a) in group.iter().enumerate() {
+                    for b in &group[index + 1..] {
+                        // the gap between them along at least one axis
+                        let gap_x =
+                            b.x.max(a.x)
+                                .saturating_sub((a.x + a.width).min(b.x + b.width));
+                        let gap_y =
+                            b.y.max(a.y)
+                                .saturating_sub((a.y + a.height).min(b.y + b.height));
+                        assert!(gap_x.max(gap_y) >= 2 * reach, "{a:?} {b:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hilbert_order_steps_to_neighbours() {
+        let tiles = tiles(TileOrder::Hilbert, 32, 32);
+        assert_eq!(tiles.len(), 64);
+        for pair in tiles.windows(2) {
+            let distance = pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y);
+            assert_eq!(distance, 4, "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_in_the_middle() {
+        let tiles = tiles(TileOrder::Spiral, 20, 20);
+        assert_eq!((tiles[0].x, tiles[0].y), (8, 8));
+        // the ring around the center comes before the edges
+        let ring = |tile: &Tile| tile.x.abs_diff(8).max(tile.y.abs_diff(8)) / 4;
+        assert!(
+            tiles
+                .windows(2)
+                .all(|pair| ring(&pair[0]) <= ring(&pair[1]))
+        );
+    }
+}
@@ -24,6 +24,46 @@ impl Color {
         }
     }
 
+    #[must_use]
+    pub const fn black() -> Self {
+        Self {
+            red: 0.0,
+            green: 0.0,
+            blue: 0.0,
+        }
+    }
+
+    #[must_use]
+    pub const fn white() -> Self {
+        Self {
+            red: 1.0,
+            green: 1.0,
+            blue: 1.0,
+        }
+    }
+
+    /// The brightest of the three channels
+    #[must_use]
+    pub const fn max_channel(&self) -> f64 {
+        self.red.max(self.green).max(self.blue)
+    }
+
+    /// Brightness as the eye sees it, with Rec. 709 weights for linear channels
+    #[must_use]
+    pub fn luminance(&self) -> f64 {
+        0.0722f64.mul_add(self.blue, 0.2126f64.mul_add(self.red, 0.7152 * self.green))
+    }
+
+    /// Every channel clamped to [0, 1]
+    #[must_use]
+    pub const fn clamped(self) -> Self {
+        Self {
+            red: self.red.clamp(0.0, 1.0),
+            green: self.green.clamp(0.0, 1.0),
+            blue: self.blue.clamp(0.0, 1.0),
+        }
+    }
+
     pub const fn to_bytes_rgb(&self) -> (u8, u8, u8) {
         const COLOR_INTENSITY: Interval = Interval::new(0.000, 1.0);
         let red_byte = (COLOR_INTENSITY.clamp(self.red) * 255.0) as u8;
@@ -31,25 +71,6 @@ impl Color {
         let blue_byte = (COLOR_INTENSITY.clamp(self.blue) * 255.0) as u8;
         return (red_byte, green_byte, blue_byte);
     }
-
-    pub fn to_gamma_bytes_rgb(&self) -> (u8, u8, u8) {
-        Color::new(
-            Color::linear_to_gamma(self.red),
-            Color::linear_to_gamma(self.green),
-            Color::linear_to_gamma(self.blue),
-        )
-        .unwrap()
-        .to_bytes_rgb()
-    }
-
-    /// Update from linear to gamma space for image storage
-    fn linear_to_gamma(linear_component: f64) -> f64 {
-        if linear_component > 0.0 {
-            linear_component.sqrt()
-        } else {
-            0.0
-        }
-    }
 }
 
 impl Mul<f64> for Color {
@@ -63,6 +84,18 @@ impl Mul<f64> for Color {
     }
 }
 
+/// Component-wise product, eg. to filter light by a surface color
+impl Mul<Self> for Color {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            red: self.red * rhs.red,
+            green: self.green * rhs.green,
+            blue: self.blue * rhs.blue,
+        }
+    }
+}
+
 impl Mul<Color> for f64 {
     type Output = Color;
     fn mul(self, rhs: Color) -> Self::Output {
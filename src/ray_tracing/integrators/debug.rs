@@ -0,0 +1,197 @@
+//! Integrators that show one property of the first surface a ray hits
+
+use super::{Integrator, SceneView};
+use crate::{
+    math_utils::{
+        interval::Interval,
+        pdf::{CosinePdf, Pdf},
+        random::Rng,
+        ray::Ray,
+    },
+    ray_tracing::{aov::Aov, color::Color},
+};
+
+/// Surface normals, with each axis mapped from [-1, 1] to [0, 1]
+///
+/// Normals face the ray, so the same surface seen from behind shows the opposite
+/// color. Rays that escape are black.
+pub struct NormalIntegrator;
+
+impl Integrator for NormalIntegrator {
+    fn radiance(&self, ray: Ray<f64>, scene: &SceneView<'_>, _rng: &mut Rng<'_>) -> Color {
+        scene.hit(&ray).map_or_else(Color::black, |hit_record| {
+            let normal = hit_record.normal;
+            Color {
+                red: 0.5 * (normal.x + 1.0),
+                green: 0.5 * (normal.y + 1.0),
+                blue: 0.5 * (normal.z + 1.0),
+            }
+        })
+    }
+}
+
+/// Distance from the camera to the first hit in every channel, 0 for rays that escape
+///
+/// Write a `.pfm` or `.hdr` to keep the distances, or scale them into view with a
+/// negative exposure for 8 bit formats.
+pub struct DepthIntegrator;
+
+impl Integrator for DepthIntegrator {
+    fn radiance(&self, ray: Ray<f64>, scene: &SceneView<'_>, _rng: &mut Rng<'_>) -> Color {
+        Aov::Depth.sample(&ray, scene.hit(&ray).as_ref(), scene.background)
+    }
+}
+
+/// Surface color without any lighting: the material's albedo, the color of lights
+/// clamped to 1, and the background for rays that escape
+pub struct AlbedoIntegrator;
+
+impl Integrator for AlbedoIntegrator {
+    fn radiance(&self, ray: Ray<f64>, scene: &SceneView<'_>, _rng: &mut Rng<'_>) -> Color {
+        Aov::Albedo.sample(&ray, scene.hit(&ray).as_ref(), scene.background)
+    }
+}
+
+/// How open the sky above each hit is, from black where every direction is blocked
+/// to white where none is, weighting directions by the cosine to the normal
+pub struct AmbientOcclusionIntegrator {
+    samples: isize,
+    max_distance: f64,
+}
+
+impl AmbientOcclusionIntegrator {
+    /// Trace `samples` rays per hit, surfaces further away than `max_distance` don't
+    /// block them
+    #[must_use]
+    pub const fn new(samples: isize, max_distance: f64) -> Self {
+        Self {
+            samples,
+            max_distance,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    #[allow(clippy::cast_precision_loss)] // sample counts are far below 2^52
+    fn radiance(&self, ray: Ray<f64>, scene: &SceneView<'_>, rng: &mut Rng<'_>) -> Color {
+        let Some(hit_record) = scene.hit(&ray) else {
+            return Color::white();
+        };
+
+        let pdf = CosinePdf::new(&hit_record.normal);
+        let open = (0..self.samples)
+            .filter(|_| {
+                let direction = pdf.generate(rng);
+                // scale the direction to unit length so t measures distance
+                let Ok(direction) = direction.normalize() else {
+                    return false;
+                };
+                let occlusion_ray = Ray::new_at_time(hit_record.point, direction, ray.get_time());
+                scene
+                    .world
+                    .hit(
+                        &occlusion_ray,
+                        Interval::new(super::MIN_HIT_DISTANCE, self.max_distance),
+                    )
+                    .is_none()
+            })
+            .count();
+        Color::white() * (open as f64 / self.samples.max(1) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        math_utils::{point::Point, vector::Vec3},
+        ray_tracing::{
+            background::SolidBackground,
+            lights::LightList,
+            materials::lambertian::LambertianMaterial,
+            shapes::{group::Group, quad::Quad},
+        },
+    };
+
+    /// A gray floor at y = 0 with a wall along x = 1, under a sky blue background
+    fn corner() -> Group {
+        let gray = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap()));
+        let mut world = Group::new(Vec::new());
+        world.add(Box::new(Quad::new(
+            Point::new(-10.0, 0.0, -10.0),
+            Vec3::new(0.0, 0.0, 20.0),
+            Vec3::new(20.0, 0.0, 0.0),
+            Arc::clone(&gray) as _,
+        )));
+        world.add(Box::new(Quad::new(
+            Point::new(1.0, 0.0, -10.0),
+            Vec3::new(0.0, 10.0, 0.0),
+            Vec3::new(0.0, 0.0, 20.0),
+            gray,
+        )));
+        world
+    }
+
+    fn render(integrator: &dyn Integrator, ray: Ray<f64>) -> Color {
+        let world = corner();
+        let lights = LightList::default();
+        let background = SolidBackground::new(Color::new(0.5, 0.7, 1.0).unwrap());
+        let scene = SceneView {
+            world: &world,
+            lights: &lights,
+            background: &background,
+        };
+        integrator.radiance(ray, &scene, &mut Rng::new(7))
+    }
+
+    fn down_at(x: f64) -> Ray<f64> {
+        Ray::new(Point::new(x, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0))
+    }
+
+    #[test]
+    fn test_normals_and_depth() {
+        let normal = render(&NormalIntegrator, down_at(-1.0));
+        assert_eq!(
+            normal,
+            Color {
+                red: 0.5,
+                green: 1.0,
+                blue: 0.5
+            }
+        );
+        let depth = render(
+            &DepthIntegrator,
+            Ray::new(Point::new(-1.0, 2.0, 0.0), Vec3::new(0.0, -4.0, 0.0)),
+        );
+        assert!((depth.red - 2.0).abs() < 1e-12);
+        let sky = Ray::new(Point::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(render(&DepthIntegrator, sky), Color::black());
+    }
+
+    #[test]
+    fn test_albedo() {
+        assert_eq!(
+            render(&AlbedoIntegrator, down_at(-1.0)),
+            Color::new(0.5, 0.5, 0.5).unwrap()
+        );
+        let sky = Ray::new(Point::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            render(&AlbedoIntegrator, sky),
+            Color::new(0.5, 0.7, 1.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ambient_occlusion_darkens_corners() {
+        // right next to the wall it blocks half of the hemisphere
+        let integrator = AmbientOcclusionIntegrator::new(4000, f64::INFINITY);
+        let corner = render(&integrator, down_at(0.9)).red;
+        assert!((corner - 0.5).abs() < 0.05, "{corner}");
+
+        // but not once it's out of reach
+        let short = AmbientOcclusionIntegrator::new(100, 0.4);
+        assert_eq!(render(&short, down_at(0.5)), Color::white());
+    }
+}
@@ -0,0 +1,151 @@
+//! Light transport algorithms, separate from how the camera generates rays
+//!
+//! The camera asks an `Integrator` for the color seen along each ray it traces. The
+//! path tracer computes actual light, the rest show a single property of the first
+//! surface a ray hits, which helps when debugging geometry, materials or lighting.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    math_utils::{interval::Interval, random::Rng, ray::Ray},
+    ray_tracing::{
+        background::Background,
+        color::Color,
+        lights::LightList,
+        shapes::hittable::{HitRecord, Hittable},
+    },
+};
+
+pub mod debug;
+pub mod path;
+
+/// Hits closer than this to a ray's origin are the surface it just left
+pub(crate) const MIN_HIT_DISTANCE: f64 = 0.01;
+
+/// Everything an integrator can see of the scene
+pub struct SceneView<'a> {
+    pub world: &'a dyn Hittable,
+    pub lights: &'a LightList,
+    /// What rays that escape the scene see
+    pub background: &'a dyn Background,
+}
+
+impl SceneView<'_> {
+    /// Closest hit along `ray`, ignoring the surface the ray starts on
+    #[must_use]
+    pub fn hit(&self, ray: &Ray<f64>) -> Option<HitRecord> {
+        self.world
+            .hit(ray, Interval::new(MIN_HIT_DISTANCE, f64::INFINITY))
+    }
+}
+
+pub trait Integrator: Sync {
+    /// Color seen along a camera ray, one sample of the pixel it goes through, with
+    /// every random choice along the way drawn from `rng`
+    fn radiance(&self, ray: Ray<f64>, scene: &SceneView<'_>, rng: &mut Rng<'_>) -> Color;
+}
+
+/// Integrators that can be picked by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegratorKind {
+    /// Full global illumination, see `path::PathTracer`
+    #[default]
+    Path,
+    Normals,
+    Depth,
+    Albedo,
+    AmbientOcclusion,
+}
+
+impl IntegratorKind {
+    /// Names accepted by `from_str`
+    pub const NAMES: [&str; 5] = ["path", "normals", "depth", "albedo", "ambient-occlusion"];
+}
+
+impl fmt::Display for IntegratorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = match self {
+            Self::Path => 0,
+            Self::Normals => 1,
+            Self::Depth => 2,
+            Self::Albedo => 3,
+            Self::AmbientOcclusion => 4,
+        };
+        f.write_str(Self::NAMES[index])
+    }
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "path" => Ok(Self::Path),
+            "normals" => Ok(Self::Normals),
+            "depth" => Ok(Self::Depth),
+            "albedo" => Ok(Self::Albedo),
+            "ambient-occlusion" => Ok(Self::AmbientOcclusion),
+            _ => Err(format!(
+                "unknown integrator {name:?}, expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Which integrator to render with and how it's set up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntegratorSettings {
+    pub kind: IntegratorKind,
+    /// Most bounces a path makes before giving up on it
+    pub max_depth: isize,
+    /// Bounces every path makes before Russian roulette may end it early
+    pub roulette_depth: isize,
+    /// Shadow rays traced per sample for ambient occlusion
+    pub occlusion_samples: isize,
+    /// How far away a surface can be and still occlude
+    pub occlusion_distance: f64,
+}
+
+impl Default for IntegratorSettings {
+    fn default() -> Self {
+        Self {
+            kind: IntegratorKind::default(),
+            max_depth: 50,
+            roulette_depth: 5,
+            occlusion_samples: 1,
+            occlusion_distance: f64::INFINITY,
+        }
+    }
+}
+
+impl IntegratorSettings {
+    #[must_use]
+    pub fn build(&self) -> Box<dyn Integrator> {
+        match self.kind {
+            IntegratorKind::Path => {
+                Box::new(path::PathTracer::new(self.max_depth, self.roulette_depth))
+            }
+            IntegratorKind::Normals => Box::new(debug::NormalIntegrator),
+            IntegratorKind::Depth => Box::new(debug::DepthIntegrator),
+            IntegratorKind::Albedo => Box::new(debug::AlbedoIntegrator),
+            IntegratorKind::AmbientOcclusion => Box::new(debug::AmbientOcclusionIntegrator::new(
+                self.occlusion_samples,
+                self.occlusion_distance,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for name in IntegratorKind::NAMES {
+            assert_eq!(name.parse::<IntegratorKind>().unwrap().to_string(), name);
+        }
+        assert!("whitted".parse::<IntegratorKind>().is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! Unidirectional path tracing with next event estimation
+
+use super::{Integrator, SceneView};
+use crate::{
+    math_utils::{
+        pdf::Pdf,
+        random::{BounceStage, Rng},
+        ray::Ray,
+    },
+    ray_tracing::{
+        color::Color,
+        lights::{LightPdf, power_heuristic},
+        materials::material::Scatter,
+    },
+};
+
+/// Follows each ray bounce by bounce, sampling the lights directly at every diffuse
+/// hit and blending that with the bounce itself by multiple importance sampling
+pub struct PathTracer {
+    max_depth: isize,
+    roulette_depth: isize,
+}
+
+impl PathTracer {
+    /// Paths end after `max_depth` bounces, and from `roulette_depth` on dim ones are
+    /// ended early at random
+    #[must_use]
+    pub const fn new(max_depth: isize, roulette_depth: isize) -> Self {
+        Self {
+            max_depth,
+            roulette_depth,
+        }
+    }
+
+    /// Light seen along a shadow ray from the lights, without following it any further
+    ///
+    /// Anything else in the way blocks it, even when it glows itself, since only the
+    /// bounces find the light of shapes that aren't sampled.
+    fn direct_light(ray: &Ray<f64>, scene: &SceneView<'_>) -> Color {
+        scene
+            .hit(ray)
+            .filter(|hit_record| scene.lights.contains_hit(ray, hit_record))
+            .map_or_else(Color::black, |hit_record| {
+                hit_record.material.emitted(&hit_record)
+            })
+    }
+}
+
+impl Integrator for PathTracer {
+    /// `throughput` is how much of the light found at the current bounce makes it back
+    /// to the camera. After `roulette_depth` bounces, dim paths are ended at random
+    /// and the survivors brightened to make up for it, which keeps the estimate
+    /// unbiased while spending little time on paths that carry almost nothing.
+    fn radiance(&self, ray: Ray<f64>, scene: &SceneView<'_>, rng: &mut Rng<'_>) -> Color {
+        let mut ray = ray;
+        let mut color = Color::black();
+        let mut throughput = Color::white();
+        // how the bounce before sampled the lights and the direction it took, which
+        // already counted some of the light the ray finds if it hits one of them
+        let mut light_sampling: Option<(LightPdf<'_>, f64)> = None;
+
+        for depth in 0..self.max_depth {
+            let bounce = depth.unsigned_abs();
+            // hit nothing, so grab the background color (diffuse light source)
+            let Some(hit_record) = scene.hit(&ray) else {
+                color += throughput * scene.background.color(&ray);
+                break;
+            };
+
+            // pick up any light the surface gives off itself, only shared with light
+            // sampling when the surface is one of the lights
+            let emission_weight = match &light_sampling {
+                Some((light_pdf, pdf_value))
+                    if hit_record.material.is_emissive()
+                        && scene.lights.contains_hit(&ray, &hit_record) =>
+                {
+                    power_heuristic(*pdf_value, light_pdf.value(ray.get_direction()))
+                }
+                _ => 1.0,
+            };
+            color += throughput * (emission_weight * hit_record.material.emitted(&hit_record));
+
+            // then scatter off that something
+            rng.start_stage(bounce, BounceStage::Scatter);
+            match hit_record.material.scatter(ray, &hit_record, rng) {
+                Some(Scatter::Specular {
+                    ray: scattered_ray,
+                    attenuation,
+                }) => {
+                    throughput = throughput * attenuation;
+                    light_sampling = None;
+                    ray = scattered_ray;
+                }
+                Some(Scatter::Diffuse { attenuation, pdf }) => {
+                    // a shadow ray only pays off if the next bounce could also find the
+                    // light, otherwise the two samples don't add up
+                    let light_pdf = if depth + 1 < self.max_depth {
+                        scene.lights.pdf(hit_record.point, ray.get_time())
+                    } else {
+                        None
+                    };
+
+                    // next event estimation: aim straight at a light
+                    if let Some(light_pdf) = &light_pdf {
+                        rng.start_stage(bounce, BounceStage::Light);
+                        let shadow_ray = Ray::new_at_time(
+                            hit_record.point,
+                            light_pdf.generate(rng),
+                            ray.get_time(),
+                        );
+                        let light_value = light_pdf.value(shadow_ray.get_direction());
+                        let scattering_pdf =
+                            hit_record
+                                .material
+                                .scattering_pdf(&ray, &hit_record, &shadow_ray);
+                        if light_value > 0.0 && scattering_pdf > 0.0 {
+                            let weight =
+                                power_heuristic(light_value, pdf.value(shadow_ray.get_direction()));
+                            let incoming = Self::direct_light(&shadow_ray, scene);
+                            color += (weight * scattering_pdf / light_value)
+                                * (throughput * attenuation * incoming);
+                        }
+                    }
+
+                    // Monte Carlo estimate of the light scattered towards us: sample one
+                    // direction from the pdf and divide by how likely it was
+                    rng.start_stage(bounce, BounceStage::Scatter);
+                    let scattered_ray =
+                        Ray::new_at_time(hit_record.point, pdf.generate(rng), ray.get_time());
+                    let pdf_value = pdf.value(scattered_ray.get_direction());
+                    if pdf_value <= 0.0 {
+                        break;
+                    }
+                    let scattering_pdf =
+                        hit_record
+                            .material
+                            .scattering_pdf(&ray, &hit_record, &scattered_ray);
+                    throughput = (scattering_pdf / pdf_value) * (throughput * attenuation);
+                    light_sampling = light_pdf.map(|light_pdf| (light_pdf, pdf_value));
+                    ray = scattered_ray;
+                }
+                // no way to scatter, the ray is absorbed and only the emitted light remains
+                None => break,
+            }
+
+            if depth + 1 >= self.roulette_depth {
+                let survival = throughput.max_channel().min(1.0);
+                rng.start_stage(bounce, BounceStage::Roulette);
+                if survival <= 0.0 || rng.get_1d() >= survival {
+                    break;
+                }
+                throughput *= 1.0 / survival;
+            }
+        }
+
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        math_utils::{point::Point, vector::Vec3},
+        ray_tracing::{
+            background::SolidBackground,
+            lights::{Light, LightList},
+            materials::{
+                dielectric::DielectricMaterial, diffuse_light::DiffuseLightMaterial,
+                lambertian::LambertianMaterial, material::Scatterer, metal::MetalMaterial,
+            },
+            shapes::{group::Group, quad::Quad, sphere::Sphere},
+        },
+    };
+
+    #[test]
+    fn test_white_furnace() {
+        // a convex diffuse object under an even sky sends every bounce straight back
+        // out, so each sample sees exactly its albedo
+        let mut world = Group::new(Vec::new());
+        world.add(Box::new(Sphere::new(
+            Point::new(0.0, 0.0, -2.0),
+            1.0,
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap())),
+        )));
+        let background = SolidBackground::new(Color::white());
+        let lights = LightList::default();
+        let scene = SceneView {
+            world: &world,
+            lights: &lights,
+            background: &background,
+        };
+
+        let mut rng = Rng::new(8);
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        for _ in 0..100 {
+            let color = PathTracer::new(50, 5).radiance(ray, &scene, &mut rng);
+            assert!((color.green - 0.5).abs() < 1e-12, "{color:?}");
+        }
+    }
+
+    /// Two facing plates of `material`, 2 apart around the origin, with the camera ray
+    /// between them looking at the lower one
+    fn plates(material: &Arc<dyn Scatterer + Send + Sync>) -> Group {
+        let mut world = Group::new(Vec::new());
+        for y in [-1.0, 1.0] {
+            world.add(Box::new(Quad::new(
+                Point::new(-5.0, y, -5.0),
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 10.0),
+                Arc::clone(material),
+            )));
+        }
+        world
+    }
+
+    #[test]
+    fn test_roulette_keeps_the_mean() {
+        // an even sky seen after many bounces between two matte plates, some of them
+        // through a glass ball
+        let matte: Arc<dyn Scatterer + Send + Sync> =
+            Arc::new(LambertianMaterial::new(Color::new(0.8, 0.8, 0.8).unwrap()));
+        let mut world = plates(&matte);
+        world.add(Box::new(Sphere::new(
+            Point::new(0.0, 0.0, -1.5),
+            0.5,
+            Arc::new(DielectricMaterial::new(1.5)),
+        )));
+        let background = SolidBackground::new(Color::white());
+        let lights = LightList::default();
+        let scene = SceneView {
+            world: &world,
+            lights: &lights,
+            background: &background,
+        };
+
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, -0.6, -1.0));
+        let average = |integrator: PathTracer, seed| {
+            let mut rng = Rng::new(seed);
+            let samples = 40_000;
+            let total: f64 = (0..samples)
+                .map(|_| integrator.radiance(ray, &scene, &mut rng).green)
+                .sum();
+            total / f64::from(samples)
+        };
+        let without = average(PathTracer::new(200, 200), 10);
+        let with = average(PathTracer::new(200, 1), 11);
+        assert!(without < 0.9, "{without}");
+        assert!(
+            (with - without).abs() < 0.02 * without,
+            "{with} vs {without}"
+        );
+    }
+
+    #[test]
+    fn test_deep_paths_stay_on_the_heap() {
+        // a ray bouncing between two facing mirrors never escapes, so it runs for every
+        // one of the bounces it's allowed
+        let mirror: Arc<dyn Scatterer + Send + Sync> = Arc::new(MetalMaterial::new(Color::white()));
+        let world = plates(&mirror);
+        let background = SolidBackground::new(Color::white());
+        let lights = LightList::default();
+        let scene = SceneView {
+            world: &world,
+            lights: &lights,
+            background: &background,
+        };
+
+        let mut rng = Rng::new(12);
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let max_depth = 100_000;
+        let color = PathTracer::new(max_depth, max_depth).radiance(ray, &scene, &mut rng);
+        assert_eq!(color, Color::black());
+    }
+
+    #[test]
+    fn test_light_sampling_matches_plain_bounces() {
+        // a floor lit by a small lamp above it, with and without sampling the lamp
+        let mut world = Group::new(Vec::new());
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -5.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Arc::new(LambertianMaterial::new(Color::new(0.8, 0.8, 0.8).unwrap())),
+        )));
+        let lamp: Arc<dyn Light + Send + Sync> = Arc::new(Quad::new(
+            Point::new(-0.5, 1.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Arc::new(DiffuseLightMaterial::new(Color::white(), 4.0)),
+        ));
+        world.add(Box::new(Arc::clone(&lamp)));
+        let background = SolidBackground::new(Color::black());
+        let mut lights = LightList::default();
+        lights.add(lamp);
+
+        let ray = Ray::new(Point::new(0.3, 0.5, 0.2), Vec3::new(0.0, -1.0, 0.0));
+        let average = |lights: &LightList, rng: &mut Rng<'_>| {
+            let scene = SceneView {
+                world: &world,
+                lights,
+                background: &background,
+            };
+            let samples = 40_000;
+            let total: f64 = (0..samples)
+                .map(|_| PathTracer::new(2, 2).radiance(ray, &scene, rng).red)
+                .sum();
+            total / f64::from(samples)
+        };
+
+        let mut rng = Rng::new(9);
+        let sampled = average(&lights, &mut rng);
+        let plain = average(&LightList::default(), &mut rng);
+        assert!(
+            (sampled - plain).abs() < 0.05 * plain,
+            "{sampled} vs {plain}"
+        );
+    }
+
+    #[test]
+    fn test_unsampled_emitters_in_front_of_lights_count_once() {
+        // a glowing plate that isn't sampled hangs under half of a lamp that is
+        let mut world = Group::new(Vec::new());
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -5.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Arc::new(LambertianMaterial::new(Color::new(0.8, 0.8, 0.8).unwrap())),
+        )));
+        world.add(Box::new(Quad::new(
+            Point::new(-0.5, 0.9, -0.5),
+            Vec3::new(0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Arc::new(DiffuseLightMaterial::new(Color::white(), 2.0)),
+        )));
+        let lamp: Arc<dyn Light + Send + Sync> = Arc::new(Quad::new(
+            Point::new(-0.5, 1.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Arc::new(DiffuseLightMaterial::new(Color::white(), 4.0)),
+        ));
+        world.add(Box::new(Arc::clone(&lamp)));
+        let background = SolidBackground::new(Color::black());
+        let mut lights = LightList::default();
+        lights.add(lamp);
+
+        let ray = Ray::new(Point::new(0.3, 0.5, 0.2), Vec3::new(0.0, -1.0, 0.0));
+        let average = |lights: &LightList, rng: &mut Rng<'_>| {
+            let scene = SceneView {
+                world: &world,
+                lights,
+                background: &background,
+            };
+            let samples = 40_000;
+            let total: f64 = (0..samples)
+                .map(|_| PathTracer::new(2, 2).radiance(ray, &scene, rng).red)
+                .sum();
+            total / f64::from(samples)
+        };
+
+        let mut rng = Rng::new(13);
+        let sampled = average(&lights, &mut rng);
+        let plain = average(&LightList::default(), &mut rng);
+        assert!(
+            (sampled - plain).abs() < 0.05 * plain,
+            "{sampled} vs {plain}"
+        );
+    }
+}
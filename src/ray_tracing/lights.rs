@@ -0,0 +1,186 @@
+//! The emissive objects of a scene, for sampling light directly
+//!
+//! A small light is rarely found by bouncing rays at random, so at every diffuse hit
+//! the camera also aims a shadow ray at a random point on a random light. The two
+//! ways of finding light are blended with multiple importance sampling, see
+//! `power_heuristic`.
+
+use std::sync::Arc;
+
+use crate::{
+    math_utils::{interval::Interval, pdf::Pdf, point::Point, random::Rng, ray::Ray, vector::Vec3},
+    ray_tracing::{
+        integrators::MIN_HIT_DISTANCE,
+        shapes::hittable::{HitRecord, Hittable},
+    },
+};
+
+/// Objects that can be sampled directly, which anything in a `LightList` has to be
+pub trait Light: Hittable {
+    /// Density of `random_direction` picking `direction` from `origin` at `time`, per
+    /// unit solid angle
+    fn pdf_value(&self, origin: &Point<f64>, direction: &Vec3<f64>, time: f64) -> f64;
+
+    /// Direction from `origin` towards a random point on the object, where it is at
+    /// `time`
+    fn random_direction(&self, origin: &Point<f64>, time: f64, rng: &mut Rng<'_>) -> Vec3<f64>;
+}
+
+/// Objects that give off light and can be sampled, shared with the world
+#[derive(Default, Clone)]
+pub struct LightList {
+    lights: Vec<Arc<dyn Light + Send + Sync>>,
+}
+
+impl LightList {
+    pub fn add(&mut self, light: Arc<dyn Light + Send + Sync>) {
+        self.lights.push(light);
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Whether `hit_record`, the closest hit along `ray`, is on one of the lights
+    #[must_use]
+    pub fn contains_hit(&self, ray: &Ray<f64>, hit_record: &HitRecord) -> bool {
+        // the lights are part of the world, so one hit no further away than the world's
+        // closest hit is the surface that was hit
+        let interval = Interval::new(MIN_HIT_DISTANCE, hit_record.t + MIN_HIT_DISTANCE);
+        self.lights
+            .iter()
+            .any(|light| light.hit(ray, interval).is_some())
+    }
+
+    /// Density over directions from `origin` towards the lights as they are at `time`,
+    /// `None` without any
+    #[must_use]
+    pub fn pdf(&self, origin: Point<f64>, time: f64) -> Option<LightPdf<'_>> {
+        (!self.is_empty()).then_some(LightPdf {
+            lights: &self.lights,
+            origin,
+            time,
+        })
+    }
+}
+
+/// Picks a light uniformly, then a direction towards it
+pub struct LightPdf<'a> {
+    lights: &'a [Arc<dyn Light + Send + Sync>],
+    origin: Point<f64>,
+    time: f64,
+}
+
+impl Pdf for LightPdf<'_> {
+    /// Lights can overlap as seen from the origin, so every light that could have
+    /// produced the direction counts
+    #[allow(clippy::cast_precision_loss)] // light counts are far below 2^52
+    fn value(&self, direction: &Vec3<f64>) -> f64 {
+        let total: f64 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf_value(&self.origin, direction, self.time))
+            .sum();
+        total / self.lights.len() as f64
+    }
+
+    fn generate(&self, rng: &mut Rng<'_>) -> Vec3<f64> {
+        // rounding can push the product up to the count itself
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )] // light counts are far below 2^52
+        let index = ((rng.get_1d() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        let light = &self.lights[index];
+        light.random_direction(&self.origin, self.time, rng)
+    }
+}
+
+/// Weight for a sample picked with density `chosen` when `other` could also have
+/// picked it, squaring favors whichever strategy is much more confident
+#[must_use]
+pub fn power_heuristic(chosen: f64, other: f64) -> f64 {
+    let (chosen, other) = (chosen * chosen, other * other);
+    if chosen + other > 0.0 {
+        chosen / (chosen + other)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math_utils::pdf::SpherePdf,
+        ray_tracing::{
+            color::Color,
+            materials::diffuse_light::DiffuseLightMaterial,
+            shapes::{quad::Quad, sphere::Sphere},
+        },
+    };
+
+    fn lights() -> LightList {
+        let material = Arc::new(DiffuseLightMaterial::new(Color::black(), 1.0));
+        let mut lights = LightList::default();
+        lights.add(Arc::new(Sphere::new(
+            Point::new(0.0, 3.0, 0.0),
+            1.0,
+            Arc::clone(&material) as _,
+        )));
+        lights.add(Arc::new(Quad::new(
+            Point::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material,
+        )));
+        lights
+    }
+
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        let mut rng = Rng::new(5);
+        let lights = lights();
+        let pdf = lights.pdf(Point::new(0.0, 0.0, 0.0), 0.0).unwrap();
+        let sphere = SpherePdf;
+        let samples = 400_000;
+        let total: f64 = (0..samples)
+            .map(|_| {
+                let direction = sphere.generate(&mut rng);
+                pdf.value(&direction) / sphere.value(&direction)
+            })
+            .sum();
+        let integral = total / f64::from(samples);
+        assert!((integral - 1.0).abs() < 0.03, "{integral}");
+    }
+
+    #[test]
+    fn test_generated_directions_have_density() {
+        let mut rng = Rng::new(6);
+        let lights = lights();
+        let pdf = lights.pdf(Point::new(0.0, 0.0, 0.0), 0.0).unwrap();
+        for _ in 0..200 {
+            assert!(pdf.value(&pdf.generate(&mut rng)) > 0.0);
+        }
+        assert!(
+            LightList::default()
+                .pdf(Point::new(0.0, 0.0, 0.0), 0.0)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_power_heuristic() {
+        assert!((power_heuristic(1.0, 1.0) - 0.5).abs() < 1e-12);
+        assert!((power_heuristic(3.0, 1.0) - 0.9).abs() < 1e-12);
+        assert!((power_heuristic(2.0, 0.5) + power_heuristic(0.5, 2.0) - 1.0).abs() < 1e-12);
+        assert!(power_heuristic(0.0, 0.0).abs() < f64::EPSILON);
+    }
+}
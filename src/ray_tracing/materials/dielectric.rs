@@ -3,8 +3,12 @@
 use num_traits::Pow;
 
 use crate::{
-    math_utils::ray::Ray,
-    ray_tracing::{color::Color, materials::material::Scatterer, shapes::hittable::HitRecord},
+    math_utils::{random::Rng, ray::Ray},
+    ray_tracing::{
+        color::Color,
+        materials::material::{Scatter, Scatterer},
+        shapes::hittable::HitRecord,
+    },
 };
 
 pub struct DielectricMaterial {
@@ -25,7 +29,12 @@ impl DielectricMaterial {
 }
 
 impl Scatterer for DielectricMaterial {
-    fn scatter(&self, ray_in: Ray<f64>, hit_record: &HitRecord) -> Option<(Ray<f64>, Color)> {
+    fn scatter(
+        &self,
+        ray_in: Ray<f64>,
+        hit_record: &HitRecord,
+        rng: &mut Rng<'_>,
+    ) -> Option<Scatter> {
         let attenuation = Color::new(1.0, 1.0, 1.0).unwrap();
         let ri = if hit_record.front_face {
             1.0 / self.refraction_index
@@ -40,7 +49,7 @@ impl Scatterer for DielectricMaterial {
 
         let cannot_refract = ri * sin_theta > 1.0;
 
-        let direction = if cannot_refract || DielectricMaterial::reflectance(cos_theta, ri) > rand::random() {
+        let direction = if cannot_refract || DielectricMaterial::reflectance(cos_theta, ri) > rng.get_1d() {
             // cannot refract
             unit_direction.reflect(&hit_record.normal)
         } else {
@@ -49,6 +58,14 @@ impl Scatterer for DielectricMaterial {
 
         let scattered = Ray::new_at_time(hit_record.point, direction, ray_in.get_time());
 
-        Some((scattered, attenuation))
+        Some(Scatter::Specular {
+            ray: scattered,
+            attenuation,
+        })
+    }
+
+    /// Clear glass lets all light through
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        Color::white()
     }
 }
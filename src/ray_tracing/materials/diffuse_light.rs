@@ -0,0 +1,50 @@
+//! Light emitting material
+
+use crate::{
+    math_utils::{random::Rng, ray::Ray},
+    ray_tracing::{
+        color::Color,
+        materials::material::{Scatter, Scatterer},
+        shapes::hittable::HitRecord,
+    },
+};
+
+/// Emits the same light in every direction from both sides of the surface
+/// and absorbs everything that hits it
+pub struct DiffuseLightMaterial {
+    emit: Color,
+}
+
+impl DiffuseLightMaterial {
+    /// `intensity` scales the color, so lights can be brighter than a color channel allows
+    #[must_use]
+    pub fn new(color: Color, intensity: f64) -> Self {
+        Self {
+            emit: intensity * color,
+        }
+    }
+}
+
+impl Scatterer for DiffuseLightMaterial {
+    fn scatter(
+        &self,
+        _ray_in: Ray<f64>,
+        _hit_record: &HitRecord,
+        _rng: &mut Rng<'_>,
+    ) -> Option<Scatter> {
+        None
+    }
+
+    /// The light's own color, so lights stand out in albedo images
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.emit.clamped()
+    }
+
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
+        self.emit
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.emit != Color::black()
+    }
+}
@@ -1,6 +1,12 @@
+use std::f64::consts::PI;
+
 use crate::{
-    math_utils::{ray::Ray, vector::Vec3},
-    ray_tracing::{color::Color, materials::material::Scatterer, shapes::hittable::HitRecord},
+    math_utils::{pdf::CosinePdf, random::Rng, ray::Ray},
+    ray_tracing::{
+        color::Color,
+        materials::material::{Scatter, Scatterer},
+        shapes::hittable::HitRecord,
+    },
 };
 
 /// Lambertian diffuse material
@@ -18,22 +24,30 @@ impl LambertianMaterial {
 impl Scatterer for LambertianMaterial {
     fn scatter(
         &self,
-        r_in: Ray<f64>, // don't need in ray for lambertian reflection
+        _ray_in: Ray<f64>, // don't need in ray for lambertian reflection
         hit_record: &HitRecord,
-    ) -> Option<(Ray<f64>, Color)> {
-        let random_unit_vector = Vec3::random_unit_vector_same_hemisphere(&hit_record.normal);
-        let scatter_direction = hit_record.normal + random_unit_vector;
+        _rng: &mut Rng<'_>, // the direction is picked from the pdf later
+    ) -> Option<Scatter> {
+        Some(Scatter::Diffuse {
+            attenuation: self.albedo,
+            pdf: Box::new(CosinePdf::new(&hit_record.normal)),
+        })
+    }
 
-        // handle cases where vector is essentially zero by defaulting to the normal
-        let scatter_direction = if scatter_direction.near_zero() {
-            hit_record.normal
-        } else {
-            scatter_direction
+    /// Light leaves in proportion to the cosine of its angle to the normal
+    fn scattering_pdf(
+        &self,
+        _ray_in: &Ray<f64>,
+        hit_record: &HitRecord,
+        scattered: &Ray<f64>,
+    ) -> f64 {
+        let Ok(direction) = scattered.get_direction().normalize() else {
+            return 0.0;
         };
+        (hit_record.normal.dot(&direction) / PI).max(0.0)
+    }
 
-        let scattered_ray = Ray::new_at_time(hit_record.point, scatter_direction, r_in.get_time());
-        let attenuation = self.albedo;
-
-        Some((scattered_ray, attenuation))
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.albedo
     }
 }
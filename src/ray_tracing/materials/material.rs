@@ -1,8 +1,55 @@
 use crate::{
-    math_utils::ray::Ray,
+    math_utils::{pdf::Pdf, random::Rng, ray::Ray},
     ray_tracing::{color::Color, shapes::hittable::HitRecord},
 };
 
+/// Where the light arriving at a hit point came from
+pub enum Scatter {
+    /// A single direction fixed by the surface, eg. a mirror reflection, followed as is
+    /// with no density to divide by
+    Specular { ray: Ray<f64>, attenuation: Color },
+    /// Spread over many directions, the integrator picks one from `pdf` and weights it
+    /// by the material's `scattering_pdf`
+    Diffuse {
+        attenuation: Color,
+        pdf: Box<dyn Pdf>,
+    },
+}
+
 pub trait Scatterer: Sync {
-    fn scatter(&self, ray_in: Ray<f64>, hit_record: &HitRecord) -> Option<(Ray<f64>, Color)>;
+    /// How an incoming ray leaves the surface, `None` if it's absorbed
+    fn scatter(
+        &self,
+        ray_in: Ray<f64>,
+        hit_record: &HitRecord,
+        rng: &mut Rng<'_>,
+    ) -> Option<Scatter>;
+
+    /// Density the surface itself scatters `ray_in` into `scattered` with, per unit
+    /// solid angle, only used for `Scatter::Diffuse`
+    fn scattering_pdf(
+        &self,
+        _ray_in: &Ray<f64>,
+        _hit_record: &HitRecord,
+        _scattered: &Ray<f64>,
+    ) -> f64 {
+        0.0
+    }
+
+    /// Fraction of light the surface reflects, whatever the directions involved,
+    /// used for albedo images rather than for lighting
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        Color::black()
+    }
+
+    /// Light given off at the hit point, black for anything that isn't a light source
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
+        Color::black()
+    }
+
+    /// Whether `emitted` can give off any light, so objects made of it are worth
+    /// sampling as lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
 }
@@ -1,6 +1,10 @@
 use crate::{
-    math_utils::ray::Ray,
-    ray_tracing::{color::Color, materials::material::Scatterer, shapes::hittable::HitRecord},
+    math_utils::{random::Rng, ray::Ray},
+    ray_tracing::{
+        color::Color,
+        materials::material::{Scatter, Scatterer},
+        shapes::hittable::HitRecord,
+    },
 };
 
 pub struct MetalMaterial {
@@ -14,11 +18,23 @@ impl MetalMaterial {
 }
 
 impl Scatterer for MetalMaterial {
-    fn scatter(&self, ray_in: Ray<f64>, hit_record: &HitRecord) -> Option<(Ray<f64>, Color)> {
+    fn scatter(
+        &self,
+        ray_in: Ray<f64>,
+        hit_record: &HitRecord,
+        _rng: &mut Rng<'_>,
+    ) -> Option<Scatter> {
         let reflection_direction = ray_in.get_direction().reflect(&hit_record.normal);
         let scattered_ray = Ray::new_at_time(hit_record.point, reflection_direction, ray_in.get_time());
         let attenuation = self.albedo;
 
-        Some((scattered_ray, attenuation))
+        Some(Scatter::Specular {
+            ray: scattered_ray,
+            attenuation,
+        })
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.albedo
     }
 }
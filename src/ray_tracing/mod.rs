@@ -1,4 +1,8 @@
+pub mod aov;
+pub mod background;
 pub mod camera;
 pub mod color;
+pub mod integrators;
+pub mod lights;
 pub mod materials;
 pub mod shapes;
@@ -0,0 +1,632 @@
+//! Bounding volume hierarchy stored as a flat array of nodes
+//!
+//! Nodes are laid out depth first, so the first child of an interior node always sits
+//! right after it and only the second child needs an index. Traversal walks the array
+//! with a small fixed stack instead of recursing through `Box<dyn Hittable>` children.
+//!
+//! `BvhTree` only knows about bounding boxes, `FlatBvh` wraps it around a set of objects.
+
+use std::fmt;
+
+use super::{
+    group::Group,
+    hittable::{HitRecord, Hittable},
+};
+use crate::math_utils::{aabb::Aabb, interval::Interval, point::Point, ray::Ray};
+
+/// Largest number of objects the builders will put in a single leaf
+const MAX_PRIMITIVES_IN_LEAF: usize = 4;
+/// Cost of testing a ray against a node's box, relative to testing one object
+const TRAVERSAL_COST: f64 = 0.125;
+const INTERSECTION_COST: f64 = 1.0;
+/// Size of the traversal stack, the tree is never built deeper than this
+const MAX_DEPTH: usize = 64;
+/// Past this depth the SAH builder gives up and splits at the median, which
+/// bounds the remaining depth to log2 of the objects left
+const MEDIAN_FALLBACK_DEPTH: usize = 32;
+
+/// How the builder decides where to split a set of objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BvhSplit {
+    /// Split in half at the median centroid along the widest axis
+    Median,
+    /// Bucket centroids into `bins` slabs per axis and pick the split
+    /// with the lowest surface area heuristic cost
+    Sah { bins: u32 },
+}
+
+impl Default for BvhSplit {
+    fn default() -> Self {
+        Self::Sah { bins: 12 }
+    }
+}
+
+/// Summary of a built tree, used to compare builders on the same input
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BvhStats {
+    pub primitive_count: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Depth of the deepest leaf, the root is at depth 0
+    pub max_depth: usize,
+    /// Expected cost of a random ray through the tree, lower is better
+    pub sah_cost: f64,
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} primitives, {} nodes ({} leaves), depth {}, SAH cost {:.3}",
+            self.primitive_count, self.node_count, self.leaf_count, self.max_depth, self.sah_cost
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FlatNode {
+    bbox: Aabb,
+    /// First object of a leaf, or the index of the second child of an interior node
+    offset: u32,
+    /// Zero for interior nodes
+    primitive_count: u16,
+    /// Axis an interior node was split along, used to visit the nearer child first
+    axis: u8,
+}
+
+impl FlatNode {
+    const fn is_leaf(&self) -> bool {
+        self.primitive_count > 0
+    }
+}
+
+/// What the builder needs to know about an object, so the boxes are only computed once
+struct BuildPrimitive {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point<f64>,
+}
+
+impl BuildPrimitive {
+    const fn centroid_on_axis(&self, axis: usize) -> f64 {
+        match axis {
+            0 => self.centroid.x,
+            1 => self.centroid.y,
+            _ => self.centroid.z,
+        }
+    }
+}
+
+/// Node array over primitives known only by their bounding boxes
+///
+/// Shared by `FlatBvh` and `TriangleMesh`: the owner keeps its primitives in
+/// `primitive_order` and tells the tree how to intersect one by its position.
+pub struct BvhTree {
+    nodes: Vec<FlatNode>,
+    /// Original index of each primitive, in the order the leaves refer to them
+    primitive_order: Vec<usize>,
+    stats: BvhStats,
+}
+
+impl BvhTree {
+    #[must_use]
+    pub fn new(bounds: &[Aabb], split: BvhSplit) -> Self {
+        let mut primitives: Vec<BuildPrimitive> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, bbox)| BuildPrimitive {
+                index,
+                bbox: *bbox,
+                centroid: bbox.centroid(),
+            })
+            .collect();
+
+        let mut builder = Builder {
+            split,
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            max_depth: 0,
+        };
+        if !primitives.is_empty() {
+            builder.build(&mut primitives, 0, 0);
+        }
+
+        let stats = BvhStats {
+            primitive_count: primitives.len(),
+            node_count: builder.nodes.len(),
+            leaf_count: builder.nodes.iter().filter(|node| node.is_leaf()).count(),
+            max_depth: builder.max_depth,
+            sah_cost: sah_cost(&builder.nodes),
+        };
+
+        Self {
+            nodes: builder.nodes,
+            primitive_order: primitives.iter().map(|primitive| primitive.index).collect(),
+            stats,
+        }
+    }
+
+    /// Original index of the primitive at each position, the owner should store
+    /// its primitives in this order
+    #[must_use]
+    pub fn primitive_order(&self) -> &[usize] {
+        &self.primitive_order
+    }
+
+    #[must_use]
+    pub const fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    #[must_use]
+    pub fn bounding_box(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or_else(Aabb::empty, |root| root.bbox)
+    }
+
+    /// Find the closest hit, calling `hit_primitive` with the position of every
+    /// primitive whose leaf the ray reaches and the interval still worth searching
+    pub fn hit<F>(
+        &self,
+        ray: &Ray<f64>,
+        ray_interval: Interval,
+        mut hit_primitive: F,
+    ) -> Option<HitRecord>
+    where
+        F: FnMut(usize, Interval) -> Option<HitRecord>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let direction = ray.get_direction();
+        let direction_is_negative = [direction.x < 0.0, direction.y < 0.0, direction.z < 0.0];
+
+        let mut closest_so_far = ray_interval.max;
+        let mut hit_record: Option<HitRecord> = None;
+
+        let mut to_visit = [0_usize; MAX_DEPTH];
+        let mut to_visit_count = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if node
+                .bbox
+                .hit(ray, Interval::new(ray_interval.min, closest_so_far))
+            {
+                if node.is_leaf() {
+                    let first = node.offset as usize;
+                    let last = first + usize::from(node.primitive_count);
+                    for position in first..last {
+                        if let Some(new_hit_record) =
+                            hit_primitive(position, Interval::new(ray_interval.min, closest_so_far))
+                        {
+                            closest_so_far = new_hit_record.t;
+                            hit_record = Some(new_hit_record);
+                        }
+                    }
+                } else {
+                    // visit the child nearer to the ray first, its hits shrink the
+                    // interval and let us skip more of the far one
+                    let first_child = current + 1;
+                    let second_child = node.offset as usize;
+                    let (near, far) = if direction_is_negative[usize::from(node.axis)] {
+                        (second_child, first_child)
+                    } else {
+                        (first_child, second_child)
+                    };
+                    to_visit[to_visit_count] = far;
+                    to_visit_count += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if to_visit_count == 0 {
+                break;
+            }
+            to_visit_count -= 1;
+            current = to_visit[to_visit_count];
+        }
+
+        hit_record
+    }
+}
+
+pub struct FlatBvh {
+    /// Objects in the tree's primitive order, so every leaf refers to a contiguous run
+    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    tree: BvhTree,
+}
+
+impl FlatBvh {
+    #[must_use]
+    pub fn new(group: Group, split: BvhSplit) -> Self {
+        let objects = group.into_objects();
+        let bounds: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let tree = BvhTree::new(&bounds, split);
+
+        let mut objects: Vec<Option<Box<dyn Hittable + Send + Sync>>> =
+            objects.into_iter().map(Some).collect();
+        let objects: Vec<Box<dyn Hittable + Send + Sync>> = tree
+            .primitive_order()
+            .iter()
+            .filter_map(|&index| objects[index].take())
+            .collect();
+
+        Self { objects, tree }
+    }
+
+    #[must_use]
+    pub const fn stats(&self) -> &BvhStats {
+        self.tree.stats()
+    }
+}
+
+impl From<Group> for FlatBvh {
+    fn from(group: Group) -> Self {
+        Self::new(group, BvhSplit::default())
+    }
+}
+
+impl Hittable for FlatBvh {
+    fn hit(&self, ray: &Ray<f64>, ray_interval: Interval) -> Option<HitRecord> {
+        self.tree.hit(ray, ray_interval, |position, interval| {
+            self.objects[position]
+                .hit(ray, interval)
+                .map(|hit_record| HitRecord {
+                    // report the index the object had in the group, not its place in the tree
+                    object_id: self.tree.primitive_order()[position],
+                    ..hit_record
+                })
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.tree.bounding_box()
+    }
+}
+
+struct Builder {
+    split: BvhSplit,
+    nodes: Vec<FlatNode>,
+    max_depth: usize,
+}
+
+impl Builder {
+    /// Append the subtree for `primitives` to the node array, reordering `primitives`
+    /// into leaf order. `first` is where the slice starts in the full primitive list.
+    fn build(&mut self, primitives: &mut [BuildPrimitive], first: usize, depth: usize) {
+        let bbox = primitives.iter().fold(Aabb::empty(), |bbox, primitive| {
+            Aabb::surrounding(&bbox, &primitive.bbox)
+        });
+        self.max_depth = self.max_depth.max(depth);
+
+        let node_index = self.nodes.len();
+        self.nodes.push(FlatNode {
+            bbox,
+            offset: 0,
+            primitive_count: 0,
+            axis: 0,
+        });
+
+        let Some((axis, mid)) = self.choose_split(primitives, &bbox, depth) else {
+            self.make_leaf(node_index, first, primitives.len());
+            return;
+        };
+
+        let (left, right) = primitives.split_at_mut(mid);
+        self.build(left, first, depth + 1);
+        let second_child = self.nodes.len();
+        self.build(right, first + mid, depth + 1);
+
+        self.make_interior(node_index, second_child, axis);
+    }
+
+    // the casts can't truncate: leaves hold a handful of objects, there are 3 axes
+    // and a scene runs out of memory long before it has 4 billion nodes
+    #[allow(clippy::cast_possible_truncation)]
+    fn make_leaf(&mut self, node_index: usize, first: usize, count: usize) {
+        let node = &mut self.nodes[node_index];
+        node.offset = first as u32;
+        node.primitive_count = count as u16;
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn make_interior(&mut self, node_index: usize, second_child: usize, axis: usize) {
+        let node = &mut self.nodes[node_index];
+        node.offset = second_child as u32;
+        node.axis = axis as u8;
+    }
+
+    /// Partition `primitives` and return the split axis and the size of the first half,
+    /// or `None` if they should stay together in a leaf
+    #[allow(clippy::cast_precision_loss)] // object counts are nowhere near 2^52
+    fn choose_split(
+        &self,
+        primitives: &mut [BuildPrimitive],
+        bbox: &Aabb,
+        depth: usize,
+    ) -> Option<(usize, usize)> {
+        let count = primitives.len();
+        if count <= 1 {
+            return None;
+        }
+
+        let centroid_bounds = primitives.iter().fold(Aabb::empty(), |bounds, primitive| {
+            Aabb::surrounding(
+                &bounds,
+                &Aabb::from_points(primitive.centroid, primitive.centroid),
+            )
+        });
+
+        if let BvhSplit::Sah { bins } = self.split
+            && depth < MEDIAN_FALLBACK_DEPTH
+        {
+            match best_sah_split(primitives, bbox, &centroid_bounds, bins) {
+                Some((axis, bin, cost)) => {
+                    // a leaf is cheaper than any split, as long as it isn't too crowded
+                    if count <= MAX_PRIMITIVES_IN_LEAF && count as f64 * INTERSECTION_COST <= cost {
+                        return None;
+                    }
+                    let extent = centroid_bounds.axis_interval(axis);
+                    let mid = partition(primitives, |primitive| {
+                        bin_index(primitive.centroid_on_axis(axis), extent, bins) <= bin
+                    });
+                    return Some((axis, mid));
+                }
+                // every centroid is in the same spot, no plane can separate them
+                None if count <= MAX_PRIMITIVES_IN_LEAF => return None,
+                None => {}
+            }
+        } else if count <= MAX_PRIMITIVES_IN_LEAF {
+            return None;
+        }
+
+        let axis = centroid_bounds.longest_axis();
+        let mid = count / 2;
+        primitives.select_nth_unstable_by(mid, |a, b| {
+            a.centroid_on_axis(axis)
+                .total_cmp(&b.centroid_on_axis(axis))
+        });
+        Some((axis, mid))
+    }
+}
+
+/// Evaluate every bin boundary on every axis and return the cheapest as
+/// `(axis, last bin on the left, cost)`
+#[allow(clippy::cast_precision_loss)] // object counts are nowhere near 2^52
+fn best_sah_split(
+    primitives: &[BuildPrimitive],
+    bbox: &Aabb,
+    centroid_bounds: &Aabb,
+    bins: u32,
+) -> Option<(usize, usize, f64)> {
+    let bin_count = bins.max(2) as usize;
+    let node_area = bbox.surface_area();
+    let mut best: Option<(usize, usize, f64)> = None;
+
+    for axis in 0..3 {
+        let extent = centroid_bounds.axis_interval(axis);
+        if extent.size() <= 0.0 {
+            continue;
+        }
+
+        let mut bin_boxes = vec![Aabb::empty(); bin_count];
+        let mut bin_counts = vec![0_usize; bin_count];
+        for primitive in primitives {
+            let bin = bin_index(primitive.centroid_on_axis(axis), extent, bins);
+            bin_boxes[bin] = Aabb::surrounding(&bin_boxes[bin], &primitive.bbox);
+            bin_counts[bin] += 1;
+        }
+
+        // sweep from the right so each boundary knows the area and count above it
+        let mut right_costs = vec![0.0; bin_count];
+        let mut right_box = Aabb::empty();
+        let mut right_count = 0;
+        for bin in (1..bin_count).rev() {
+            right_box = Aabb::surrounding(&right_box, &bin_boxes[bin]);
+            right_count += bin_counts[bin];
+            right_costs[bin - 1] = right_count as f64 * right_box.surface_area();
+        }
+
+        let mut left_box = Aabb::empty();
+        let mut left_count = 0;
+        for bin in 0..bin_count - 1 {
+            left_box = Aabb::surrounding(&left_box, &bin_boxes[bin]);
+            left_count += bin_counts[bin];
+            if left_count == 0 || left_count == primitives.len() {
+                continue;
+            }
+
+            let cost = INTERSECTION_COST.mul_add(
+                (left_count as f64).mul_add(left_box.surface_area(), right_costs[bin]) / node_area,
+                TRAVERSAL_COST,
+            );
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, bin, cost));
+            }
+        }
+    }
+
+    best
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // clamped to [0, bins)
+fn bin_index(centroid: f64, extent: &Interval, bins: u32) -> usize {
+    let bin_count = bins.max(2);
+    let scaled = (centroid - extent.min) / extent.size() * f64::from(bin_count);
+    (scaled.max(0.0) as usize).min(bin_count as usize - 1)
+}
+
+/// Move everything matching `predicate` to the front and return how many there were
+fn partition<P>(primitives: &mut [BuildPrimitive], predicate: P) -> usize
+where
+    P: Fn(&BuildPrimitive) -> bool,
+{
+    let mut split = 0;
+    for i in 0..primitives.len() {
+        if predicate(&primitives[i]) {
+            primitives.swap(split, i);
+            split += 1;
+        }
+    }
+    split
+}
+
+/// Cost of the finished tree: every node is weighted by the chance a ray
+/// through the root also passes through it, which is the ratio of their areas
+fn sah_cost(nodes: &[FlatNode]) -> f64 {
+    let Some(root_area) = nodes.first().map(|root| root.bbox.surface_area()) else {
+        return 0.0;
+    };
+    if root_area <= 0.0 {
+        return 0.0;
+    }
+
+    nodes
+        .iter()
+        .map(|node| {
+            let node_cost = if node.is_leaf() {
+                f64::from(node.primitive_count) * INTERSECTION_COST
+            } else {
+                TRAVERSAL_COST
+            };
+            node_cost * node.bbox.surface_area() / root_area
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        math_utils::vector::Vec3,
+        ray_tracing::{
+            color::Color,
+            materials::{lambertian::LambertianMaterial, material::Scatterer},
+            shapes::sphere::Sphere,
+        },
+    };
+
+    /// A few tight clusters of small spheres spread far apart, plus a large ground sphere
+    fn clustered_scene() -> Group {
+        let material: Arc<dyn Scatterer + Send + Sync> =
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap()));
+
+        let mut objects: Vec<Box<dyn Hittable + Send + Sync>> = vec![Box::new(Sphere::new(
+            Point::new(0.0, -1000.0, 0.0),
+            995.0,
+            Arc::clone(&material),
+        ))];
+        let cluster_centers = [(-20.0, 0.0, -30.0), (0.5, 0.5, -6.0), (15.0, 4.0, -12.0)];
+        for (cx, cy, cz) in cluster_centers {
+            for i in 0..6_i32 {
+                for j in 0..6_i32 {
+                    let (i, j) = (f64::from(i), f64::from(j));
+                    let center = Point::new(
+                        i.mul_add(0.3, cx),
+                        j.mul_add(0.3, cy),
+                        (i * j).mul_add(0.01, cz),
+                    );
+                    objects.push(Box::new(Sphere::new(center, 0.12, Arc::clone(&material))));
+                }
+            }
+        }
+        Group::new(objects)
+    }
+
+    fn sample_rays() -> Vec<Ray<f64>> {
+        let mut rays = Vec::new();
+        for origin in [Point::new(0.0, 1.0, 5.0), Point::new(-3.0, 2.0, -8.0)] {
+            for a in -40..=40 {
+                for b in -25..=25 {
+                    let direction = Vec3::new(f64::from(a) * 0.02, f64::from(b) * 0.02, -1.0);
+                    rays.push(Ray::new(origin, direction));
+                }
+            }
+        }
+        rays
+    }
+
+    fn assert_matches_group(bvh: &FlatBvh) {
+        let group = clustered_scene();
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let mut hits = 0;
+        for ray in sample_rays() {
+            let expected = group
+                .hit(&ray, interval)
+                .map(|hit| (hit.t, hit.point, hit.normal, hit.object_id));
+            let actual = bvh
+                .hit(&ray, interval)
+                .map(|hit| (hit.t, hit.point, hit.normal, hit.object_id));
+            assert_eq!(expected, actual);
+            hits += usize::from(expected.is_some());
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn test_sah_matches_group() {
+        let bvh = FlatBvh::new(clustered_scene(), BvhSplit::Sah { bins: 12 });
+        assert_eq!(bvh.bounding_box(), clustered_scene().bounding_box());
+        assert_matches_group(&bvh);
+    }
+
+    #[test]
+    fn test_median_matches_group() {
+        assert_matches_group(&FlatBvh::new(clustered_scene(), BvhSplit::Median));
+    }
+
+    #[test]
+    fn test_stats() {
+        let object_count = clustered_scene().len();
+        for split in [BvhSplit::Median, BvhSplit::Sah { bins: 16 }] {
+            let stats = *FlatBvh::new(clustered_scene(), split).stats();
+            assert_eq!(stats.primitive_count, object_count);
+            // a binary tree always has one fewer interior node than leaves
+            assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+            assert!(stats.max_depth < MAX_DEPTH);
+            assert!(stats.sah_cost > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_sah_beats_median_on_clusters() {
+        let median = FlatBvh::new(clustered_scene(), BvhSplit::Median);
+        let sah = FlatBvh::new(clustered_scene(), BvhSplit::Sah { bins: 12 });
+        assert!(sah.stats().sah_cost < median.stats().sah_cost);
+    }
+
+    #[test]
+    fn test_coincident_objects_are_split() {
+        let material: Arc<dyn Scatterer + Send + Sync> =
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap()));
+        let objects: Vec<Box<dyn Hittable + Send + Sync>> = (0..20)
+            .map(|_| {
+                Box::new(Sphere::new(
+                    Point::new(0.0, 0.0, -2.0),
+                    0.5,
+                    Arc::clone(&material),
+                )) as Box<dyn Hittable + Send + Sync>
+            })
+            .collect();
+        let bvh = FlatBvh::new(Group::new(objects), BvhSplit::default());
+        assert!(bvh.stats().leaf_count > 1);
+
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = bvh.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_empty() {
+        let bvh = FlatBvh::from(Group::new(Vec::new()));
+        assert_eq!(bvh.stats().node_count, 0);
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bvh.hit(&ray, Interval::universe()).is_none());
+    }
+}
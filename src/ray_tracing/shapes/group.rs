@@ -1,18 +1,45 @@
 //! Group of hittable objects (generic)
 
 use super::hittable::{HitRecord, Hittable};
-use crate::math_utils::interval::Interval;
+use crate::math_utils::{aabb::Aabb, interval::Interval};
 
 pub struct Group {
     objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    bbox: Aabb,
 }
 
 impl Group {
     pub fn new(hittable_objects: Vec<Box<dyn Hittable + Send + Sync>>) -> Group {
+        let bbox = hittable_objects.iter().fold(Aabb::empty(), |bbox, object| {
+            Aabb::surrounding(&bbox, &object.bounding_box())
+        });
+
         Group {
             objects: hittable_objects,
+            bbox,
         }
     }
+
+    pub fn add(&mut self, object: Box<dyn Hittable + Send + Sync>) {
+        self.bbox = Aabb::surrounding(&self.bbox, &object.bounding_box());
+        self.objects.push(object);
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Give up the group and hand back its objects, eg. to build a BVH from them
+    #[must_use]
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable + Send + Sync>> {
+        self.objects
+    }
 }
 
 impl Hittable for Group {
@@ -22,20 +49,26 @@ impl Hittable for Group {
         ray_interval: Interval,
     ) -> Option<HitRecord> {
         // O(n) check for each object
-        // super inefficient for complex meshes, should use bounding volume hierarchies
+        // fine for a handful of objects, convert to a `FlatBvh` for anything larger
         let mut closest_so_far = ray_interval.max;
         let mut hit_record: Option<HitRecord> = None;
 
-        for object in &self.objects {
-            match object.hit(ray, Interval::new(ray_interval.min, closest_so_far)) {
-                Some(new_hit_record) => {
-                    closest_so_far = new_hit_record.t;
-                    hit_record = Some(new_hit_record);
-                }
-                None => {}
+        for (object_id, object) in self.objects.iter().enumerate() {
+            if let Some(new_hit_record) =
+                object.hit(ray, Interval::new(ray_interval.min, closest_so_far))
+            {
+                closest_so_far = new_hit_record.t;
+                hit_record = Some(HitRecord {
+                    object_id,
+                    ..new_hit_record
+                });
             }
         }
 
         hit_record
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
 }
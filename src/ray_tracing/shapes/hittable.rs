@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use crate::{math_utils::interval::Interval, ray_tracing::materials::material::Scatterer};
+use crate::{
+    math_utils::{aabb::Aabb, interval::Interval},
+    ray_tracing::materials::material::Scatterer,
+};
 
 pub struct HitRecord {
     /// The point where the ray hit the object
@@ -12,8 +15,14 @@ pub struct HitRecord {
     pub t: f64,
     /// Whether the hit was on the front face of the object
     pub front_face: bool,
+    /// Surface coordinates of the hit point, each in [0, 1]
+    pub u: f64,
+    pub v: f64,
     // the material of the object that was hit
     pub material: Arc<dyn Scatterer + Send + Sync>,
+    /// Position of the object in the group or BVH that holds it, which for the world is
+    /// the order shapes appear in the scene. Shapes leave it at 0 for their container
+    pub object_id: usize,
 }
 
 pub trait Hittable: Sync {
@@ -22,4 +31,22 @@ pub trait Hittable: Sync {
         ray: &crate::math_utils::ray::Ray<f64>,
         ray_interval: Interval,
     ) -> Option<HitRecord>;
+
+    /// Box enclosing the object for every ray time in [0, 1]
+    fn bounding_box(&self) -> Aabb;
+}
+
+/// Lets one object be shared, eg. by the world and the list of lights
+impl<T: Hittable + Send + ?Sized> Hittable for Arc<T> {
+    fn hit(
+        &self,
+        ray: &crate::math_utils::ray::Ray<f64>,
+        ray_interval: Interval,
+    ) -> Option<HitRecord> {
+        (**self).hit(ray, ray_interval)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
 }
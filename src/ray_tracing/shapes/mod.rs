@@ -1,5 +1,9 @@
 //! Shapes for ray tracing
 
+pub mod flat_bvh;
 pub mod group;
 pub mod hittable;
+pub mod quad;
 pub mod sphere;
+pub mod triangle;
+pub mod triangle_mesh;
@@ -0,0 +1,261 @@
+//! A flat parallelogram, eg. for walls, floors and area lights
+
+use std::sync::Arc;
+
+use super::hittable::{HitRecord, Hittable};
+use crate::{
+    math_utils::{
+        aabb::Aabb, interval::Interval, point::Point, random::Rng, ray::Ray, vector::Vec3,
+    },
+    ray_tracing::{integrators::MIN_HIT_DISTANCE, lights::Light, materials::material::Scatterer},
+};
+
+/// Spans `corner + a * u + b * v` for `a` and `b` in [0, 1]
+///
+/// The front face is the side `u x v` points to, so listing the edges
+/// counter-clockwise as seen from the front makes the normal face the viewer.
+pub struct Quad {
+    corner: Point<f64>,
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    /// `n / (n . n)` for the unnormalized normal `n`, used to find the hit's
+    /// coordinates along `u` and `v`
+    w: Vec3<f64>,
+    normal: Vec3<f64>,
+    /// Plane constant, every point `p` on the plane has `normal . p = d`
+    d: f64,
+    area: f64,
+    material: Arc<dyn Scatterer + Send + Sync>,
+    bbox: Aabb,
+}
+
+impl Quad {
+    /// # Panics
+    /// If `u` and `v` are parallel, since they wouldn't span a surface
+    pub fn new(
+        corner: Point<f64>,
+        u: Vec3<f64>,
+        v: Vec3<f64>,
+        material: Arc<dyn Scatterer + Send + Sync>,
+    ) -> Self {
+        // flat quads have no thickness along the normal, pad so the box is never empty
+        const MIN_BOX_THICKNESS: f64 = 1e-4;
+
+        let perpendicular = u.cross(&v);
+        let normal = perpendicular
+            .normalize()
+            .expect("quad edges must not be parallel");
+        let plane_constant = normal.dot(&Vec3::new(corner.x, corner.y, corner.z));
+        let scaled_normal = perpendicular / perpendicular.dot(&perpendicular);
+
+        let bbox = Aabb::surrounding(
+            &Aabb::from_points(corner, corner + u + v),
+            &Aabb::from_points(corner + u, corner + v),
+        )
+        .pad_to_minimums(MIN_BOX_THICKNESS);
+
+        Self {
+            corner,
+            u,
+            v,
+            w: scaled_normal,
+            normal,
+            d: plane_constant,
+            area: perpendicular.magnitude(),
+            material,
+            bbox,
+        }
+    }
+
+    #[must_use]
+    pub const fn get_corner(&self) -> Point<f64> {
+        self.corner
+    }
+
+    #[must_use]
+    pub const fn get_normal(&self) -> Vec3<f64> {
+        self.normal
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray<f64>, ray_interval: Interval) -> Option<HitRecord> {
+        // rays running along the plane never cross it
+        const PARALLEL_EPSILON: f64 = 1e-8;
+        let denominator = self.normal.dot(ray.get_direction());
+        if denominator.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+
+        let origin = ray.get_origin();
+        let t = (self.d - self.normal.dot(&Vec3::new(origin.x, origin.y, origin.z))) / denominator;
+        if !ray_interval.surrounds(t) {
+            return None;
+        }
+
+        // express the hit in the (u, v) basis, edges count as inside
+        let point = ray.at(t);
+        let planar_hit = point - self.corner;
+        let alpha = self.w.dot(&planar_hit.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hit));
+        let unit_interval = Interval::new(0.0, 1.0);
+        if !unit_interval.contains(alpha) || !unit_interval.contains(beta) {
+            return None;
+        }
+
+        let front_face = denominator < 0.0;
+        let normal = if front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+
+        Some(HitRecord {
+            point,
+            normal,
+            t,
+            front_face,
+            u: alpha,
+            v: beta,
+            material: Arc::clone(&self.material),
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Light for Quad {
+    /// Points are picked uniformly over the area, which seen from `origin` is a
+    /// density of `distance^2 / (cos * area)` per unit solid angle
+    fn pdf_value(&self, origin: &Point<f64>, direction: &Vec3<f64>, time: f64) -> f64 {
+        let ray = Ray::new_at_time(*origin, *direction, time);
+        let Some(hit) = self.hit(&ray, Interval::new(MIN_HIT_DISTANCE, f64::INFINITY)) else {
+            return 0.0;
+        };
+        let distance_squared = hit.t * hit.t * direction.magnitude_squared();
+        let cosine = (direction.dot(&self.normal) / direction.magnitude()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random_direction(&self, origin: &Point<f64>, _time: f64, rng: &mut Rng<'_>) -> Vec3<f64> {
+        let [a, b] = rng.get_2d();
+        let point = self.corner + a * self.u + b * self.v;
+        point - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_tracing::{color::Color, materials::lambertian::LambertianMaterial};
+
+    /// 2 x 1 quad in the z = -1 plane facing +z, with its corner at the origin
+    fn quad() -> Quad {
+        Quad::new(
+            Point::new(0.0, 0.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap())),
+        )
+    }
+
+    fn ray_from(origin: (f64, f64, f64), direction: (f64, f64, f64)) -> Ray<f64> {
+        Ray::new(
+            Point::new(origin.0, origin.1, origin.2),
+            Vec3::new(direction.0, direction.1, direction.2),
+        )
+    }
+
+    fn positive_interval() -> Interval {
+        Interval::new(0.001, f64::INFINITY)
+    }
+
+    #[test]
+    fn test_hit_front() {
+        let ray = ray_from((1.5, 0.25, 1.0), (0.0, 0.0, -1.0));
+        let hit = quad().hit(&ray, positive_interval()).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-12);
+        assert_eq!(hit.point, Point::new(1.5, 0.25, -1.0));
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((hit.u - 0.75).abs() < 1e-12);
+        assert!((hit.v - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_hit_back() {
+        let ray = ray_from((0.5, 0.5, -3.0), (0.0, 0.0, 1.0));
+        let hit = quad().hit(&ray, positive_interval()).unwrap();
+        assert!(!hit.front_face);
+        // the normal always opposes the ray
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_miss_outside() {
+        for (x, y) in [(-0.1, 0.5), (2.1, 0.5), (1.0, -0.1), (1.0, 1.1)] {
+            let ray = ray_from((x, y, 1.0), (0.0, 0.0, -1.0));
+            assert!(quad().hit(&ray, positive_interval()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_miss_behind_ray() {
+        let ray = ray_from((1.0, 0.5, 1.0), (0.0, 0.0, 1.0));
+        assert!(quad().hit(&ray, positive_interval()).is_none());
+    }
+
+    #[test]
+    fn test_edges_and_corners_hit() {
+        for (x, y) in [(0.0, 0.5), (2.0, 0.5), (1.0, 0.0), (1.0, 1.0), (2.0, 1.0)] {
+            let ray = ray_from((x, y, 1.0), (0.0, 0.0, -1.0));
+            assert!(quad().hit(&ray, positive_interval()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_grazing_ray_in_plane_misses() {
+        let ray = ray_from((-1.0, 0.5, -1.0), (1.0, 0.0, 0.0));
+        assert!(quad().hit(&ray, positive_interval()).is_none());
+    }
+
+    #[test]
+    fn test_oblique_rays_either_side_of_edge() {
+        // both start just below the top edge and tilt up, crossing the plane just
+        // inside and just outside of it
+        let inside = ray_from((1.0, 0.999, 0.0), (0.0, 0.0009, -1.0));
+        let hit = quad().hit(&inside, positive_interval()).unwrap();
+        assert!(hit.v < 1.0);
+
+        let outside = ray_from((1.0, 0.999, 0.0), (0.0, 0.0011, -1.0));
+        assert!(quad().hit(&outside, positive_interval()).is_none());
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        let mut rng = Rng::new(3);
+        let quad = quad();
+        let origin = Point::new(1.0, 0.5, 1.0);
+        for _ in 0..100 {
+            let direction = quad.random_direction(&origin, 0.0, &mut rng);
+            assert!(
+                quad.hit(&Ray::new(origin, direction), positive_interval())
+                    .is_some()
+            );
+        }
+        // straight on from 2 away, 4 / 2 per unit solid angle
+        let straight = Vec3::new(0.0, 0.0, -1.0);
+        assert!((quad.pdf_value(&origin, &straight, 0.0) - 2.0).abs() < 1e-12);
+        assert!(quad.pdf_value(&origin, &-straight, 0.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_bounding_box_is_padded() {
+        let bbox = quad().bounding_box();
+        assert!(bbox.z.size() > 0.0);
+        assert_eq!(bbox.x, Interval::new(0.0, 2.0));
+    }
+}
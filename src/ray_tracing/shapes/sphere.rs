@@ -4,8 +4,10 @@ use std::sync::Arc;
 
 use super::hittable::{HitRecord, Hittable};
 use crate::{
-    math_utils::{interval::Interval, point::Point, ray::Ray, vector::Vec3},
-    ray_tracing::materials::material::Scatterer,
+    math_utils::{
+        aabb::Aabb, interval::Interval, onb::Onb, point::Point, random::Rng, ray::Ray, vector::Vec3,
+    },
+    ray_tracing::{integrators::MIN_HIT_DISTANCE, lights::Light, materials::material::Scatterer},
 };
 
 pub struct Sphere {
@@ -47,6 +49,17 @@ impl Sphere {
         return self.center + (time * self.velocity)        
     }
 
+    /// Map a point on the unit sphere to (u, v), with u going around the y axis
+    /// starting from -x and v going from -y up to +y
+    fn surface_coordinates(point: &Vec3<f64>) -> (f64, f64) {
+        let theta = (-point.y).clamp(-1.0, 1.0).acos();
+        let phi = (-point.z).atan2(point.x) + std::f64::consts::PI;
+        (
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        )
+    }
+
     pub fn add_velocity(self, velocity: Vec3<f64>) -> Sphere {
         Sphere {
             velocity,
@@ -94,14 +107,104 @@ impl Hittable for Sphere {
         } else {
             -outward_normal
         };
+        let (surface_u, surface_v) = Self::surface_coordinates(&outward_normal);
 
         Some(HitRecord {
             point,
             normal,
             t,
             front_face,
-            material: Arc::clone(&self.material) // self.material.clone(),
+            u: surface_u,
+            v: surface_v,
+            material: Arc::clone(&self.material), // self.material.clone(),
+            object_id: 0,
         })
     }
+
+    fn bounding_box(&self) -> Aabb {
+        // a moving sphere sweeps out a box covering both ends of its path
+        let radius_vector = Vec3::new(self.radius, self.radius, self.radius);
+        let start = self.at(0.0);
+        let end = self.at(1.0);
+        Aabb::surrounding(
+            &Aabb::from_points(start - radius_vector, start + radius_vector),
+            &Aabb::from_points(end - radius_vector, end + radius_vector),
+        )
+    }
+}
+
+impl Light for Sphere {
+    /// Directions are picked uniformly within the cone the sphere fills as seen from
+    /// `origin`, or over every direction from inside it
+    fn pdf_value(&self, origin: &Point<f64>, direction: &Vec3<f64>, time: f64) -> f64 {
+        let distance_squared = (self.at(time) - *origin).magnitude_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * std::f64::consts::PI);
+        }
+        let ray = Ray::new_at_time(*origin, *direction, time);
+        if self
+            .hit(&ray, Interval::new(MIN_HIT_DISTANCE, f64::INFINITY))
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * std::f64::consts::PI * (1.0 - cos_theta_max))
+    }
+
+    fn random_direction(&self, origin: &Point<f64>, time: f64, rng: &mut Rng<'_>) -> Vec3<f64> {
+        let towards_center = self.at(time) - *origin;
+        let distance_squared = towards_center.magnitude_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return Vec3::random_unit_vector(rng);
+        }
+
+        // uniform over the cap of the unit sphere within the cone, around +z
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let [u, v] = rng.get_2d();
+        let z = u.mul_add(cos_theta_max - 1.0, 1.0);
+        let phi = 2.0 * std::f64::consts::PI * v;
+        let sin_theta = (1.0 - z * z).sqrt();
+        let local = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        Onb::new(&towards_center).transform(&local)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_tracing::{color::Color, materials::lambertian::LambertianMaterial};
+
+    #[test]
+    fn test_light_sampling_follows_motion() {
+        // moves from straight ahead to well off to the side over the shutter interval
+        let sphere = Sphere::new_with_velocity(
+            Point::new(0.0, 0.0, -5.0),
+            0.5,
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap())),
+            Vec3::new(3.0, 0.0, 0.0),
+        );
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let mut rng = Rng::new(7);
+        for time in [0.0, 0.5, 1.0] {
+            for _ in 0..100 {
+                let direction = sphere.random_direction(&origin, time, &mut rng);
+                let ray = Ray::new_at_time(origin, direction, time);
+                assert!(
+                    sphere
+                        .hit(&ray, Interval::new(MIN_HIT_DISTANCE, f64::INFINITY))
+                        .is_some(),
+                    "{time}"
+                );
+                assert!(sphere.pdf_value(&origin, &direction, time) > 0.0, "{time}");
+            }
+        }
+        // where it started out is empty by the end
+        let ahead = Vec3::new(0.0, 0.0, -1.0);
+        assert!(sphere.pdf_value(&origin, &ahead, 0.0) > 0.0);
+        assert!(sphere.pdf_value(&origin, &ahead, 1.0).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,307 @@
+//! A single triangle, and the ray intersection shared with `TriangleMesh`
+
+use std::sync::Arc;
+
+use super::hittable::{HitRecord, Hittable};
+use crate::{
+    math_utils::{
+        aabb::Aabb, interval::Interval, point::Point, random::Rng, ray::Ray, vector::Vec3,
+    },
+    ray_tracing::{integrators::MIN_HIT_DISTANCE, lights::Light, materials::material::Scatterer},
+};
+
+/// Triangles lying in an axis plane have a flat box, pad it so the slab test can hit it
+const MIN_BOX_THICKNESS: f64 = 1e-4;
+
+/// Where a ray crosses a triangle
+pub struct TriangleIntersection {
+    pub t: f64,
+    /// Barycentric weights of the second and third vertices, the first gets `1 - b1 - b2`
+    pub b1: f64,
+    pub b2: f64,
+}
+
+/// Watertight ray/triangle intersection (Woop, Benthin and Wald 2013)
+///
+/// The triangle is moved into a space where the ray runs along +z from the origin,
+/// which reduces the inside test to the signs of three 2D edge functions. A shared
+/// edge gives its two triangles exactly opposite edge values, so rays can't slip
+/// through the crack between neighbours of a mesh. Points on an edge count as hits,
+/// rays in the triangle's plane miss.
+#[must_use]
+#[allow(clippy::suboptimal_flops)]
+pub fn intersect_triangle(
+    ray: &Ray<f64>,
+    vertices: [Point<f64>; 3],
+    ray_interval: Interval,
+) -> Option<TriangleIntersection> {
+    let direction = ray.get_direction();
+    let origin = *ray.get_origin();
+
+    // make z the axis the ray moves fastest along, keeping the winding intact
+    let kz = largest_axis(direction);
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    if component(direction, kz) < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // shear so the ray points straight along z
+    let shear_x = component(direction, kx) / component(direction, kz);
+    let shear_y = component(direction, ky) / component(direction, kz);
+    let shear_z = 1.0 / component(direction, kz);
+
+    let [p0, p1, p2] = vertices.map(|vertex| {
+        let relative = vertex - origin;
+        let z = component(&relative, kz);
+        (
+            (-shear_x).mul_add(z, component(&relative, kx)),
+            (-shear_y).mul_add(z, component(&relative, ky)),
+            shear_z * z,
+        )
+    });
+
+    // edge functions are the scaled barycentric coordinates, all of the same sign when
+    // the ray is inside. Left unfused on purpose: a shared edge must give its two
+    // triangles exactly opposite values, which a fused multiply-add doesn't guarantee
+    let edge_function = |p: (f64, f64, f64), q: (f64, f64, f64)| p.0 * q.1 - p.1 * q.0;
+    let e0 = edge_function(p2, p1);
+    let e1 = edge_function(p0, p2);
+    let e2 = edge_function(p1, p0);
+    if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+        return None;
+    }
+
+    let determinant = e0 + e1 + e2;
+    if determinant == 0.0 {
+        return None;
+    }
+
+    let t = e2.mul_add(p2.2, e0.mul_add(p0.2, e1 * p1.2)) / determinant;
+    if !ray_interval.surrounds(t) {
+        return None;
+    }
+
+    Some(TriangleIntersection {
+        t,
+        b1: e1 / determinant,
+        b2: e2 / determinant,
+    })
+}
+
+const fn component(vector: &Vec3<f64>, axis: usize) -> f64 {
+    match axis {
+        0 => vector.x,
+        1 => vector.y,
+        _ => vector.z,
+    }
+}
+
+fn largest_axis(vector: &Vec3<f64>) -> usize {
+    let (x, y, z) = (vector.x.abs(), vector.y.abs(), vector.z.abs());
+    if x > y && x > z {
+        0
+    } else if y > z {
+        1
+    } else {
+        2
+    }
+}
+
+/// Box around the three vertices, padded to never be flat
+#[must_use]
+pub fn triangle_bounding_box(vertices: [Point<f64>; 3]) -> Aabb {
+    let [p0, p1, p2] = vertices;
+    Aabb::surrounding(&Aabb::from_points(p0, p1), &Aabb::from_points(p2, p2))
+        .pad_to_minimums(MIN_BOX_THICKNESS)
+}
+
+/// A standalone triangle, for meshes prefer `TriangleMesh` which shares the vertices
+///
+/// The front face is the side the vertices appear counter-clockwise from.
+pub struct Triangle {
+    vertices: [Point<f64>; 3],
+    normal: Vec3<f64>,
+    area: f64,
+    material: Arc<dyn Scatterer + Send + Sync>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    /// # Panics
+    /// If the vertices are in a line, since they wouldn't span a surface
+    pub fn new(
+        p0: Point<f64>,
+        p1: Point<f64>,
+        p2: Point<f64>,
+        material: Arc<dyn Scatterer + Send + Sync>,
+    ) -> Self {
+        let vertices = [p0, p1, p2];
+        let perpendicular = (p1 - p0).cross(&(p2 - p0));
+        let normal = perpendicular
+            .normalize()
+            .expect("triangle vertices must not be in a line");
+
+        Self {
+            vertices,
+            normal,
+            area: 0.5 * perpendicular.magnitude(),
+            material,
+            bbox: triangle_bounding_box(vertices),
+        }
+    }
+
+    #[must_use]
+    pub const fn get_vertices(&self) -> [Point<f64>; 3] {
+        self.vertices
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray<f64>, ray_interval: Interval) -> Option<HitRecord> {
+        let intersection = intersect_triangle(ray, self.vertices, ray_interval)?;
+
+        let front_face = ray.get_direction().dot(&self.normal) < 0.0;
+        let normal = if front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+
+        Some(HitRecord {
+            point: ray.at(intersection.t),
+            normal,
+            t: intersection.t,
+            front_face,
+            u: intersection.b1,
+            v: intersection.b2,
+            material: Arc::clone(&self.material),
+            object_id: 0,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Light for Triangle {
+    /// Points are picked uniformly over the area, see `Quad::pdf_value`
+    fn pdf_value(&self, origin: &Point<f64>, direction: &Vec3<f64>, time: f64) -> f64 {
+        let ray = Ray::new_at_time(*origin, *direction, time);
+        let Some(hit) = self.hit(&ray, Interval::new(MIN_HIT_DISTANCE, f64::INFINITY)) else {
+            return 0.0;
+        };
+        let distance_squared = hit.t * hit.t * direction.magnitude_squared();
+        let cosine = (direction.dot(&self.normal) / direction.magnitude()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random_direction(&self, origin: &Point<f64>, _time: f64, rng: &mut Rng<'_>) -> Vec3<f64> {
+        let [p0, p1, p2] = self.vertices;
+        let [mut a, mut b] = rng.get_2d();
+        // fold the far half of the parallelogram back onto the triangle
+        if a + b > 1.0 {
+            (a, b) = (1.0 - a, 1.0 - b);
+        }
+        (p0 + a * (p1 - p0) + b * (p2 - p0)) - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_tracing::{color::Color, materials::lambertian::LambertianMaterial};
+
+    /// Right triangle in the z = -1 plane, counter-clockwise when seen from +z
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Point::new(0.0, 0.0, -1.0),
+            Point::new(1.0, 0.0, -1.0),
+            Point::new(0.0, 1.0, -1.0),
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap())),
+        )
+    }
+
+    fn ray_down_z(x: f64, y: f64) -> Ray<f64> {
+        Ray::new(Point::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    fn positive_interval() -> Interval {
+        Interval::new(0.001, f64::INFINITY)
+    }
+
+    #[test]
+    fn test_hit_inside() {
+        let hit = triangle()
+            .hit(&ray_down_z(0.25, 0.5), positive_interval())
+            .unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-12);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((hit.u - 0.25).abs() < 1e-12);
+        assert!((hit.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_hit_from_behind() {
+        let ray = Ray::new(Point::new(0.2, 0.2, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = triangle().hit(&ray, positive_interval()).unwrap();
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_miss() {
+        for (x, y) in [(0.6, 0.6), (-0.01, 0.5), (0.5, -0.01), (2.0, 2.0)] {
+            assert!(
+                triangle()
+                    .hit(&ray_down_z(x, y), positive_interval())
+                    .is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn test_edges_hit() {
+        for (x, y) in [(0.5, 0.5), (0.0, 0.5), (0.5, 0.0), (0.0, 0.0)] {
+            assert!(
+                triangle()
+                    .hit(&ray_down_z(x, y), positive_interval())
+                    .is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parallel_ray_misses() {
+        let ray = Ray::new(Point::new(-1.0, 0.2, -1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(triangle().hit(&ray, positive_interval()).is_none());
+    }
+
+    #[test]
+    fn test_interval_is_respected() {
+        assert!(
+            triangle()
+                .hit(&ray_down_z(0.2, 0.2), Interval::new(0.001, 1.5))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        let mut rng = Rng::new(4);
+        let triangle = triangle();
+        let origin = Point::new(0.25, 0.25, 1.0);
+        for _ in 0..100 {
+            let direction = triangle.random_direction(&origin, 0.0, &mut rng);
+            assert!(
+                triangle
+                    .hit(&Ray::new(origin, direction), positive_interval())
+                    .is_some()
+            );
+        }
+        // straight on from 2 away, 4 / 0.5 per unit solid angle
+        let straight = Vec3::new(0.0, 0.0, -1.0);
+        assert!((triangle.pdf_value(&origin, &straight, 0.0) - 8.0).abs() < 1e-12);
+    }
+}
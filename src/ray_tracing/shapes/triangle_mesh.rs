@@ -0,0 +1,491 @@
+//! Indexed triangle mesh
+//!
+//! All triangles share one vertex buffer, optional per-vertex normals and texture
+//! coordinates, and one material. The mesh carries its own BVH over the triangles,
+//! so a large model is a single object in the scene instead of one per triangle.
+//! An emissive mesh is sampled as one light, picking its triangles by area.
+
+use std::{fmt, sync::Arc};
+
+use super::{
+    flat_bvh::{BvhSplit, BvhStats, BvhTree},
+    hittable::{HitRecord, Hittable},
+    triangle::{intersect_triangle, triangle_bounding_box},
+};
+use crate::{
+    math_utils::{
+        aabb::Aabb, interval::Interval, point::Point, random::Rng, ray::Ray, vector::Vec3,
+    },
+    ray_tracing::{integrators::MIN_HIT_DISTANCE, lights::Light, materials::material::Scatterer},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshError {
+    /// A triangle refers to a vertex past the end of the vertex buffer
+    IndexOutOfRange { triangle: usize, index: usize },
+    /// Per-vertex normals or texture coordinates need one entry for every vertex
+    AttributeCountMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IndexOutOfRange { triangle, index } => {
+                write!(f, "triangle {triangle} refers to missing vertex {index}")
+            }
+            Self::AttributeCountMismatch { expected, found } => {
+                write!(f, "expected {expected} vertex attributes but found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+pub struct TriangleMesh {
+    vertices: Vec<Point<f64>>,
+    normals: Option<Vec<Vec3<f64>>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    /// Vertex indices of each triangle, stored in the tree's primitive order
+    triangles: Vec<[usize; 3]>,
+    /// Running total of the triangles' areas in the same order, for picking one by area
+    cumulative_areas: Vec<f64>,
+    material: Arc<dyn Scatterer + Send + Sync>,
+    tree: BvhTree,
+}
+
+impl TriangleMesh {
+    /// Triangles list their vertices counter-clockwise as seen from the front.
+    /// When normals are given they decide which side is the front instead.
+    ///
+    /// # Errors
+    /// If a triangle refers to a vertex that doesn't exist, or if there isn't
+    /// exactly one normal or texture coordinate per vertex
+    pub fn new(
+        vertices: Vec<Point<f64>>,
+        triangles: &[[usize; 3]],
+        normals: Option<Vec<Vec3<f64>>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        material: Arc<dyn Scatterer + Send + Sync>,
+    ) -> Result<Self, MeshError> {
+        for (triangle, indices) in triangles.iter().enumerate() {
+            if let Some(&index) = indices.iter().find(|&&index| index >= vertices.len()) {
+                return Err(MeshError::IndexOutOfRange { triangle, index });
+            }
+        }
+        let attribute_counts = [normals.as_ref().map(Vec::len), uvs.as_ref().map(Vec::len)];
+        if let Some(found) = attribute_counts
+            .into_iter()
+            .flatten()
+            .find(|&count| count != vertices.len())
+        {
+            return Err(MeshError::AttributeCountMismatch {
+                expected: vertices.len(),
+                found,
+            });
+        }
+
+        let bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|&[i0, i1, i2]| triangle_bounding_box([vertices[i0], vertices[i1], vertices[i2]]))
+            .collect();
+        let tree = BvhTree::new(&bounds, BvhSplit::default());
+        let triangles: Vec<[usize; 3]> = tree
+            .primitive_order()
+            .iter()
+            .map(|&index| triangles[index])
+            .collect();
+        let cumulative_areas = triangles
+            .iter()
+            .scan(0.0, |total, &[i0, i1, i2]| {
+                let (p0, p1, p2) = (vertices[i0], vertices[i1], vertices[i2]);
+                *total += 0.5 * (p1 - p0).cross(&(p2 - p0)).magnitude();
+                Some(*total)
+            })
+            .collect();
+
+        Ok(Self {
+            vertices,
+            normals,
+            uvs,
+            triangles,
+            cumulative_areas,
+            material,
+            tree,
+        })
+    }
+
+    #[must_use]
+    pub const fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    #[must_use]
+    pub const fn stats(&self) -> &BvhStats {
+        self.tree.stats()
+    }
+
+    #[must_use]
+    pub fn material(&self) -> &Arc<dyn Scatterer + Send + Sync> {
+        &self.material
+    }
+
+    fn corners(&self, position: usize) -> [Point<f64>; 3] {
+        self.triangles[position].map(|index| self.vertices[index])
+    }
+
+    fn area(&self) -> f64 {
+        self.cumulative_areas.last().copied().unwrap_or(0.0)
+    }
+
+    fn hit_triangle(
+        &self,
+        position: usize,
+        ray: &Ray<f64>,
+        ray_interval: Interval,
+    ) -> Option<HitRecord> {
+        let [i0, i1, i2] = self.triangles[position];
+        let (p0, p1, p2) = (self.vertices[i0], self.vertices[i1], self.vertices[i2]);
+        let intersection = intersect_triangle(ray, [p0, p1, p2], ray_interval)?;
+        let (b1, b2) = (intersection.b1, intersection.b2);
+        let b0 = 1.0 - b1 - b2;
+
+        let mut geometric_normal = (p1 - p0).cross(&(p2 - p0)).normalize().ok()?;
+        let shading_normal = match &self.normals {
+            Some(normals) => {
+                let interpolated = b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2];
+                let interpolated = interpolated.normalize().unwrap_or(geometric_normal);
+                // vertex normals are the authority on which side is outside
+                if interpolated.dot(&geometric_normal) < 0.0 {
+                    geometric_normal = -geometric_normal;
+                }
+                interpolated
+            }
+            None => geometric_normal,
+        };
+
+        let front_face = ray.get_direction().dot(&geometric_normal) < 0.0;
+        let normal = if front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+
+        let (u, v) = self.uvs.as_ref().map_or((b1, b2), |uvs| {
+            let (u0, v0) = uvs[i0];
+            let (u1, v1) = uvs[i1];
+            let (u2, v2) = uvs[i2];
+            (
+                b2.mul_add(u2, b0.mul_add(u0, b1 * u1)),
+                b2.mul_add(v2, b0.mul_add(v0, b1 * v1)),
+            )
+        });
+
+        Some(HitRecord {
+            point: ray.at(intersection.t),
+            normal,
+            t: intersection.t,
+            front_face,
+            u,
+            v,
+            material: Arc::clone(&self.material),
+            object_id: 0,
+        })
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray<f64>, ray_interval: Interval) -> Option<HitRecord> {
+        self.tree.hit(ray, ray_interval, |position, interval| {
+            self.hit_triangle(position, ray, interval)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.tree.bounding_box()
+    }
+}
+
+impl Light for TriangleMesh {
+    /// Points are picked uniformly over the whole surface, so every triangle along the
+    /// direction could have given it, see `Triangle::pdf_value`
+    fn pdf_value(&self, origin: &Point<f64>, direction: &Vec3<f64>, time: f64) -> f64 {
+        let area = self.area();
+        if area <= 0.0 {
+            return 0.0;
+        }
+        let ray = Ray::new_at_time(*origin, *direction, time);
+        let mut density = 0.0;
+        // never reporting a hit makes the tree visit every triangle along the ray
+        self.tree.hit(
+            &ray,
+            Interval::new(MIN_HIT_DISTANCE, f64::INFINITY),
+            |position, interval| {
+                let [p0, p1, p2] = self.corners(position);
+                if let Some(intersection) = intersect_triangle(&ray, [p0, p1, p2], interval) {
+                    let perpendicular = (p1 - p0).cross(&(p2 - p0));
+                    let distance_squared =
+                        intersection.t * intersection.t * direction.magnitude_squared();
+                    let cosine = (direction.dot(&perpendicular)
+                        / (direction.magnitude() * perpendicular.magnitude()))
+                    .abs();
+                    density += distance_squared / (cosine * area);
+                }
+                None
+            },
+        );
+        density
+    }
+
+    fn random_direction(&self, origin: &Point<f64>, _time: f64, rng: &mut Rng<'_>) -> Vec3<f64> {
+        let [choice, mut b] = rng.get_2d();
+        // the first number picks a triangle by area and what's left of it places the
+        // point, so the mesh takes no more numbers than a single triangle
+        let target = choice * self.area();
+        let position = self
+            .cumulative_areas
+            .partition_point(|&total| total <= target)
+            .min(self.triangles.len() - 1);
+        let start = position
+            .checked_sub(1)
+            .map_or(0.0, |before| self.cumulative_areas[before]);
+        let triangle_area = self.cumulative_areas[position] - start;
+        let mut a = if triangle_area > 0.0 {
+            ((target - start) / triangle_area).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+
+        let [p0, p1, p2] = self.corners(position);
+        // fold the far half of the parallelogram back onto the triangle
+        if a + b > 1.0 {
+            (a, b) = (1.0 - a, 1.0 - b);
+        }
+        (p0 + a * (p1 - p0) + b * (p2 - p0)) - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math_utils::pdf::{Pdf, SpherePdf},
+        ray_tracing::{
+            color::Color,
+            materials::lambertian::LambertianMaterial,
+            shapes::{group::Group, triangle::Triangle},
+        },
+    };
+
+    fn material() -> Arc<dyn Scatterer + Send + Sync> {
+        Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap()))
+    }
+
+    /// Grid of `n` x `n` quads in the z = -2 plane, two triangles each, facing +z
+    fn grid(n: u32) -> (Vec<Point<f64>>, Vec<[usize; 3]>) {
+        let step = |k: u32| (f64::from(k) / f64::from(n)).mul_add(2.0, -1.0);
+        let vertices = (0..=n)
+            .flat_map(|j| (0..=n).map(move |i| Point::new(step(i), step(j), -2.0)))
+            .collect();
+
+        let row = n as usize + 1;
+        let mut triangles = Vec::new();
+        for j in 0..row - 1 {
+            for i in 0..row - 1 {
+                let corner = j * row + i;
+                triangles.push([corner, corner + 1, corner + row + 1]);
+                triangles.push([corner, corner + row + 1, corner + row]);
+            }
+        }
+        (vertices, triangles)
+    }
+
+    fn rays() -> Vec<Ray<f64>> {
+        let mut rays = Vec::new();
+        for a in -15..=15 {
+            for b in -15..=15 {
+                let direction = Vec3::new(f64::from(a) * 0.04, f64::from(b) * 0.04, -1.0);
+                rays.push(Ray::new(Point::new(0.01, -0.02, 0.0), direction));
+            }
+        }
+        rays
+    }
+
+    #[test]
+    fn test_mesh_matches_separate_triangles() {
+        let (vertices, triangles) = grid(8);
+        let group = Group::new(
+            triangles
+                .iter()
+                .map(|&[i0, i1, i2]| {
+                    Box::new(Triangle::new(
+                        vertices[i0],
+                        vertices[i1],
+                        vertices[i2],
+                        material(),
+                    )) as Box<dyn Hittable + Send + Sync>
+                })
+                .collect(),
+        );
+        let mesh = TriangleMesh::new(vertices, &triangles, None, None, material()).unwrap();
+        assert_eq!(mesh.triangle_count(), 128);
+
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let mut hits = 0;
+        for ray in rays() {
+            let expected = group
+                .hit(&ray, interval)
+                .map(|hit| (hit.t, hit.normal, hit.front_face));
+            let actual = mesh
+                .hit(&ray, interval)
+                .map(|hit| (hit.t, hit.normal, hit.front_face));
+            assert_eq!(expected.is_some(), actual.is_some());
+            if let (Some(expected), Some(actual)) = (expected, actual) {
+                assert!((expected.0 - actual.0).abs() < 1e-12);
+                assert_eq!(expected.1, actual.1);
+                assert_eq!(expected.2, actual.2);
+                hits += 1;
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn test_no_cracks_along_shared_edges() {
+        let (vertices, triangles) = grid(4);
+        let mesh = TriangleMesh::new(vertices, &triangles, None, None, material()).unwrap();
+        // rays straight down through the inner diagonal edges, grid lines and corners
+        for k in -7..=7 {
+            let x = f64::from(k) * 0.125;
+            for y in [x, 0.0, 0.5, -x] {
+                let ray = Ray::new(Point::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0));
+                assert!(
+                    mesh.hit(&ray, Interval::new(0.001, f64::INFINITY))
+                        .is_some()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolated_attributes() {
+        let vertices = vec![
+            Point::new(0.0, 0.0, -1.0),
+            Point::new(1.0, 0.0, -1.0),
+            Point::new(0.0, 1.0, -1.0),
+        ];
+        // normals tilted towards +x, listed against the winding so they flip the front side
+        let tilted = Vec3::new(1.0, 0.0, -1.0).normalize().unwrap();
+        let normals = vec![tilted; 3];
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (0.0, 0.5)];
+        let mesh = TriangleMesh::new(vertices, &[[0, 1, 2]], Some(normals), Some(uvs), material())
+            .unwrap();
+
+        let ray = Ray::new(Point::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!(!hit.front_face);
+        assert!((hit.normal.x + tilted.x).abs() < 1e-12);
+        assert!((hit.normal.z + tilted.z).abs() < 1e-12);
+        assert!((hit.u - 0.5).abs() < 1e-12);
+        assert!((hit.v - 0.125).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_light_matches_separate_triangles() {
+        // the grid's triangles all have the same area, so picking them by area is the
+        // same as picking them uniformly
+        let (vertices, triangles) = grid(4);
+        let separate: Vec<Triangle> = triangles
+            .iter()
+            .map(|&[i0, i1, i2]| {
+                Triangle::new(vertices[i0], vertices[i1], vertices[i2], material())
+            })
+            .collect();
+        let mesh = TriangleMesh::new(vertices, &triangles, None, None, material()).unwrap();
+
+        let origin = Point::new(0.01, -0.02, 0.0);
+        for ray in rays() {
+            let direction = ray.get_direction();
+            let expected = separate
+                .iter()
+                .map(|triangle| triangle.pdf_value(&origin, direction, 0.0))
+                .sum::<f64>()
+                / 32.0;
+            let actual = mesh.pdf_value(&origin, direction, 0.0);
+            assert!(
+                (expected - actual).abs() < 1e-9 * expected.max(1.0),
+                "{expected} vs {actual}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_light_pdf_integrates_to_one() {
+        // a bent strip of triangles of different sizes, seen from outside its bend
+        let vertices = vec![
+            Point::new(-1.0, 0.0, -1.0),
+            Point::new(0.0, 0.0, -2.0),
+            Point::new(-1.0, 1.0, -1.0),
+            Point::new(0.0, 1.0, -2.0),
+            Point::new(2.0, 0.0, -1.0),
+            Point::new(2.0, 1.5, -1.0),
+        ];
+        let triangles = [[0, 1, 3], [0, 3, 2], [1, 4, 5], [1, 5, 3]];
+        let mesh = TriangleMesh::new(vertices, &triangles, None, None, material()).unwrap();
+
+        let mut rng = Rng::new(7);
+        let origin = Point::new(0.5, 0.5, 1.0);
+        let samples = 100_000;
+        let mut total = 0.0;
+        for _ in 0..samples {
+            let direction = mesh.random_direction(&origin, 0.0, &mut rng);
+            let density = mesh.pdf_value(&origin, &direction, 0.0);
+            assert!(density > 0.0);
+            // the area is the integral of 1 / density over the directions picked
+            total += 1.0 / density;
+        }
+        let solid_angle = total / f64::from(samples);
+
+        let sphere = SpherePdf;
+        let hits = (0..samples)
+            .filter(|_| {
+                let direction = sphere.generate(&mut rng);
+                mesh.hit(
+                    &Ray::new(origin, direction),
+                    Interval::new(0.001, f64::INFINITY),
+                )
+                .is_some()
+            })
+            .count();
+        #[allow(clippy::cast_precision_loss)] // sample counts are far below 2^52
+        let expected = 4.0 * std::f64::consts::PI * hits as f64 / f64::from(samples);
+        assert!(
+            (solid_angle - expected).abs() < 0.03 * expected,
+            "{solid_angle} vs {expected}"
+        );
+    }
+
+    #[test]
+    fn test_invalid_meshes() {
+        let (vertices, _) = grid(1);
+        let out_of_range =
+            TriangleMesh::new(vertices.clone(), &[[0, 1, 9]], None, None, material());
+        assert_eq!(
+            out_of_range.err(),
+            Some(MeshError::IndexOutOfRange {
+                triangle: 0,
+                index: 9
+            })
+        );
+
+        let short_normals = Some(vec![Vec3::new(0.0, 0.0, 1.0); 2]);
+        let mismatched = TriangleMesh::new(vertices, &[[0, 1, 2]], short_normals, None, material());
+        assert_eq!(
+            mismatched.err(),
+            Some(MeshError::AttributeCountMismatch {
+                expected: 4,
+                found: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,1137 @@
+//! TOML scene descriptions
+//!
+//! A scene file has five parts, all optional:
+//!
+//! ```toml
+//! [render]
+//! image_width = 400
+//! aspect_ratio = 1.5
+//! samples_per_pixel = 100
+//! sampler = "sobol"
+//! filter = "gaussian"
+//! filter_radius = 1.5
+//! tile_size = 32
+//! tile_order = "hilbert"
+//! crop_window = [0.25, 0.75, 0.4, 0.6]
+//! crop_full_size = false
+//! adaptive_threshold = 0.05
+//! heatmap = "samples.png"
+//! pass_samples = 8
+//! snapshot_interval = 30
+//! integrator = "path"
+//! max_depth = 50
+//! roulette_depth = 5
+//! output = "image.ppm"
+//! exposure = 0.5
+//! tone_map = "aces"
+//! denoise = true
+//!
+//! [aovs]
+//! normal = "normal.pfm"
+//! depth = "depth.pfm"
+//!
+//! [camera]
+//! look_from = [0, 1, 3]
+//! look_at = [0, 0, -1]
+//! vertical_fov = 30
+//! defocus_angle = 0.6
+//! focus_dist = 4
+//! background = { type = "gradient", bottom = [1, 1, 1], top = [0.5, 0.7, 1] }
+//!
+//! [materials]
+//! ground = { type = "lambertian", albedo = [0.8, 0.8, 0.0] }
+//! glass = { type = "dielectric", refraction_index = 1.5 }
+//!
+//! [[shapes]]
+//! type = "sphere"
+//! center = [0, -100.5, -1]
+//! radius = 100
+//! material = "ground"
+//! ```
+//!
+//! Anything left out keeps the `CameraBuilder` default. The `integrator` is `path`
+//! for real lighting, or one of the debug views `normals`, `depth`, `albedo` or
+//! `ambient-occlusion`, which traces `occlusion_samples` shadow rays reaching as far as
+//! `occlusion_distance`. Backgrounds are `solid`
+//! (`color`), `gradient` (`bottom`, `top`) or `environment` (`file`, a Radiance
+//! `.hdr` or a `.pfm`). Materials are `lambertian` (`albedo`), `metal` (`albedo`),
+//! `dielectric` (`refraction_index`) or `diffuse_light` (`color`, `intensity`).
+//! Shapes are `sphere` (`center`, `radius`, `velocity`), `quad` (`corner`, `u`, `v`),
+//! `triangle` (`vertices`) or `mesh` (`file`, an `.obj` model). Every shape names its
+//! material, a mesh's material only covers faces its `.mtl` files don't. Files are
+//! looked up relative to the scene file.
+//!
+//! Every entry in `[aovs]` saves an image of the surfaces camera rays hit first to a
+//! `.pfm` file. The names are `normal`, `albedo`, `depth`, `position` and `object-id`.
+//! With `denoise` the image is filtered with the normal and albedo AOVs as guides
+//! before it's saved.
+//!
+//! The `sampler` spreads the random numbers of a pixel's samples: `sobol` (the
+//! default), `halton`, `stratified` or plain `independent` random numbers. Each
+//! sample counts towards the pixels within `filter_radius` of it, weighted by the
+//! `filter`: `box` (the default, radius 0.5, which keeps every sample in its own
+//! pixel), `tent` (1), `gaussian` (1.5), `mitchell` (2) or `lanczos` (3).
+//!
+//! The threads render the image in square tiles of `tile_size` pixels, handed out
+//! along a `hilbert` curve (the default) or in a `spiral` from the center. They only
+//! change how fast an image renders, not what it looks like.
+//!
+//! A crop window renders only part of the image, either `crop_pixels = [x, y, width,
+//! height]` from the top left corner or `crop_window = [x_min, x_max, y_min, y_max]`
+//! as fractions of the image. Only the window is saved, unless `crop_full_size` keeps
+//! the whole image with everything outside the window black.
+//!
+//! Setting `adaptive_threshold`, `adaptive_min_samples` or `adaptive_max_samples`
+//! turns on adaptive sampling, which keeps the same total of `samples_per_pixel` a
+//! pixel but stops sampling pixels once their noise is below the threshold and spends
+//! the rest on noisier ones. `heatmap` saves how many samples every pixel got.
+//!
+//! With `pass_samples` the render is progressive: the whole image gets that many
+//! more samples a pixel in every pass, and the output is saved after each pass, or
+//! at most once every `snapshot_interval` seconds, so a render can be stopped as
+//! soon as it looks good enough. Snapshots skip the denoiser.
+//!
+//! Shapes made of `diffuse_light`, and the parts of meshes whose `.mtl` material has
+//! a `Ke`, are also sampled directly as lights, which makes small lights far less
+//! noisy.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::obj::load_obj;
+use crate::{
+    image_utils::{denoise::Denoiser, tone_map::DisplayTransform},
+    math_utils::{point::Point, vector::Vec3},
+    ray_tracing::{
+        aov::{self, Aov},
+        background::{Background, EnvironmentMap, GradientBackground, SolidBackground},
+        camera::{
+            adaptive::AdaptiveSampling,
+            camera::CameraBuilder,
+            crop::{Crop, CropWindow},
+            filter::Filter,
+            tiles::Tiling,
+        },
+        color::Color,
+        integrators::IntegratorSettings,
+        lights::{Light, LightList},
+        materials::{
+            dielectric::DielectricMaterial, diffuse_light::DiffuseLightMaterial,
+            lambertian::LambertianMaterial, material::Scatterer, metal::MetalMaterial,
+        },
+        shapes::{group::Group, quad::Quad, sphere::Sphere, triangle::Triangle},
+    },
+};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    /// Not valid TOML, or an entry with missing, unknown or mistyped fields
+    Syntax(toml::de::Error),
+    /// A well-formed entry describing something that can't be built
+    Invalid {
+        entry: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Syntax(error) => write!(f, "{error}"),
+            Self::Invalid { entry, message } => write!(f, "{entry}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Syntax(error) => Some(error),
+            Self::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Everything needed to render a scene
+pub struct Scene {
+    /// Camera and render settings, still open to changes before `make_camera`
+    pub camera: CameraBuilder,
+    pub world: Group,
+    /// Where the image should be written
+    pub output: PathBuf,
+    /// How radiance is turned into display colors for 8 bit formats
+    pub display: DisplayTransform,
+    /// Emissive shapes of the world, sampled directly while rendering
+    pub lights: LightList,
+    /// How the light along each camera ray is worked out
+    pub integrator: IntegratorSettings,
+    /// Extra images to render and the files they go to
+    pub aovs: BTreeMap<Aov, PathBuf>,
+    /// Filters the noise out of the finished image, `None` to keep it as rendered
+    pub denoiser: Option<Denoiser>,
+    /// Where to save an image of the samples each pixel got
+    pub heatmap: Option<PathBuf>,
+    /// Least time between two snapshots of a progressive render, zero to save one
+    /// after every pass
+    pub snapshot_interval: Duration,
+}
+
+/// Read and build a scene file
+///
+/// # Errors
+/// If the file, or a model or image it refers to, can't be read, or if an entry
+/// is malformed or describes something impossible
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = std::fs::read_to_string(path)?;
+    parse_scene(&source, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// Build a scene from the text of a scene file, with files looked up in `directory`
+///
+/// # Errors
+/// See `load_scene`
+pub fn parse_scene(source: &str, directory: &Path) -> Result<Scene, SceneError> {
+    let file: SceneFile = toml::from_str(source).map_err(SceneError::Syntax)?;
+    let line_of = |span: std::ops::Range<usize>| source[..span.start].matches('\n').count() + 1;
+
+    let mut materials = HashMap::new();
+    for (name, entry) in &file.materials {
+        let entry_name = format!("materials.{name} (line {})", line_of(entry.span()));
+        let material = entry
+            .get_ref()
+            .build()
+            .map_err(|message| SceneError::Invalid {
+                entry: entry_name,
+                message,
+            })?;
+        materials.insert(name.as_str(), material);
+    }
+
+    let mut world = Group::new(Vec::new());
+    let mut lights = LightList::default();
+    for (index, entry) in file.shapes.iter().enumerate() {
+        let entry_name = format!("shapes[{index}] (line {})", line_of(entry.span()));
+        let shapes = entry
+            .get_ref()
+            .build(&materials, directory)
+            .map_err(|message| SceneError::Invalid {
+                entry: entry_name,
+                message,
+            })?;
+
+        for shape in shapes {
+            if shape.emissive {
+                // shared so the light is both part of the world and sampled on its own
+                let light: Arc<dyn Light + Send + Sync> = Arc::from(shape.object);
+                lights.add(Arc::clone(&light));
+                world.add(Box::new(light));
+            } else {
+                world.add(shape.object);
+            }
+        }
+    }
+
+    let camera = file.camera.build(&file.render.build()?, directory)?;
+    let output = file
+        .render
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("image.ppm"));
+    let display = file.render.display()?;
+    let integrator = file.render.integrator()?;
+    let snapshot_interval = match file.render.snapshot_interval {
+        Some(seconds) => Duration::try_from_secs_f64(seconds)
+            .map_err(|_| invalid("render.snapshot_interval", "must be a number of seconds"))?,
+        None => Duration::ZERO,
+    };
+
+    let mut aovs = BTreeMap::new();
+    for (name, path) in &file.aovs {
+        let entry_name = format!("aovs.{name}");
+        let aov = name
+            .parse()
+            .map_err(|message| invalid(entry_name.as_str(), message))?;
+        aov::check_file(path).map_err(|message| invalid(entry_name.as_str(), message))?;
+        aovs.insert(aov, path.clone());
+    }
+
+    Ok(Scene {
+        camera,
+        world,
+        output,
+        display,
+        lights,
+        integrator,
+        aovs,
+        denoiser: file.render.denoise.unwrap_or(false).then(Denoiser::default),
+        heatmap: file.render.heatmap.clone(),
+        snapshot_interval,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    render: RenderSettings,
+    #[serde(default)]
+    aovs: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    camera: CameraSettings,
+    /// Sorted so problems are reported in the same order every time
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialEntry>>,
+    #[serde(default)]
+    shapes: Vec<Spanned<ShapeEntry>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RenderSettings {
+    image_width: Option<isize>,
+    aspect_ratio: Option<f64>,
+    samples_per_pixel: Option<isize>,
+    sampler: Option<String>,
+    filter: Option<String>,
+    filter_radius: Option<f64>,
+    tile_size: Option<isize>,
+    tile_order: Option<String>,
+    crop_pixels: Option<[usize; 4]>,
+    crop_window: Option<[f64; 4]>,
+    crop_full_size: Option<bool>,
+    adaptive_threshold: Option<f64>,
+    adaptive_min_samples: Option<isize>,
+    adaptive_max_samples: Option<isize>,
+    heatmap: Option<PathBuf>,
+    pass_samples: Option<isize>,
+    snapshot_interval: Option<f64>,
+    integrator: Option<String>,
+    max_depth: Option<isize>,
+    roulette_depth: Option<isize>,
+    occlusion_samples: Option<isize>,
+    occlusion_distance: Option<f64>,
+    output: Option<PathBuf>,
+    exposure: Option<f64>,
+    tone_map: Option<String>,
+    white_point: Option<f64>,
+    denoise: Option<bool>,
+}
+
+impl RenderSettings {
+    fn build(&self) -> Result<CameraBuilder, SceneError> {
+        let defaults = CameraBuilder::default();
+
+        let aspect_ratio = self.aspect_ratio.unwrap_or(defaults.aspect_ratio);
+        if aspect_ratio.is_nan() || aspect_ratio <= 0.0 {
+            return Err(invalid("render.aspect_ratio", "must be positive"));
+        }
+        let sampler = match &self.sampler {
+            Some(name) => name
+                .parse()
+                .map_err(|message| invalid("render.sampler", message))?,
+            None => defaults.sampler,
+        };
+        let filter = match &self.filter {
+            Some(name) => Filter::new(
+                name.parse()
+                    .map_err(|message| invalid("render.filter", message))?,
+            ),
+            None => defaults.filter,
+        };
+        let radius = self.filter_radius.unwrap_or(filter.radius);
+        if !radius.is_finite() || radius <= 0.0 {
+            return Err(invalid("render.filter_radius", "must be positive"));
+        }
+        let tile_order = match &self.tile_order {
+            Some(name) => name
+                .parse()
+                .map_err(|message| invalid("render.tile_order", message))?,
+            None => defaults.tiling.order,
+        };
+
+        Ok(CameraBuilder {
+            image_width: positive(
+                "image_width",
+                self.image_width.unwrap_or(defaults.image_width),
+            )?,
+            aspect_ratio,
+            samples_per_pixel: positive(
+                "samples_per_pixel",
+                self.samples_per_pixel.unwrap_or(defaults.samples_per_pixel),
+            )?,
+            sampler,
+            filter: Filter { radius, ..filter },
+            tiling: Tiling {
+                size: positive("tile_size", self.tile_size.unwrap_or(defaults.tiling.size))?,
+                order: tile_order,
+            },
+            crop: self.crop()?,
+            adaptive: self.adaptive()?,
+            pass_samples: self
+                .pass_samples
+                .map(|pass_samples| positive("pass_samples", pass_samples))
+                .transpose()?,
+            ..defaults
+        })
+    }
+
+    /// At most one of the two ways to give a crop window
+    fn crop(&self) -> Result<Option<Crop>, SceneError> {
+        let window = match (self.crop_pixels, self.crop_window) {
+            (Some(_), Some(_)) => {
+                return Err(invalid(
+                    "render.crop_window",
+                    "can't be given along with crop_pixels",
+                ));
+            }
+            (Some(pixels), None) => CropWindow::pixels(pixels)
+                .map_err(|message| invalid("render.crop_pixels", message))?,
+            (None, Some(edges)) => CropWindow::normalized(edges)
+                .map_err(|message| invalid("render.crop_window", message))?,
+            (None, None) => {
+                if self.crop_full_size.is_some() {
+                    return Err(invalid(
+                        "render.crop_full_size",
+                        "needs crop_pixels or crop_window",
+                    ));
+                }
+                return Ok(None);
+            }
+        };
+        Ok(Some(Crop {
+            window,
+            full_size: self.crop_full_size.unwrap_or(false),
+        }))
+    }
+
+    /// Adaptive sampling is on as soon as any of its settings is given
+    fn adaptive(&self) -> Result<Option<AdaptiveSampling>, SceneError> {
+        if self.adaptive_threshold.is_none()
+            && self.adaptive_min_samples.is_none()
+            && self.adaptive_max_samples.is_none()
+        {
+            return Ok(None);
+        }
+        let defaults = AdaptiveSampling::default();
+
+        let threshold = self.adaptive_threshold.unwrap_or(defaults.threshold);
+        if !threshold.is_finite() || threshold <= 0.0 {
+            return Err(invalid("render.adaptive_threshold", "must be positive"));
+        }
+
+        Ok(Some(AdaptiveSampling {
+            threshold,
+            min_samples: positive(
+                "adaptive_min_samples",
+                self.adaptive_min_samples.unwrap_or(defaults.min_samples),
+            )?,
+            max_samples: self
+                .adaptive_max_samples
+                .map(|max_samples| positive("adaptive_max_samples", max_samples))
+                .transpose()?,
+        }))
+    }
+
+    fn integrator(&self) -> Result<IntegratorSettings, SceneError> {
+        let defaults = IntegratorSettings::default();
+
+        let kind = match &self.integrator {
+            Some(name) => name
+                .parse()
+                .map_err(|message| invalid("render.integrator", message))?,
+            None => defaults.kind,
+        };
+        let occlusion_distance = self
+            .occlusion_distance
+            .unwrap_or(defaults.occlusion_distance);
+        if occlusion_distance.is_nan() || occlusion_distance <= 0.0 {
+            return Err(invalid("render.occlusion_distance", "must be positive"));
+        }
+
+        Ok(IntegratorSettings {
+            kind,
+            max_depth: positive("max_depth", self.max_depth.unwrap_or(defaults.max_depth))?,
+            roulette_depth: positive(
+                "roulette_depth",
+                self.roulette_depth.unwrap_or(defaults.roulette_depth),
+            )?,
+            occlusion_samples: positive(
+                "occlusion_samples",
+                self.occlusion_samples.unwrap_or(defaults.occlusion_samples),
+            )?,
+            occlusion_distance,
+        })
+    }
+
+    fn display(&self) -> Result<DisplayTransform, SceneError> {
+        let defaults = DisplayTransform::default();
+
+        let exposure = self.exposure.unwrap_or(defaults.exposure);
+        if !exposure.is_finite() {
+            return Err(invalid(
+                "render.exposure",
+                "must be a finite number of stops",
+            ));
+        }
+        let tone_map = match &self.tone_map {
+            Some(name) => name
+                .parse()
+                .map_err(|message| invalid("render.tone_map", message))?,
+            None => defaults.tone_map,
+        };
+        let white_point = self.white_point.unwrap_or(defaults.white_point);
+        if white_point.is_nan() || white_point <= 0.0 {
+            return Err(invalid("render.white_point", "must be positive"));
+        }
+
+        Ok(DisplayTransform {
+            exposure,
+            tone_map,
+            white_point,
+        })
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraSettings {
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    vertical_fov: Option<f64>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    background: Option<BackgroundEntry>,
+}
+
+impl CameraSettings {
+    fn build(
+        &self,
+        builder: &CameraBuilder,
+        directory: &Path,
+    ) -> Result<CameraBuilder, SceneError> {
+        let lookfrom = self.look_from.map_or(builder.lookfrom, point);
+        let lookat = self.look_at.map_or(builder.lookat, point);
+        let view = lookat - lookfrom;
+        if view.cross(&Vec3::new(0.0, 1.0, 0.0)).near_zero() {
+            return Err(invalid(
+                "camera",
+                "look_at must differ from look_from and not be straight above or below it",
+            ));
+        }
+
+        let vertical_fov = self.vertical_fov.unwrap_or(builder.vertical_fov);
+        if !(vertical_fov > 0.0 && vertical_fov < 180.0) {
+            return Err(invalid(
+                "camera.vertical_fov",
+                "must be between 0 and 180 degrees",
+            ));
+        }
+        let defocus_angle = self.defocus_angle.unwrap_or(builder.defocus_angle);
+        if !(0.0..180.0).contains(&defocus_angle) {
+            return Err(invalid(
+                "camera.defocus_angle",
+                "must be between 0 and 180 degrees",
+            ));
+        }
+        let focus_dist = self.focus_dist.unwrap_or(builder.focus_dist);
+        if focus_dist.is_nan() || focus_dist <= 0.0 {
+            return Err(invalid("camera.focus_dist", "must be positive"));
+        }
+
+        let background = match &self.background {
+            Some(entry) => entry
+                .build(directory)
+                .map_err(|message| invalid("camera.background", message))?,
+            None => Arc::clone(&builder.background),
+        };
+
+        Ok(CameraBuilder {
+            vertical_fov,
+            lookfrom,
+            lookat,
+            defocus_angle,
+            focus_dist,
+            background,
+            ..builder.clone()
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundEntry {
+    Solid { color: [f64; 3] },
+    Gradient { bottom: [f64; 3], top: [f64; 3] },
+    Environment { file: PathBuf },
+}
+
+impl BackgroundEntry {
+    fn build(&self, directory: &Path) -> Result<Arc<dyn Background + Send + Sync>, String> {
+        Ok(match self {
+            Self::Solid { color } => Arc::new(SolidBackground::new(radiance(*color)?)),
+            Self::Gradient { bottom, top } => {
+                Arc::new(GradientBackground::new(radiance(*bottom)?, radiance(*top)?))
+            }
+            Self::Environment { file } => Arc::new(
+                EnvironmentMap::from_file(&directory.join(file))
+                    .map_err(|error| format!("can't load {}: {error}", file.display()))?,
+            ),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialEntry {
+    Lambertian {
+        albedo: [f64; 3],
+    },
+    Metal {
+        albedo: [f64; 3],
+    },
+    Dielectric {
+        refraction_index: f64,
+    },
+    DiffuseLight {
+        color: [f64; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+const fn default_intensity() -> f64 {
+    1.0
+}
+
+impl MaterialEntry {
+    fn build(&self) -> Result<Arc<dyn Scatterer + Send + Sync>, String> {
+        Ok(match self {
+            Self::Lambertian { albedo } => Arc::new(LambertianMaterial::new(reflectance(*albedo)?)),
+            Self::Metal { albedo } => Arc::new(MetalMaterial::new(reflectance(*albedo)?)),
+            Self::Dielectric { refraction_index } => {
+                if refraction_index.is_nan() || *refraction_index <= 0.0 {
+                    return Err("refraction_index must be positive".to_string());
+                }
+                Arc::new(DielectricMaterial::new(*refraction_index))
+            }
+            Self::DiffuseLight { color, intensity } => {
+                if intensity.is_nan() || *intensity < 0.0 {
+                    return Err("intensity can't be negative".to_string());
+                }
+                Arc::new(DiffuseLightMaterial::new(reflectance(*color)?, *intensity))
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeEntry {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+        /// Distance moved over the shutter interval, for motion blur
+        velocity: Option<[f64; 3]>,
+    },
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+    },
+    Mesh {
+        file: PathBuf,
+        material: Option<String>,
+    },
+}
+
+/// A shape built from an entry, sampled as a light when it gives off light
+struct Shape {
+    object: Box<dyn Light + Send + Sync>,
+    emissive: bool,
+}
+
+impl Shape {
+    fn new(object: impl Light + Send + Sync + 'static, material: &dyn Scatterer) -> Self {
+        Self {
+            object: Box::new(object),
+            emissive: material.is_emissive(),
+        }
+    }
+}
+
+impl ShapeEntry {
+    /// Meshes become one shape for every part of the model
+    fn build(
+        &self,
+        materials: &HashMap<&str, Arc<dyn Scatterer + Send + Sync>>,
+        directory: &Path,
+    ) -> Result<Vec<Shape>, String> {
+        let material = |name: &str| {
+            materials
+                .get(name)
+                .map(Arc::clone)
+                .ok_or_else(|| format!("unknown material {name}"))
+        };
+
+        Ok(match self {
+            Self::Sphere {
+                center,
+                radius,
+                material: name,
+                velocity,
+            } => {
+                if radius.is_nan() || *radius <= 0.0 {
+                    return Err("radius must be positive".to_string());
+                }
+                let material = material(name)?;
+                let sphere = Sphere::new_with_velocity(
+                    point(*center),
+                    *radius,
+                    Arc::clone(&material),
+                    velocity.map_or_else(Vec3::zero, vector),
+                );
+                vec![Shape::new(sphere, material.as_ref())]
+            }
+            Self::Quad {
+                corner,
+                u,
+                v,
+                material: name,
+            } => {
+                if vector(*u).cross(&vector(*v)).near_zero() {
+                    return Err("u and v must not be parallel".to_string());
+                }
+                let material = material(name)?;
+                let quad = Quad::new(
+                    point(*corner),
+                    vector(*u),
+                    vector(*v),
+                    Arc::clone(&material),
+                );
+                vec![Shape::new(quad, material.as_ref())]
+            }
+            Self::Triangle {
+                vertices,
+                material: name,
+            } => {
+                let [p0, p1, p2] = vertices.map(point);
+                if (p1 - p0).cross(&(p2 - p0)).near_zero() {
+                    return Err("vertices must not be in a line".to_string());
+                }
+                let material = material(name)?;
+                let triangle = Triangle::new(p0, p1, p2, Arc::clone(&material));
+                vec![Shape::new(triangle, material.as_ref())]
+            }
+            Self::Mesh {
+                file,
+                material: name,
+            } => {
+                // faces without a material of their own need something to fall back on
+                let default_material = match name {
+                    Some(name) => material(name)?,
+                    None => Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap())),
+                };
+                load_obj(&directory.join(file), &default_material)
+                    .map_err(|error| format!("can't load {}: {error}", file.display()))?
+                    .into_iter()
+                    .map(|mesh| {
+                        let material = Arc::clone(mesh.material());
+                        Shape::new(mesh, material.as_ref())
+                    })
+                    .collect()
+            }
+        })
+    }
+}
+
+fn invalid(entry: impl Into<String>, message: impl Into<String>) -> SceneError {
+    SceneError::Invalid {
+        entry: entry.into(),
+        message: message.into(),
+    }
+}
+
+/// Counts in `[render]`, which need to be at least 1
+fn positive(name: &str, value: isize) -> Result<isize, SceneError> {
+    if value > 0 {
+        Ok(value)
+    } else {
+        Err(invalid(format!("render.{name}"), "must be at least 1"))
+    }
+}
+
+const fn point([x, y, z]: [f64; 3]) -> Point<f64> {
+    Point::new(x, y, z)
+}
+
+const fn vector([x, y, z]: [f64; 3]) -> Vec3<f64> {
+    Vec3::new(x, y, z)
+}
+
+/// A surface color, which can't reflect more light than it receives
+fn reflectance([red, green, blue]: [f64; 3]) -> Result<Color, String> {
+    Color::new(red, green, blue).map_err(|_| "color channels must be between 0 and 1".to_string())
+}
+
+/// Light arriving from somewhere, which can be as bright as it likes
+fn radiance([red, green, blue]: [f64; 3]) -> Result<Color, String> {
+    if [red, green, blue]
+        .iter()
+        .any(|channel| channel.is_nan() || *channel < 0.0)
+    {
+        return Err("color channels can't be negative".to_string());
+    }
+    Ok(Color { red, green, blue })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        image_utils::tone_map::ToneMap,
+        math_utils::samplers::SamplerKind,
+        ray_tracing::{
+            camera::{filter::FilterKind, tiles::TileOrder},
+            integrators::IntegratorKind,
+        },
+    };
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(source, Path::new(""))
+    }
+
+    fn invalid_entry(source: &str) -> String {
+        match parse(source) {
+            Err(SceneError::Invalid { entry, .. }) => entry,
+            Err(error) => panic!("expected an invalid entry but got {error}"),
+            Ok(_) => panic!("expected an invalid entry but the scene loaded"),
+        }
+    }
+
+    #[test]
+    fn test_empty_scene_uses_defaults() {
+        let scene = parse("").unwrap();
+        let defaults = CameraBuilder::default();
+        assert!(scene.world.is_empty());
+        assert_eq!(scene.camera.image_width, defaults.image_width);
+        assert_eq!(scene.camera.lookat, defaults.lookat);
+        assert_eq!(scene.output, PathBuf::from("image.ppm"));
+        assert_eq!(scene.display, DisplayTransform::default());
+        assert_eq!(scene.denoiser, None);
+        assert_eq!(scene.camera.adaptive, None);
+        assert_eq!(scene.camera.pass_samples, None);
+        assert_eq!(scene.camera.filter, Filter::default());
+        assert_eq!(scene.camera.crop, None);
+        assert_eq!(scene.snapshot_interval, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_full_scene() {
+        let scene = parse(
+            r#"
+[render]
+image_width = 64
+samples_per_pixel = 4
+sampler = "halton"
+filter = "mitchell"
+filter_radius = 1.5
+tile_size = 8
+tile_order = "spiral"
+crop_pixels = [8, 4, 16, 10]
+crop_full_size = true
+adaptive_max_samples = 32
+heatmap = "samples.png"
+pass_samples = 2
+snapshot_interval = 1.5
+integrator = "ambient-occlusion"
+roulette_depth = 3
+occlusion_distance = 2.5
+output = "out.ppm"
+exposure = -1
+tone_map = "extended-reinhard"
+white_point = 8
+denoise = true
+
+[aovs]
+normal = "normal.pfm"
+object-id = "out/id.pfm"
+
+[camera]
+look_from = [0, 1, 3]
+focus_dist = 3
+background = { type = "solid", color = [2, 2, 2] }
+
+[materials]
+matte = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+lamp = { type = "diffuse_light", color = [1, 1, 1], intensity = 4 }
+
+[[shapes]]
+type = "sphere"
+center = [0, 0, -1]
+radius = 0.5
+material = "matte"
+
+[[shapes]]
+type = "quad"
+corner = [-1, 2, -2]
+u = [2, 0, 0]
+v = [0, 0, 2]
+material = "lamp"
+
+[[shapes]]
+type = "triangle"
+vertices = [[0, 0, 0], [1, 0, 0], [0, 1, 0]]
+material = "matte"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.world.len(), 3);
+        // only the quad is made of light
+        assert_eq!(scene.lights.len(), 1);
+        assert_eq!(scene.camera.image_width, 64);
+        assert_eq!(scene.camera.samples_per_pixel, 4);
+        assert_eq!(scene.camera.sampler, SamplerKind::Halton);
+        assert_eq!(
+            scene.camera.filter,
+            Filter {
+                kind: FilterKind::Mitchell,
+                radius: 1.5
+            }
+        );
+        assert_eq!(
+            scene.camera.tiling,
+            Tiling {
+                size: 8,
+                order: TileOrder::Spiral
+            }
+        );
+        assert_eq!(
+            scene.camera.crop,
+            Some(Crop {
+                window: CropWindow::Pixels {
+                    x: 8,
+                    y: 4,
+                    width: 16,
+                    height: 10
+                },
+                full_size: true
+            })
+        );
+        assert_eq!(
+            scene.camera.adaptive,
+            Some(AdaptiveSampling {
+                max_samples: Some(32),
+                ..AdaptiveSampling::default()
+            })
+        );
+        assert_eq!(scene.heatmap, Some(PathBuf::from("samples.png")));
+        assert_eq!(scene.camera.pass_samples, Some(2));
+        assert_eq!(scene.snapshot_interval, Duration::from_millis(1500));
+        assert_eq!(scene.integrator.kind, IntegratorKind::AmbientOcclusion);
+        assert_eq!(scene.integrator.roulette_depth, 3);
+        assert!((scene.integrator.occlusion_distance - 2.5).abs() < 1e-12);
+        assert_eq!(scene.denoiser, Some(Denoiser::default()));
+        assert_eq!(
+            scene.aovs,
+            BTreeMap::from([
+                (Aov::Normal, PathBuf::from("normal.pfm")),
+                (Aov::ObjectId, PathBuf::from("out/id.pfm")),
+            ])
+        );
+        assert_eq!(scene.camera.lookfrom, Point::new(0.0, 1.0, 3.0));
+        assert_eq!(scene.output, PathBuf::from("out.ppm"));
+        assert_eq!(
+            scene.display,
+            DisplayTransform {
+                exposure: -1.0,
+                tone_map: ToneMap::ExtendedReinhard,
+                white_point: 8.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for source in [
+            "[render\n",
+            "[render]\nimage_width = \"wide\"\n",
+            "[camera]\nzoom = 2\n",
+            "[[shapes]]\ntype = \"cube\"\nmaterial = \"a\"\n",
+            "[[shapes]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nmaterial = \"a\"\n",
+        ] {
+            assert!(
+                matches!(parse(source), Err(SceneError::Syntax(_))),
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors_name_the_entry() {
+        let materials =
+            "[materials]\nmatte = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\n";
+        let unknown_material = format!(
+            "{materials}\n[[shapes]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"matte\"\n\n[[shapes]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"shiny\"\n"
+        );
+        assert_eq!(invalid_entry(&unknown_material), "shapes[1] (line 10)");
+
+        let too_bright = "[materials]\nmatte = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\nwhite = { type = \"metal\", albedo = [1.5, 1, 1] }\n";
+        assert_eq!(invalid_entry(too_bright), "materials.white (line 3)");
+
+        assert_eq!(
+            invalid_entry("[render]\nsamples_per_pixel = 0\n"),
+            "render.samples_per_pixel"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nadaptive_threshold = -0.1\n"),
+            "render.adaptive_threshold"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nadaptive_max_samples = 0\n"),
+            "render.adaptive_max_samples"
+        );
+        assert_eq!(
+            invalid_entry("[render]\npass_samples = 0\n"),
+            "render.pass_samples"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nsnapshot_interval = -1\n"),
+            "render.snapshot_interval"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nsampler = \"random\"\n"),
+            "render.sampler"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nfilter = \"sinc\"\n"),
+            "render.filter"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nfilter_radius = 0\n"),
+            "render.filter_radius"
+        );
+        assert_eq!(
+            invalid_entry("[render]\ntile_size = 0\n"),
+            "render.tile_size"
+        );
+        assert_eq!(
+            invalid_entry("[render]\ntile_order = \"rows\"\n"),
+            "render.tile_order"
+        );
+        assert_eq!(
+            invalid_entry("[render]\ncrop_pixels = [0, 0, 0, 4]\n"),
+            "render.crop_pixels"
+        );
+        assert_eq!(
+            invalid_entry("[render]\ncrop_window = [0.5, 0.25, 0, 1]\n"),
+            "render.crop_window"
+        );
+        assert_eq!(
+            invalid_entry("[render]\ncrop_pixels = [0, 0, 4, 4]\ncrop_window = [0, 1, 0, 1]\n"),
+            "render.crop_window"
+        );
+        assert_eq!(
+            invalid_entry("[render]\ncrop_full_size = true\n"),
+            "render.crop_full_size"
+        );
+        assert_eq!(
+            invalid_entry("[render]\ntone_map = \"filmic\"\n"),
+            "render.tone_map"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nintegrator = \"whitted\"\n"),
+            "render.integrator"
+        );
+        assert_eq!(
+            invalid_entry("[render]\nocclusion_distance = 0\n"),
+            "render.occlusion_distance"
+        );
+        assert_eq!(
+            invalid_entry("[aovs]\nmotion = \"motion.pfm\"\n"),
+            "aovs.motion"
+        );
+        assert_eq!(
+            invalid_entry("[aovs]\ndepth = \"depth.png\"\n"),
+            "aovs.depth"
+        );
+        assert_eq!(
+            invalid_entry("[camera]\nlook_from = [0, 0, 0]\nlook_at = [0, 5, 0]\n"),
+            "camera"
+        );
+        assert_eq!(
+            invalid_entry("[camera]\nbackground = { type = \"solid\", color = [-1, 0, 0] }\n"),
+            "camera.background"
+        );
+    }
+
+    #[test]
+    fn test_degenerate_shapes_are_rejected() {
+        let materials = "[materials]\nm = { type = \"dielectric\", refraction_index = 1.5 }\n";
+        for shape in [
+            "type = \"sphere\"\ncenter = [0, 0, 0]\nradius = -1\nmaterial = \"m\"",
+            "type = \"quad\"\ncorner = [0, 0, 0]\nu = [1, 0, 0]\nv = [2, 0, 0]\nmaterial = \"m\"",
+            "type = \"triangle\"\nvertices = [[0, 0, 0], [1, 1, 1], [2, 2, 2]]\nmaterial = \"m\"",
+        ] {
+            let source = format!("{materials}[[shapes]]\n{shape}\n");
+            assert_eq!(invalid_entry(&source), "shapes[0] (line 3)");
+        }
+    }
+
+    #[test]
+    fn test_emissive_mesh_parts_are_lights() {
+        let directory = std::env::temp_dir().join(format!("mesh-lights-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("lamp.mtl"), "newmtl glow\nKe 4 4 4\n").unwrap();
+        std::fs::write(
+            directory.join("lamp.obj"),
+            "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             f 1 2 3\nusemtl glow\nf 1 3 4\n",
+        )
+        .unwrap();
+        let source = "\
+[materials]
+lamp = { type = \"diffuse_light\", color = [1, 1, 1], intensity = 4 }
+
+[[shapes]]
+type = \"mesh\"
+file = \"lamp.obj\"
+
+[[shapes]]
+type = \"mesh\"
+file = \"lamp.obj\"
+material = \"lamp\"
+";
+        let scene = parse_scene(source, &directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+        let scene = scene.unwrap();
+
+        // one part of each model is made of the default material, only the second
+        // model's default gives off light
+        assert_eq!(scene.world.len(), 4);
+        assert_eq!(scene.lights.len(), 3);
+    }
+}
@@ -0,0 +1,4 @@
+//! Loading what gets rendered from files
+
+pub mod file;
+pub mod obj;
@@ -0,0 +1,702 @@
+//! Wavefront `.obj` models and their `.mtl` material libraries
+//!
+//! Supports positions, texture coordinates and normals, polygonal faces (split into
+//! triangle fans), negative indices counting back from the latest entry, and `g`/`o`
+//! groups. Faces sharing a group and material become one `TriangleMesh`. Statements
+//! the renderer has no use for, like smoothing groups or lines, are skipped.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    math_utils::{point::Point, vector::Vec3},
+    ray_tracing::{
+        color::Color,
+        materials::{
+            dielectric::DielectricMaterial, diffuse_light::DiffuseLightMaterial,
+            lambertian::LambertianMaterial, material::Scatterer, metal::MetalMaterial,
+        },
+        shapes::triangle_mesh::{MeshError, TriangleMesh},
+    },
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    /// A statement that couldn't be understood, with the 1-based line it's on
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    Mesh(MeshError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+            Self::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{file}:{line}: {message}"),
+            Self::Mesh(error) => write!(f, "invalid mesh: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Parse { .. } => None,
+            Self::Mesh(error) => Some(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for ObjError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<MeshError> for ObjError {
+    fn from(error: MeshError) -> Self {
+        Self::Mesh(error)
+    }
+}
+
+/// Load a model as one mesh for every group and material, with material libraries
+/// looked up next to it
+///
+/// Faces before any `usemtl` get `default_material`.
+///
+/// # Errors
+/// If a file can't be read, a statement is malformed, or a face refers to a vertex
+/// or material that doesn't exist
+pub fn load_obj(
+    path: &Path,
+    default_material: &Arc<dyn Scatterer + Send + Sync>,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let file = path.display().to_string();
+    let model = parse_obj(BufReader::new(File::open(path)?), &file)?;
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut materials = HashMap::new();
+    for (line, name) in &model.libraries {
+        let library_path = directory.join(name);
+        let library = File::open(&library_path).map_err(|error| ObjError::Parse {
+            file: file.clone(),
+            line: *line,
+            message: format!(
+                "can't open material library {}: {error}",
+                library_path.display()
+            ),
+        })?;
+        let library_file = library_path.display().to_string();
+        for (name, material) in parse_mtl(BufReader::new(library), &library_file)? {
+            materials.insert(name, material.to_scatterer());
+        }
+    }
+
+    model.into_meshes(&file, &materials, default_material)
+}
+
+/// One corner of a face, as 0-based indices into the model's attribute lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Faces sharing a group and material, which become one mesh
+struct Part {
+    /// Where the material was first used, for reporting unknown names
+    material: Option<(usize, String)>,
+    triangles: Vec<[Corner; 3]>,
+}
+
+#[derive(Default)]
+struct ObjModel {
+    positions: Vec<Point<f64>>,
+    normals: Vec<Vec3<f64>>,
+    uvs: Vec<(f64, f64)>,
+    /// `mtllib` names with the line they're on
+    libraries: Vec<(usize, String)>,
+    parts: Vec<Part>,
+}
+
+fn parse_obj<R: BufRead>(reader: R, file: &str) -> Result<ObjModel, ObjError> {
+    let mut model = ObjModel::default();
+    let mut part_index: HashMap<(String, Option<String>), usize> = HashMap::new();
+    let mut group = String::new();
+    let mut material: Option<(usize, String)> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line?;
+        let error = |message: String| ObjError::Parse {
+            file: file.to_string(),
+            line: line_number,
+            message,
+        };
+
+        let statement = line.split('#').next().unwrap_or_default();
+        let mut fields = statement.split_whitespace();
+        let Some(keyword) = fields.next() else {
+            continue;
+        };
+        let arguments: Vec<&str> = fields.collect();
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(&arguments).map_err(error)?;
+                model.positions.push(Point::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(&arguments).map_err(error)?;
+                model.normals.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                let [u] = parse_floats(&arguments).map_err(error)?;
+                let v = match arguments.get(1) {
+                    Some(field) => parse_float(field).map_err(error)?,
+                    None => 0.0,
+                };
+                model.uvs.push((u, v));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices but has {}",
+                        arguments.len()
+                    )));
+                }
+                let corners = arguments
+                    .iter()
+                    .map(|field| model.parse_corner(field))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                let key = (
+                    group.clone(),
+                    material.as_ref().map(|(_, name)| name.clone()),
+                );
+                let part = *part_index.entry(key).or_insert_with(|| {
+                    model.parts.push(Part {
+                        material: material.clone(),
+                        triangles: Vec::new(),
+                    });
+                    model.parts.len() - 1
+                });
+                let triangles = &mut model.parts[part].triangles;
+                for pair in corners[1..].windows(2) {
+                    triangles.push([corners[0], pair[0], pair[1]]);
+                }
+            }
+            "g" | "o" => group = arguments.join(" "),
+            "usemtl" => {
+                if arguments.is_empty() {
+                    return Err(error("usemtl needs a material name".to_string()));
+                }
+                material = Some((line_number, arguments.join(" ")));
+            }
+            "mtllib" => {
+                if arguments.is_empty() {
+                    return Err(error("mtllib needs a file name".to_string()));
+                }
+                model.libraries.extend(
+                    arguments
+                        .iter()
+                        .map(|name| (line_number, (*name).to_string())),
+                );
+            }
+            // smoothing groups, lines, points and free-form geometry aren't rendered
+            _ => {}
+        }
+    }
+
+    Ok(model)
+}
+
+impl ObjModel {
+    /// Parse `position`, `position/uv`, `position//normal` or `position/uv/normal`
+    fn parse_corner(&self, field: &str) -> Result<Corner, String> {
+        let indices: Vec<&str> = field.split('/').collect();
+        if indices.len() > 3 {
+            return Err(format!("bad face vertex {field}"));
+        }
+
+        let optional_index = |slot: usize, count: usize, kind: &str| match indices.get(slot) {
+            Some(index) if !index.is_empty() => resolve_index(index, count, kind).map(Some),
+            _ => Ok(None),
+        };
+
+        Ok(Corner {
+            position: resolve_index(indices[0], self.positions.len(), "vertex")?,
+            uv: optional_index(1, self.uvs.len(), "texture coordinate")?,
+            normal: optional_index(2, self.normals.len(), "normal")?,
+        })
+    }
+
+    fn into_meshes(
+        self,
+        file: &str,
+        materials: &HashMap<String, Arc<dyn Scatterer + Send + Sync>>,
+        default_material: &Arc<dyn Scatterer + Send + Sync>,
+    ) -> Result<Vec<TriangleMesh>, ObjError> {
+        let mut meshes = Vec::new();
+        for part in &self.parts {
+            let material = match &part.material {
+                None => Arc::clone(default_material),
+                Some((line, name)) => {
+                    Arc::clone(materials.get(name).ok_or_else(|| ObjError::Parse {
+                        file: file.to_string(),
+                        line: *line,
+                        message: format!("unknown material {name}"),
+                    })?)
+                }
+            };
+            meshes.push(self.build_mesh(part, material)?);
+        }
+        Ok(meshes)
+    }
+
+    /// Give every distinct corner its own mesh vertex, since a position can be
+    /// shared by corners with different normals or texture coordinates
+    fn build_mesh(
+        &self,
+        part: &Part,
+        material: Arc<dyn Scatterer + Send + Sync>,
+    ) -> Result<TriangleMesh, MeshError> {
+        let mut vertex_of_corner: HashMap<Corner, usize> = HashMap::new();
+        let mut corners = Vec::new();
+        let triangles: Vec<[usize; 3]> = part
+            .triangles
+            .iter()
+            .map(|triangle| {
+                triangle.map(|corner| {
+                    *vertex_of_corner.entry(corner).or_insert_with(|| {
+                        corners.push(corner);
+                        corners.len() - 1
+                    })
+                })
+            })
+            .collect();
+
+        let vertices = corners
+            .iter()
+            .map(|corner| self.positions[corner.position])
+            .collect();
+        // attributes only count when every corner of the mesh has them
+        let normals = corners
+            .iter()
+            .map(|corner| corner.normal.map(|index| self.normals[index]))
+            .collect();
+        let uvs = corners
+            .iter()
+            .map(|corner| corner.uv.map(|index| self.uvs[index]))
+            .collect();
+
+        TriangleMesh::new(vertices, &triangles, normals, uvs, material)
+    }
+}
+
+/// Turn a 1-based index, or a negative one counting back from the end, into a 0-based one
+fn resolve_index(field: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index: isize = field
+        .parse()
+        .map_err(|_| format!("bad {kind} index {field}"))?;
+    let resolved = match index {
+        1.. => index.unsigned_abs() - 1,
+        ..0 => count
+            .checked_sub(index.unsigned_abs())
+            .ok_or_else(|| format!("{kind} index {index} is before the first {kind}"))?,
+        0 => return Err(format!("{kind} indices start at 1")),
+    };
+    if resolved >= count {
+        return Err(format!("{kind} {index} hasn't been defined"));
+    }
+    Ok(resolved)
+}
+
+fn parse_float(field: &str) -> Result<f64, String> {
+    field.parse().map_err(|_| format!("bad number {field}"))
+}
+
+/// Parse the first `N` arguments, anything after them is ignored
+fn parse_floats<const N: usize>(arguments: &[&str]) -> Result<[f64; N], String> {
+    if arguments.len() < N {
+        return Err(format!(
+            "expected {N} numbers but found {}",
+            arguments.len()
+        ));
+    }
+    let mut values = [0.0; N];
+    for (value, field) in values.iter_mut().zip(arguments) {
+        *value = parse_float(field)?;
+    }
+    Ok(values)
+}
+
+/// The `.mtl` properties that map onto the renderer's materials
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MtlMaterial {
+    /// `Kd`
+    diffuse: Color,
+    /// `Ks`
+    specular: Color,
+    /// `Ke`, which may be brighter than 1
+    emission: Color,
+    /// `Ni`
+    refraction_index: Option<f64>,
+    /// `d`, or 1 - `Tr`
+    dissolve: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MaterialKind {
+    Light,
+    Glass,
+    Metal,
+    Diffuse,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8).unwrap(),
+            specular: Color::black(),
+            emission: Color::black(),
+            refraction_index: None,
+            dissolve: 1.0,
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// Emission wins, then transparency, then whichever of the specular and diffuse
+    /// colors is brighter
+    fn kind(&self) -> MaterialKind {
+        let brightness = |color: Color| color.red + color.green + color.blue;
+        if brightness(self.emission) > 0.0 {
+            MaterialKind::Light
+        } else if self.dissolve < 1.0 {
+            MaterialKind::Glass
+        } else if brightness(self.specular) > brightness(self.diffuse) {
+            MaterialKind::Metal
+        } else {
+            MaterialKind::Diffuse
+        }
+    }
+
+    fn to_scatterer(self) -> Arc<dyn Scatterer + Send + Sync> {
+        // glass without an index of refraction would be invisible, assume ordinary glass
+        const DEFAULT_REFRACTION_INDEX: f64 = 1.5;
+
+        match self.kind() {
+            MaterialKind::Light => {
+                let emission = self.emission;
+                let intensity = emission.red.max(emission.green).max(emission.blue);
+                Arc::new(DiffuseLightMaterial::new(
+                    (1.0 / intensity) * emission,
+                    intensity,
+                ))
+            }
+            MaterialKind::Glass => Arc::new(DielectricMaterial::new(
+                self.refraction_index.unwrap_or(DEFAULT_REFRACTION_INDEX),
+            )),
+            MaterialKind::Metal => Arc::new(MetalMaterial::new(self.specular)),
+            MaterialKind::Diffuse => Arc::new(LambertianMaterial::new(self.diffuse)),
+        }
+    }
+}
+
+fn parse_mtl<R: BufRead>(reader: R, file: &str) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line?;
+        let error = |message: String| ObjError::Parse {
+            file: file.to_string(),
+            line: line_number,
+            message,
+        };
+
+        let statement = line.split('#').next().unwrap_or_default();
+        let mut fields = statement.split_whitespace();
+        let Some(keyword) = fields.next() else {
+            continue;
+        };
+        let arguments: Vec<&str> = fields.collect();
+
+        if keyword == "newmtl" {
+            if arguments.is_empty() {
+                return Err(error("newmtl needs a material name".to_string()));
+            }
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((arguments.join(" "), MtlMaterial::default()));
+            continue;
+        }
+
+        let Some((_, material)) = current.as_mut() else {
+            return Err(error(format!("{keyword} before any newmtl")));
+        };
+        match keyword {
+            "Kd" => material.diffuse = parse_reflectance(&arguments).map_err(error)?,
+            "Ks" => material.specular = parse_reflectance(&arguments).map_err(error)?,
+            "Ke" => material.emission = parse_emission(&arguments).map_err(error)?,
+            "Ni" => {
+                let [index] = parse_floats(&arguments).map_err(error)?;
+                material.refraction_index = Some(index);
+            }
+            "d" | "Tr" => {
+                let [value] = parse_floats(&arguments).map_err(error)?;
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(error(format!("{keyword} must be between 0 and 1")));
+                }
+                material.dissolve = if keyword == "d" { value } else { 1.0 - value };
+            }
+            // shininess, illumination models, texture maps and the like aren't supported
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+/// Parse `r g b`, or a single value used for all three
+fn parse_color(arguments: &[&str]) -> Result<Color, String> {
+    if let Some(first) = arguments.first()
+        && first.parse::<f64>().is_err()
+    {
+        return Err(format!("unsupported color format {first}"));
+    }
+    let [red, green, blue] = if arguments.len() < 3 {
+        let [value] = parse_floats(arguments)?;
+        [value; 3]
+    } else {
+        parse_floats(arguments)?
+    };
+    Ok(Color { red, green, blue })
+}
+
+fn parse_reflectance(arguments: &[&str]) -> Result<Color, String> {
+    let color = parse_color(arguments)?;
+    Color::new(color.red, color.green, color.blue)
+        .map_err(|_| "color channels must be between 0 and 1".to_string())
+}
+
+fn parse_emission(arguments: &[&str]) -> Result<Color, String> {
+    let color = parse_color(arguments)?;
+    if color.red < 0.0 || color.green < 0.0 || color.blue < 0.0 {
+        return Err("emission can't be negative".to_string());
+    }
+    Ok(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math_utils::{interval::Interval, ray::Ray},
+        ray_tracing::shapes::hittable::{HitRecord, Hittable},
+    };
+
+    fn default_material() -> Arc<dyn Scatterer + Send + Sync> {
+        Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5).unwrap()))
+    }
+
+    fn parse_error_line<T>(result: &Result<T, ObjError>) -> Option<usize> {
+        match result {
+            Err(ObjError::Parse { line, .. }) => Some(*line),
+            _ => None,
+        }
+    }
+
+    const SQUARE: &str = "\
+# unit square in the z = -1 plane, facing +z
+mtllib square.mtl
+v 0 0 -1
+v 1 0 -1
+v 1 1 -1
+v 0 1 -1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+g left
+f 1/1/1 2/2/1 3/3/1 4/4/1
+g right
+usemtl shiny
+f -4//-1 -3//-1 -2//-1
+";
+
+    #[test]
+    fn test_parse_obj() {
+        let model = parse_obj(SQUARE.as_bytes(), "square.obj").unwrap();
+        assert_eq!(model.positions.len(), 4);
+        assert_eq!(model.uvs.len(), 4);
+        assert_eq!(model.libraries, vec![(2, "square.mtl".to_string())]);
+        assert_eq!(model.parts.len(), 2);
+
+        // the quad is split into a fan around its first corner
+        let fan = &model.parts[0].triangles;
+        assert_eq!(fan.len(), 2);
+        assert_eq!(fan[1].map(|corner| corner.position), [0, 2, 3]);
+        assert_eq!(fan[1][2].uv, Some(3));
+
+        let negative = &model.parts[1];
+        assert_eq!(negative.material, Some((15, "shiny".to_string())));
+        assert_eq!(
+            negative.triangles[0],
+            [0, 1, 2].map(|position| Corner {
+                position,
+                uv: None,
+                normal: Some(0),
+            })
+        );
+    }
+
+    #[test]
+    fn test_same_group_and_material_share_a_part() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\ng a\nf 1 2 3\ng b\nf 1 2 3\ng a\nf 3 2 1\n";
+        let model = parse_obj(obj.as_bytes(), "groups.obj").unwrap();
+        assert_eq!(model.parts.len(), 2);
+        assert_eq!(model.parts[0].triangles.len(), 2);
+    }
+
+    #[test]
+    fn test_obj_errors_have_line_numbers() {
+        let cases = [
+            ("v 0 0\n", 1),
+            ("v 0 0 0\nv 1 0 0\n\nf 1 2\n", 4),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n", 4),
+            ("v 0 0 0\nf -2 1 1\n", 2),
+            ("v 0 0 0\nf 0 1 1\n", 2),
+            ("v 0 0 0\nf 1/1 1 1\n", 2),
+            ("v 0 0 0\nf 1/x 1 1\n", 2),
+            ("v a b c\n", 1),
+            ("# comment\nusemtl\n", 2),
+        ];
+        for (obj, line) in cases {
+            assert_eq!(
+                parse_error_line(&parse_obj(obj.as_bytes(), "bad.obj")),
+                Some(line),
+                "{obj:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_meshes() {
+        let model = parse_obj(SQUARE.as_bytes(), "square.obj").unwrap();
+        let mut materials = HashMap::new();
+        materials.insert("shiny".to_string(), default_material());
+        let meshes = model
+            .into_meshes("square.obj", &materials, &default_material())
+            .unwrap();
+        assert_eq!(meshes.len(), 2);
+
+        let ray = Ray::new(Point::new(0.75, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = meshes
+            .iter()
+            .find_map(|mesh| mesh.hit(&ray, Interval::new(0.001, f64::INFINITY)))
+            .unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-12);
+        assert!(hit.front_face);
+        assert!((hit.u - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_unknown_material_is_reported_where_used() {
+        let model = parse_obj(SQUARE.as_bytes(), "square.obj").unwrap();
+        let result = model.into_meshes("square.obj", &HashMap::new(), &default_material());
+        assert_eq!(parse_error_line(&result), Some(15));
+    }
+
+    #[test]
+    fn test_parse_mtl() {
+        let mtl = "\
+newmtl matte
+Kd 0.2 0.4 0.6
+Ks 0.1 0.1 0.1
+newmtl mirror
+Kd 0.1
+Ks 0.9 0.9 0.9
+newmtl glass
+d 0.2
+Ni 1.33
+newmtl lamp
+Ke 4 4 2
+illum 2
+";
+        let materials = parse_mtl(mtl.as_bytes(), "test.mtl").unwrap();
+        assert_eq!(materials.len(), 4);
+        assert_eq!(
+            materials["matte"].diffuse,
+            Color::new(0.2, 0.4, 0.6).unwrap()
+        );
+        assert_eq!(materials["matte"].kind(), MaterialKind::Diffuse);
+        assert_eq!(materials["mirror"].kind(), MaterialKind::Metal);
+        assert_eq!(materials["glass"].kind(), MaterialKind::Glass);
+        assert_eq!(materials["glass"].refraction_index, Some(1.33));
+        assert_eq!(materials["lamp"].kind(), MaterialKind::Light);
+    }
+
+    #[test]
+    fn test_light_keeps_its_brightness() {
+        let mtl = "newmtl lamp\nKe 4 2 0\n";
+        let materials = parse_mtl(mtl.as_bytes(), "test.mtl").unwrap();
+        let light = materials["lamp"].to_scatterer();
+
+        let hit = HitRecord {
+            point: Point::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            front_face: true,
+            u: 0.0,
+            v: 0.0,
+            material: Arc::clone(&light),
+            object_id: 0,
+        };
+        let emitted = light.emitted(&hit);
+        assert!((emitted.red - 4.0).abs() < 1e-12);
+        assert!((emitted.green - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mtl_errors_have_line_numbers() {
+        let cases = [
+            ("Kd 1 1 1\n", 1),
+            ("newmtl a\nKd 2 0 0\n", 2),
+            ("newmtl a\n\nKs spectral file.rfl\n", 3),
+            ("newmtl a\nd 1.5\n", 2),
+            ("newmtl\n", 1),
+        ];
+        for (mtl, line) in cases {
+            assert_eq!(
+                parse_error_line(&parse_mtl(mtl.as_bytes(), "bad.mtl")),
+                Some(line),
+                "{mtl:?}"
+            );
+        }
+    }
+}